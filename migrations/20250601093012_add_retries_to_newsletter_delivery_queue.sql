@@ -0,0 +1,12 @@
+ALTER TABLE newsletter_delivery_queue ADD COLUMN n_retries SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE newsletter_delivery_queue ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
+
+CREATE TABLE newsletter_delivery_dead_letters (
+  newsletter_id uuid NOT NULL
+  REFERENCES newsletters (newsletter_id),
+  subscriber_email TEXT NOT NULL,
+  n_retries SMALLINT NOT NULL,
+  last_error TEXT NOT NULL,
+  failed_at timestamptz NOT NULL,
+  PRIMARY KEY(newsletter_id, subscriber_email)
+);
@@ -0,0 +1,12 @@
+CREATE TABLE newsletter_deliveries (
+  newsletter_id uuid NOT NULL
+  REFERENCES newsletters (newsletter_id),
+  subscriber_email TEXT NOT NULL,
+  n_attempts SMALLINT NOT NULL,
+  outcome TEXT NOT NULL,
+  provider_message_id TEXT NULL,
+  last_error TEXT NULL,
+  first_attempted_at timestamptz NOT NULL,
+  last_attempted_at timestamptz NOT NULL,
+  PRIMARY KEY(newsletter_id, subscriber_email)
+);
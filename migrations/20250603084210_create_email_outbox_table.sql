@@ -0,0 +1,13 @@
+CREATE TABLE email_outbox (
+  email_id uuid NOT NULL,
+  recipient TEXT NOT NULL,
+  subject TEXT NOT NULL,
+  html_content TEXT NOT NULL,
+  text_content TEXT NOT NULL,
+  n_retries SMALLINT NOT NULL DEFAULT 0,
+  execute_after timestamptz NOT NULL DEFAULT now(),
+  last_error TEXT NULL,
+  failed_at timestamptz NULL,
+  created_at timestamptz NOT NULL,
+  PRIMARY KEY(email_id)
+);
@@ -0,0 +1,6 @@
+-- Tokens issued before this migration get a day from now rather than expiring on the spot.
+ALTER TABLE subscription_tokens
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '24 hours',
+    ADD COLUMN consumed_at timestamptz NULL;
+ALTER TABLE subscription_tokens ALTER COLUMN expires_at DROP DEFAULT;
@@ -0,0 +1,8 @@
+-- Issues are 'scheduled' until the worker fires them at `send_at`, then 'published'.
+-- Cancelled issues stay around as 'cancelled' and never get a `published_at`.
+ALTER TABLE newsletters
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'published',
+    ADD COLUMN send_at timestamptz NULL,
+    ALTER COLUMN published_at DROP NOT NULL;
+ALTER TABLE newsletters ALTER COLUMN status DROP DEFAULT;
+CREATE INDEX newsletters_scheduled_idx ON newsletters (send_at) WHERE status = 'scheduled';
@@ -0,0 +1,13 @@
+-- 'published' splits into 'sending' (tasks still queued) and 'sent' (queue drained).
+UPDATE newsletters n SET status = CASE
+    WHEN EXISTS (
+        SELECT 1 FROM newsletter_delivery_queue q WHERE q.newsletter_id = n.newsletter_id
+    ) THEN 'sending'
+    ELSE 'sent'
+END
+WHERE status = 'published';
+ALTER TABLE newsletters
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now(),
+    ADD CONSTRAINT newsletters_status_check
+        CHECK (status IN ('draft', 'scheduled', 'sending', 'sent', 'cancelled'));
@@ -0,0 +1,2 @@
+-- Which of the configured email providers accepted the email.
+ALTER TABLE newsletter_deliveries ADD COLUMN provider TEXT NULL;
@@ -0,0 +1,21 @@
+-- Feedback from the email provider: deliveries, bounces and spam complaints.
+CREATE TABLE email_events (
+    event_id uuid NOT NULL,
+    PRIMARY KEY (event_id),
+    kind TEXT NOT NULL CHECK (kind IN ('delivery', 'hard_bounce', 'soft_bounce', 'complaint')),
+    email TEXT NOT NULL,
+    provider_message_id TEXT NULL,
+    detail TEXT NULL,
+    occurred_at timestamptz NOT NULL,
+    received_at timestamptz NOT NULL
+);
+CREATE INDEX email_events_email_idx ON email_events (email);
+
+-- Addresses we must never mail again, whether or not they are still subscribed. Stored in
+-- lower case.
+CREATE TABLE suppressed_emails (
+    email TEXT NOT NULL,
+    PRIMARY KEY (email),
+    reason TEXT NOT NULL CHECK (reason IN ('bounced', 'complained')),
+    suppressed_at timestamptz NOT NULL
+);
@@ -0,0 +1,13 @@
+-- Open and click tracking is opt-in per issue.
+ALTER TABLE newsletters ADD COLUMN track_engagement BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE newsletter_engagement (
+    event_id uuid NOT NULL,
+    PRIMARY KEY (event_id),
+    newsletter_id uuid NOT NULL REFERENCES newsletters (newsletter_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('open', 'click')),
+    url TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX newsletter_engagement_newsletter_idx ON newsletter_engagement (newsletter_id, kind);
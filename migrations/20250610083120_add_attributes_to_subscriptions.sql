@@ -0,0 +1,2 @@
+-- Free-form fields carried over from imports, keyed by CSV column name.
+ALTER TABLE subscriptions ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,7 @@
+-- Erased subscribers leave an HMAC of their lower-cased address, keyed with the app's secret,
+-- behind instead of the address itself, so we can still refuse to mail them without keeping who
+-- they were.
+ALTER TABLE suppressed_emails DROP CONSTRAINT suppressed_emails_reason_check;
+ALTER TABLE suppressed_emails
+    ADD CONSTRAINT suppressed_emails_reason_check
+    CHECK (reason IN ('bounced', 'complained', 'erased'));
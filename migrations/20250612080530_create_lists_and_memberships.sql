@@ -0,0 +1,36 @@
+-- Lists are addressed by slug, in forms and in the API alike. Everyone subscribed before lists
+-- existed was on 'newsletter'.
+CREATE TABLE lists (
+    slug TEXT NOT NULL,
+    PRIMARY KEY (slug),
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+INSERT INTO lists (slug, name, created_at) VALUES ('newsletter', 'Newsletter', now());
+
+-- Whether a subscriber receives a list is decided here; `subscriptions.status` sums these up for
+-- the address as a whole.
+CREATE TABLE list_memberships (
+    list_slug TEXT NOT NULL REFERENCES lists (slug),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    PRIMARY KEY (list_slug, subscriber_id),
+    status TEXT NOT NULL
+        CHECK (status IN ('pending_confirmation', 'confirmed', 'unsubscribed')),
+    subscribed_at timestamptz NOT NULL
+);
+INSERT INTO list_memberships (list_slug, subscriber_id, status, subscribed_at)
+SELECT 'newsletter', id,
+    CASE
+        WHEN status IN ('pending_confirmation', 'confirmed', 'unsubscribed') THEN status
+        WHEN status IS NULL THEN 'pending_confirmation'
+        -- Bounced and complained addresses stay suppressed whatever their memberships say.
+        ELSE 'confirmed'
+    END,
+    subscribed_at
+FROM subscriptions;
+
+ALTER TABLE newsletters
+    ADD COLUMN list_slug TEXT NOT NULL DEFAULT 'newsletter' REFERENCES lists (slug);
+-- A confirmation link confirms one list.
+ALTER TABLE subscription_tokens
+    ADD COLUMN list_slug TEXT NOT NULL DEFAULT 'newsletter' REFERENCES lists (slug);
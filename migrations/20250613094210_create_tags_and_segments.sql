@@ -0,0 +1,19 @@
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (subscriber_id, tag),
+    tagged_at timestamptz NOT NULL
+);
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+-- `filter` holds a `SegmentFilter`. It is evaluated when an issue is queued, not when the
+-- segment is saved, so later tagging counts.
+CREATE TABLE segments (
+    segment_id uuid NOT NULL,
+    PRIMARY KEY (segment_id),
+    name TEXT NOT NULL UNIQUE,
+    filter JSONB NOT NULL,
+    created_at timestamptz NOT NULL
+);
+
+ALTER TABLE newsletters ADD COLUMN segment_id uuid REFERENCES segments (segment_id);
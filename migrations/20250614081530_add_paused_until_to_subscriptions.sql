@@ -0,0 +1,3 @@
+-- Set from the preference center. Paused subscribers stay confirmed, but no issue is queued for
+-- them until the date has passed.
+ALTER TABLE subscriptions ADD COLUMN paused_until timestamptz;
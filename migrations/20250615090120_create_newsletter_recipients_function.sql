@@ -0,0 +1,37 @@
+-- Who an issue sent to a list and narrowed by a segment filter goes to. Both queueing an issue
+-- and previewing a segment read from here, so the preview counts exactly who would be sent to.
+-- The filter arguments mirror `SegmentFilter`; empty arrays and NULLs leave a condition out.
+CREATE FUNCTION newsletter_recipients(
+    list_slug TEXT,
+    tags TEXT[],
+    any_tags TEXT[],
+    exclude_tags TEXT[],
+    subscribed_within_days INT,
+    subscribed_after timestamptz,
+    subscribed_before timestamptz
+) RETURNS TABLE (subscriber_id uuid, email TEXT)
+LANGUAGE sql STABLE AS $$
+    SELECT s.id, s.email
+    FROM subscriptions s
+    JOIN list_memberships m
+        ON m.subscriber_id = s.id AND m.list_slug = $1 AND m.status = 'confirmed'
+    WHERE s.status = 'confirmed'
+        AND NOT EXISTS (SELECT 1 FROM suppressed_emails WHERE email = lower(s.email))
+        AND (s.paused_until IS NULL OR s.paused_until <= now())
+        AND NOT EXISTS (
+            SELECT 1 FROM UNNEST($2) AS wanted (tag)
+            WHERE NOT EXISTS (
+                SELECT 1 FROM subscriber_tags t
+                WHERE t.subscriber_id = s.id AND t.tag = wanted.tag
+            )
+        )
+        AND (cardinality($3) = 0 OR EXISTS (
+            SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = ANY($3)
+        ))
+        AND NOT EXISTS (
+            SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = ANY($4)
+        )
+        AND ($5 IS NULL OR s.subscribed_at > now() - make_interval(days => $5))
+        AND ($6 IS NULL OR s.subscribed_at >= $6)
+        AND ($7 IS NULL OR s.subscribed_at < $7)
+$$;
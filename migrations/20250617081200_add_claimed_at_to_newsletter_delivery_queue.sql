@@ -0,0 +1,7 @@
+-- Set, and committed, just before a worker hands the email to the provider. Claimed rows are
+-- never picked up again: if the worker dies mid-call nobody knows whether the email left, and a
+-- row dead-lettered for an admin to look at is better than a batch of up to 500 emails sent twice.
+ALTER TABLE newsletter_delivery_queue ADD COLUMN claimed_at timestamptz;
+-- Looked up on every pass of the worker, to sweep claims that have timed out.
+CREATE INDEX newsletter_delivery_queue_claimed_idx ON newsletter_delivery_queue (claimed_at)
+    WHERE claimed_at IS NOT NULL;
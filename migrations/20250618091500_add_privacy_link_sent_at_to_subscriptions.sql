@@ -0,0 +1,3 @@
+-- When a privacy link last went out to the address, so asking over and over can't be used to
+-- flood someone's mailbox.
+ALTER TABLE subscriptions ADD COLUMN privacy_link_sent_at timestamptz;
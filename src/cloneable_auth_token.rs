@@ -21,6 +21,18 @@ impl AuthToken {
             Err(_) => Err(serde::de::Error::custom("bad token")),
         }
     }
+
+    pub fn deserialize_option_from_str<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<SecretAuthToken>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(deserializer) {
+            Ok(s) => Ok(s.map(AuthToken::new)),
+            Err(_) => Err(serde::de::Error::custom("bad token")),
+        }
+    }
 }
 
 impl Zeroize for AuthToken {
@@ -1,15 +1,21 @@
 use crate::cloneable_auth_token::{AuthToken, SecretAuthToken};
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::email_client::{
+    EmailClient, EmailTransport, FailoverTransport, FileTransport, PostmarkTransport,
+    RateLimitedTransport, SmtpTransport, StdoutTransport,
+};
 use secrecy::ExposeSecret;
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub worker: WorkerSettings,
     #[serde(deserialize_with = "AuthToken::deserialize_from_str")]
     pub redis_uri: SecretAuthToken,
 }
@@ -34,22 +40,237 @@ pub struct ApplicationSettings {
     pub base_url: String,
     #[serde(deserialize_with = "AuthToken::deserialize_from_str")]
     pub hmac_secret: SecretAuthToken,
+    /// Shared with the email provider to authenticate its bounce and complaint webhooks.
+    #[serde(deserialize_with = "AuthToken::deserialize_from_str")]
+    pub webhook_secret: SecretAuthToken,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub confirmation_token_ttl_hours: i64,
+    /// How long shutdown may take before in-flight work is abandoned.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_deadline_seconds: u64,
+}
+
+impl ApplicationSettings {
+    pub fn shutdown_deadline(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_deadline_seconds)
+    }
+
+    pub fn confirmation_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.confirmation_token_ttl_hours)
+    }
+}
+
+/// How the background worker drains the delivery queues.
+#[derive(serde::Deserialize, Clone)]
+pub struct WorkerSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub concurrency: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idle_poll_interval_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub error_backoff_milliseconds: u64,
+    /// How long a batch may stay claimed before its worker is presumed dead and the emails are
+    /// dead-lettered with an unknown outcome.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub claim_timeout_seconds: u64,
+}
+
+impl WorkerSettings {
+    pub fn idle_poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.idle_poll_interval_milliseconds)
+    }
+
+    pub fn error_backoff(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.error_backoff_milliseconds)
+    }
+
+    pub fn claim_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.claim_timeout_seconds)
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
-    pub base_url: String,
     pub sender_email: String,
-    #[serde(deserialize_with = "AuthToken::deserialize_from_str")]
-    pub auth_token: SecretAuthToken,
     pub timeout_milliseconds: u64,
+    /// The primary provider.
+    #[serde(flatten)]
+    pub provider: ProviderSettings,
+    /// Tried in order when the providers before them are failing.
+    #[serde(default)]
+    pub failover: Vec<ProviderSettings>,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerSettings,
+    pub rate_limit: Option<RateLimitSettings>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct ProviderSettings {
+    /// Recorded against every email the provider delivers. Defaults to the transport's name.
+    pub name: Option<String>,
+    #[serde(default)]
+    pub transport: EmailTransportKind,
+    #[serde(default)]
+    pub base_url: String,
+    #[serde(default, deserialize_with = "AuthToken::deserialize_option_from_str")]
+    pub auth_token: Option<SecretAuthToken>,
+    pub smtp: Option<SmtpSettings>,
+    pub maildir: Option<String>,
+}
+
+/// After `failure_threshold` consecutive 5xx replies or timeouts a provider is skipped for
+/// `cooldown_seconds`, then given another chance.
+#[derive(serde::Deserialize, Clone)]
+pub struct CircuitBreakerSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failure_threshold: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cooldown_seconds: u64,
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown_seconds: 30,
+        }
+    }
+}
+
+/// Caps on how fast emails leave, shared by everything sending through the client.
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub messages_per_second: Option<f64>,
+    /// Caps for individual recipient domains, e.g. `gmail.com: 10`.
+    #[serde(default)]
+    pub per_domain: HashMap<String, f64>,
+}
+
+impl RateLimitSettings {
+    /// Every rate has to be a positive number: the limiter can't hold mail to zero per second.
+    pub fn validate(&self) -> Result<(), String> {
+        let rates = self
+            .messages_per_second
+            .iter()
+            .map(|rate| ("messages_per_second", *rate))
+            .chain(
+                self.per_domain
+                    .iter()
+                    .map(|(domain, rate)| (domain.as_str(), *rate)),
+            );
+        for (name, rate) in rates {
+            if !(rate.is_finite() && rate > 0.) {
+                return Err(format!(
+                    "The `{}` rate limit must be a positive number of messages per second, not {}.",
+                    name, rate
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which backend delivers email. `base_url` and `auth_token` only matter for `postmark`,
+/// `smtp` needs the `smtp` section and `file` needs `maildir`.
+#[derive(serde::Deserialize, Clone, Default, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailTransportKind {
+    #[default]
+    Postmark,
+    Smtp,
+    File,
+    Stdout,
+}
+
+impl EmailTransportKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailTransportKind::Postmark => "postmark",
+            EmailTransportKind::Smtp => "smtp",
+            EmailTransportKind::File => "file",
+            EmailTransportKind::Stdout => "stdout",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    #[serde(default, deserialize_with = "AuthToken::deserialize_option_from_str")]
+    pub password: Option<SecretAuthToken>,
+    #[serde(default = "default_starttls")]
+    pub starttls: bool,
+}
+
+fn default_starttls() -> bool {
+    true
 }
 
 impl EmailClientSettings {
     pub fn client(self) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address.");
         let timeout = self.timout();
-        EmailClient::new(self.base_url, sender_email, self.auth_token, timeout)
+        let providers = std::iter::once(self.provider)
+            .chain(self.failover)
+            .map(|provider| provider.transport(timeout))
+            .collect();
+        let transport: Arc<dyn EmailTransport> = Arc::new(FailoverTransport::new(
+            providers,
+            self.circuit_breaker.failure_threshold,
+            std::time::Duration::from_secs(self.circuit_breaker.cooldown_seconds),
+        ));
+        let transport = match self.rate_limit {
+            Some(limits) => Arc::new(RateLimitedTransport::new(
+                transport,
+                limits.messages_per_second,
+                limits.per_domain,
+            )),
+            None => transport,
+        };
+        EmailClient::with_transport(sender_email, transport)
+    }
+}
+
+impl ProviderSettings {
+    fn transport(self, timeout: std::time::Duration) -> (String, Arc<dyn EmailTransport>) {
+        let name = self
+            .name
+            .unwrap_or_else(|| self.transport.as_str().to_string());
+        let transport: Arc<dyn EmailTransport> = match self.transport {
+            EmailTransportKind::Postmark => Arc::new(PostmarkTransport::new(
+                self.base_url,
+                self.auth_token
+                    .expect("The postmark transport needs an `auth_token`."),
+                timeout,
+            )),
+            EmailTransportKind::Smtp => {
+                let smtp = self
+                    .smtp
+                    .expect("The smtp transport needs an `smtp` section.");
+                let credentials = smtp.username.map(|username| {
+                    let password = smtp
+                        .password
+                        .map(|p| p.expose_secret().token.clone())
+                        .unwrap_or_default();
+                    (username, password)
+                });
+                Arc::new(
+                    SmtpTransport::new(&smtp.host, smtp.port, credentials, smtp.starttls, timeout)
+                        .expect("Invalid SMTP relay."),
+                )
+            }
+            EmailTransportKind::File => Arc::new(FileTransport::new(
+                self.maildir
+                    .expect("The file transport needs a `maildir` directory."),
+            )),
+            EmailTransportKind::Stdout => Arc::new(StdoutTransport),
+        };
+        (name, transport)
     }
 }
 
@@ -87,7 +308,13 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
                 .separator("__"),
         )
         .build()?;
-    settings.try_deserialize::<Settings>()
+    let settings = settings.try_deserialize::<Settings>()?;
+    if let Some(rate_limit) = &settings.email_client.rate_limit {
+        rate_limit
+            .validate()
+            .map_err(config::ConfigError::Message)?;
+    }
+    Ok(settings)
 }
 
 pub enum Environment {
@@ -136,3 +363,42 @@ impl DatabaseSettings {
             .database(&self.database_name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimitSettings;
+    use claims::{assert_err, assert_ok};
+    use std::collections::HashMap;
+
+    fn limits(messages_per_second: Option<f64>, per_domain: &[(&str, f64)]) -> RateLimitSettings {
+        RateLimitSettings {
+            messages_per_second,
+            per_domain: per_domain
+                .iter()
+                .map(|(domain, rate)| (domain.to_string(), *rate))
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn positive_rate_limits_are_valid() {
+        assert_ok!(limits(Some(50.), &[("gmail.com", 0.5)]).validate());
+        assert_ok!(limits(None, &[]).validate());
+    }
+
+    #[test]
+    fn rate_limits_that_would_stop_sending_are_rejected() {
+        for rate in [0., -1., f64::NAN, f64::INFINITY] {
+            assert_err!(
+                limits(Some(rate), &[]).validate(),
+                "{} should be rejected",
+                rate
+            );
+            assert_err!(
+                limits(None, &[("gmail.com", rate)]).validate(),
+                "{} should be rejected",
+                rate
+            );
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use crate::domain::newsletter_deliveries::{record_delivery, DeliveryOutcome};
+use crate::domain::newsletter_queue::notify_worker;
+use crate::domain::newsletters::mark_sent_if_drained;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Recorded for emails whose worker died between claiming them and hearing back from the provider.
+pub const OUTCOME_UNKNOWN: &str =
+    "Outcome unknown: the worker stopped while this email was claimed, so it may have been sent.";
+
+#[derive(serde::Serialize)]
+pub struct DeadLetter {
+    pub newsletter_id: Uuid,
+    pub subscriber_email: String,
+    pub n_retries: i16,
+    pub last_error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn list_dead_letters(pool: &PgPool) -> Result<Vec<DeadLetter>, sqlx::Error> {
+    sqlx::query_as!(
+        DeadLetter,
+        r#"
+    SELECT newsletter_id, subscriber_email, n_retries, last_error, failed_at
+        FROM newsletter_delivery_dead_letters
+    ORDER BY failed_at DESC"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Moves a delivery task out of the queue for good, keeping the last error around for admins.
+#[tracing::instrument(skip_all)]
+pub async fn dead_letter_task(
+    trx: &mut Transaction<'_, Postgres>,
+    newsletter_id: Uuid,
+    subscriber_email: &str,
+    n_retries: i16,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+    INSERT INTO newsletter_delivery_dead_letters (
+        newsletter_id,
+        subscriber_email,
+        n_retries,
+        last_error,
+        failed_at
+    )
+    VALUES ($1, $2, $3, $4, now())
+    ON CONFLICT (newsletter_id, subscriber_email) DO UPDATE
+    SET n_retries = EXCLUDED.n_retries,
+        last_error = EXCLUDED.last_error,
+        failed_at = EXCLUDED.failed_at"#,
+        newsletter_id,
+        subscriber_email,
+        n_retries,
+        error
+    );
+    trx.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+    DELETE FROM newsletter_delivery_queue WHERE newsletter_id = $1 AND subscriber_email = $2"#,
+        newsletter_id,
+        subscriber_email
+    );
+    trx.execute(query).await?;
+    mark_sent_if_drained(trx, newsletter_id).await?;
+    Ok(())
+}
+
+/// Dead-letters the emails claimed more than `claim_timeout` ago, so that a worker dying mid-call
+/// doesn't leave its batch pending forever. Whether they went out is unknown: requeueing them is
+/// left to an admin. Returns how many were moved.
+#[tracing::instrument(skip_all)]
+pub async fn dead_letter_stale_claims(
+    pool: &PgPool,
+    claim_timeout: Duration,
+) -> Result<u64, sqlx::Error> {
+    let mut trx = pool.begin().await?;
+    let stale = sqlx::query!(
+        r#"
+    SELECT newsletter_id, subscriber_email, n_retries
+        FROM newsletter_delivery_queue
+    WHERE claimed_at <= now() - make_interval(secs => $1)
+    ORDER BY newsletter_id
+    FOR UPDATE
+    SKIP LOCKED"#,
+        claim_timeout.as_secs_f64()
+    )
+    .fetch_all(&mut *trx)
+    .await?;
+    for task in &stale {
+        record_delivery(
+            &mut trx,
+            task.newsletter_id,
+            &task.subscriber_email,
+            DeliveryOutcome::Failed,
+            None,
+            Some(OUTCOME_UNKNOWN),
+        )
+        .await?;
+        dead_letter_task(
+            &mut trx,
+            task.newsletter_id,
+            &task.subscriber_email,
+            task.n_retries,
+            OUTCOME_UNKNOWN,
+        )
+        .await?;
+    }
+    trx.commit().await?;
+    Ok(stale.len() as u64)
+}
+
+/// Puts dead letters back in the delivery queue with a fresh retry budget. `None` filters match
+/// everything, so requeueing without arguments drains the whole table: callers should make sure
+/// that is what was asked for.
+#[tracing::instrument(skip_all)]
+pub async fn requeue_dead_letters(
+    pool: &PgPool,
+    newsletter_id: Option<Uuid>,
+    subscriber_email: Option<&str>,
+) -> Result<u64, sqlx::Error> {
+    let mut trx = pool.begin().await?;
+    let query = sqlx::query!(
+        r#"
+    WITH requeued AS (
+        DELETE FROM newsletter_delivery_dead_letters
+        WHERE ($1::uuid IS NULL OR newsletter_id = $1)
+            AND ($2::text IS NULL OR subscriber_email = $2)
+        RETURNING newsletter_id, subscriber_email
+    )
+    INSERT INTO newsletter_delivery_queue (newsletter_id, subscriber_email)
+    SELECT newsletter_id, subscriber_email FROM requeued
+    ON CONFLICT DO NOTHING"#,
+        newsletter_id,
+        subscriber_email
+    );
+    let n_requeued = trx.execute(query).await?.rows_affected();
+    let query = sqlx::query!(
+        r#"
+    UPDATE newsletters SET status = 'sending', updated_at = now()
+    WHERE status = 'sent'
+        AND newsletter_id IN (SELECT newsletter_id FROM newsletter_delivery_queue)"#
+    );
+    trx.execute(query).await?;
+    if n_requeued > 0 {
+        notify_worker(&mut trx).await?;
+    }
+    trx.commit().await?;
+    Ok(n_requeued)
+}
@@ -0,0 +1,139 @@
+use crate::cloneable_auth_token::SecretAuthToken;
+use crate::signed_token;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmailEventKind {
+    Delivery,
+    HardBounce,
+    SoftBounce,
+    Complaint,
+}
+
+impl EmailEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailEventKind::Delivery => "delivery",
+            EmailEventKind::HardBounce => "hard_bounce",
+            EmailEventKind::SoftBounce => "soft_bounce",
+            EmailEventKind::Complaint => "complaint",
+        }
+    }
+
+    /// Hard bounces and complaints put the address on the suppression list; soft bounces are
+    /// usually a full mailbox and sort themselves out.
+    pub fn suppression_reason(&self) -> Option<SuppressionReason> {
+        match self {
+            EmailEventKind::HardBounce => Some(SuppressionReason::Bounced),
+            EmailEventKind::Complaint => Some(SuppressionReason::Complained),
+            EmailEventKind::Delivery | EmailEventKind::SoftBounce => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SuppressionReason {
+    Bounced,
+    Complained,
+}
+
+impl SuppressionReason {
+    /// Doubles as the subscriber status the address ends up with.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::Bounced => "bounced",
+            SuppressionReason::Complained => "complained",
+        }
+    }
+}
+
+pub struct EmailEvent {
+    pub kind: EmailEventKind,
+    pub email: String,
+    pub provider_message_id: Option<String>,
+    pub detail: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Stores the event and, for hard bounces and complaints, suppresses the address and flags the
+/// subscriber so no further newsletters are queued for them.
+#[tracing::instrument(skip(trx, event), fields(kind = event.kind.as_str(), email = %event.email))]
+pub async fn record_email_event(
+    trx: &mut Transaction<'_, Postgres>,
+    event: &EmailEvent,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+    INSERT INTO email_events (
+        event_id,
+        kind,
+        email,
+        provider_message_id,
+        detail,
+        occurred_at,
+        received_at
+    )
+    VALUES ($1, $2, $3, $4, $5, $6, now())"#,
+        Uuid::new_v4(),
+        event.kind.as_str(),
+        event.email,
+        event.provider_message_id,
+        event.detail,
+        event.occurred_at
+    );
+    trx.execute(query).await?;
+    if let Some(reason) = event.kind.suppression_reason() {
+        suppress_email(trx, &event.email, reason).await?;
+    }
+    Ok(())
+}
+
+// A complaint outranks an earlier bounce, it's the stronger signal.
+async fn suppress_email(
+    trx: &mut Transaction<'_, Postgres>,
+    email: &str,
+    reason: SuppressionReason,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+    INSERT INTO suppressed_emails (email, reason, suppressed_at)
+    VALUES (lower($1), $2, now())
+    ON CONFLICT (email) DO UPDATE
+    SET reason = EXCLUDED.reason
+    WHERE EXCLUDED.reason = 'complained'"#,
+        email,
+        reason.as_str()
+    );
+    trx.execute(query).await?;
+    let query = sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE lower(email) = lower($1)"#,
+        email,
+        reason.as_str()
+    );
+    trx.execute(query).await?;
+    Ok(())
+}
+
+/// What an erased subscriber leaves on the suppression list instead of their address. It is keyed
+/// with the app's secret, so it can't be matched against a list of known addresses without it.
+pub fn email_hash(secret: &SecretAuthToken, email: &str) -> String {
+    signed_token::keyed_hash(secret, "erased-email", &email.to_lowercase())
+}
+
+/// Checks the address against bounces and complaints, and against the hashes erasure leaves.
+pub async fn is_suppressed<'e>(
+    executor: impl PgExecutor<'e>,
+    secret: &SecretAuthToken,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT email FROM suppressed_emails WHERE email = lower($1) OR email = $2"#,
+        email,
+        email_hash(secret, email)
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.is_some())
+}
@@ -0,0 +1,37 @@
+use crate::domain::newsletter_queue::notify_worker;
+use crate::domain::SubscriberEmail;
+use sqlx::{Executor, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Stores an email for the background worker to deliver. Writing it in the caller's transaction
+/// means it goes out if and only if the surrounding changes are committed.
+#[tracing::instrument(skip(trx, html_content, text_content))]
+pub async fn enqueue_email(
+    trx: &mut Transaction<'_, Postgres>,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let email_id = Uuid::new_v4();
+    let query = sqlx::query!(
+        r#"
+    INSERT INTO email_outbox (
+        email_id,
+        recipient,
+        subject,
+        html_content,
+        text_content,
+        created_at
+    )
+    VALUES ($1, $2, $3, $4, $5, now())"#,
+        email_id,
+        recipient.as_ref(),
+        subject,
+        html_content,
+        text_content
+    );
+    trx.execute(query).await?;
+    notify_worker(trx).await?;
+    Ok(email_id)
+}
@@ -0,0 +1,351 @@
+use crate::cloneable_auth_token::SecretAuthToken;
+use crate::signed_token;
+use sqlx::PgPool;
+use std::str::FromStr;
+use uuid::Uuid;
+
+const OPEN_PURPOSE: &str = "open";
+const CLICK_PURPOSE: &str = "click";
+const TOP_LINKS: i64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EngagementKind {
+    Open,
+    Click,
+}
+
+impl EngagementKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EngagementKind::Open => "open",
+            EngagementKind::Click => "click",
+        }
+    }
+}
+
+/// Points every external link in `html` at the click-tracking redirect and adds an open-tracking
+/// pixel. Links back to the app itself, unsubscribe included, are left as they are.
+pub fn add_tracking(
+    html: &str,
+    base_url: &str,
+    secret: &SecretAuthToken,
+    newsletter_id: Uuid,
+    subscriber_id: Uuid,
+) -> String {
+    let recipient = format!("{}:{}", newsletter_id, subscriber_id);
+    let mut tracked = rewrite_links(html, |url| {
+        if url.starts_with(base_url) {
+            return None;
+        }
+        let token = signed_token::sign(secret, CLICK_PURPOSE, &format!("{}:{}", recipient, url));
+        Some(format!("{}/tracking/click?token={}", base_url, token))
+    });
+
+    let pixel = format!(
+        r#"<img src="{}/tracking/open?token={}" width="1" height="1" alt="" style="display:none" />"#,
+        base_url,
+        signed_token::sign(secret, OPEN_PURPOSE, &recipient)
+    );
+    match tracked.to_ascii_lowercase().rfind("</body>") {
+        Some(i) => tracked.insert_str(i, &pixel),
+        None => tracked.push_str(&pixel),
+    }
+    tracked
+}
+
+// Good enough for the HTML we write ourselves: finds quoted `href` attributes and hands their
+// (unescaped) http(s) targets to `track`, which may return a replacement.
+fn rewrite_links(html: &str, mut track: impl FnMut(&str) -> Option<String>) -> String {
+    let lowercase = html.to_ascii_lowercase();
+    let mut rewritten = String::with_capacity(html.len());
+    let mut copied = 0;
+    let mut position = 0;
+    while let Some(i) = lowercase[position..].find("href=") {
+        let start = position + i;
+        position = start + "href=".len();
+        let is_attribute = html[..start]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_whitespace);
+        let quote = match html[position..].chars().next() {
+            Some(quote @ ('"' | '\'')) if is_attribute => quote,
+            _ => continue,
+        };
+        let value_start = position + 1;
+        let value_end = match html[value_start..].find(quote) {
+            Some(len) => value_start + len,
+            None => break,
+        };
+        position = value_end;
+
+        let url = html[value_start..value_end].replace("&amp;", "&");
+        let lowercase_url = url.to_ascii_lowercase();
+        if !(lowercase_url.starts_with("http://") || lowercase_url.starts_with("https://")) {
+            continue;
+        }
+        if let Some(replacement) = track(&url) {
+            rewritten.push_str(&html[copied..value_start]);
+            rewritten.push_str(&replacement);
+            copied = value_end;
+        }
+    }
+    rewritten.push_str(&html[copied..]);
+    rewritten
+}
+
+fn parse_recipient(recipient: &str) -> Option<(Uuid, Uuid)> {
+    let (newsletter_id, subscriber_id) = recipient.split_once(':')?;
+    Some((
+        Uuid::from_str(newsletter_id).ok()?,
+        Uuid::from_str(subscriber_id).ok()?,
+    ))
+}
+
+/// Returns the issue and subscriber an open-tracking pixel was made for.
+pub fn parse_open_token(secret: &SecretAuthToken, token: &str) -> Option<(Uuid, Uuid)> {
+    parse_recipient(&signed_token::verify(secret, OPEN_PURPOSE, token)?)
+}
+
+/// Returns the issue, subscriber and original URL behind a tracked link.
+pub fn parse_click_token(secret: &SecretAuthToken, token: &str) -> Option<(Uuid, Uuid, String)> {
+    let payload = signed_token::verify(secret, CLICK_PURPOSE, token)?;
+    let mut parts = payload.splitn(3, ':');
+    let recipient = format!("{}:{}", parts.next()?, parts.next()?);
+    let (newsletter_id, subscriber_id) = parse_recipient(&recipient)?;
+    Some((newsletter_id, subscriber_id, parts.next()?.to_string()))
+}
+
+/// Nothing is recorded for subscribers or issues that have since been deleted.
+#[tracing::instrument(skip(pool))]
+pub async fn record_engagement(
+    pool: &PgPool,
+    newsletter_id: Uuid,
+    subscriber_id: Uuid,
+    kind: EngagementKind,
+    url: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+    INSERT INTO newsletter_engagement (
+        event_id,
+        newsletter_id,
+        subscriber_id,
+        kind,
+        url,
+        occurred_at
+    )
+    SELECT $1::uuid, $2::uuid, $3::uuid, $4::text, $5::text, now()
+    WHERE EXISTS (SELECT 1 FROM subscriptions WHERE id = $3)
+        AND EXISTS (SELECT 1 FROM newsletters WHERE newsletter_id = $2)"#,
+        Uuid::new_v4(),
+        newsletter_id,
+        subscriber_id,
+        kind.as_str(),
+        url
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+pub struct LinkClicks {
+    pub url: String,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct EngagementReport {
+    pub newsletter_id: Uuid,
+    pub title: String,
+    pub track_engagement: bool,
+    pub delivered: i64,
+    pub unique_opens: i64,
+    pub unique_clicks: i64,
+    pub open_rate: f64,
+    pub click_rate: f64,
+    pub top_links: Vec<LinkClicks>,
+}
+
+/// Rates are per delivered email. A click counts as an open too, since plenty of clients block
+/// the pixel. `None` means there is no such issue.
+#[tracing::instrument(skip(pool))]
+pub async fn get_engagement_report(
+    pool: &PgPool,
+    newsletter_id: Uuid,
+) -> Result<Option<EngagementReport>, sqlx::Error> {
+    let issue = sqlx::query!(
+        r#"SELECT title, track_engagement FROM newsletters WHERE newsletter_id = $1"#,
+        newsletter_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    let issue = match issue {
+        Some(issue) => issue,
+        None => return Ok(None),
+    };
+
+    let delivered = sqlx::query_scalar!(
+        r#"
+    SELECT count(*) AS "count!" FROM newsletter_deliveries
+    WHERE newsletter_id = $1 AND outcome = 'sent'"#,
+        newsletter_id
+    )
+    .fetch_one(pool)
+    .await?;
+    let unique = sqlx::query!(
+        r#"
+    SELECT
+        count(DISTINCT subscriber_id) AS "opens!",
+        count(DISTINCT subscriber_id) FILTER (WHERE kind = 'click') AS "clicks!"
+    FROM newsletter_engagement
+    WHERE newsletter_id = $1"#,
+        newsletter_id
+    )
+    .fetch_one(pool)
+    .await?;
+    let top_links = sqlx::query_as!(
+        LinkClicks,
+        r#"
+    SELECT
+        url AS "url!",
+        count(*) AS "clicks!",
+        count(DISTINCT subscriber_id) AS "unique_clicks!"
+    FROM newsletter_engagement
+    WHERE newsletter_id = $1 AND kind = 'click'
+    GROUP BY url
+    ORDER BY 2 DESC, 1
+    LIMIT $2"#,
+        newsletter_id,
+        TOP_LINKS
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let rate = |n: i64| {
+        if delivered == 0 {
+            0.
+        } else {
+            n as f64 / delivered as f64
+        }
+    };
+    Ok(Some(EngagementReport {
+        newsletter_id,
+        title: issue.title,
+        track_engagement: issue.track_engagement,
+        delivered,
+        unique_opens: unique.opens,
+        unique_clicks: unique.clicks,
+        open_rate: rate(unique.opens),
+        click_rate: rate(unique.clicks),
+        top_links,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_tracking, parse_click_token, parse_open_token, rewrite_links};
+    use crate::cloneable_auth_token::AuthToken;
+    use claims::{assert_none, assert_some_eq};
+    use uuid::Uuid;
+
+    fn shout(url: &str) -> Option<String> {
+        Some(url.to_uppercase())
+    }
+
+    #[test]
+    fn http_links_are_rewritten() {
+        let html = r#"<a href="https://example.com/a">a</a> <a class="x" href='http://example.com/b'>b</a>"#;
+        assert_eq!(
+            rewrite_links(html, shout),
+            r#"<a href="HTTPS://EXAMPLE.COM/A">a</a> <a class="x" href='HTTP://EXAMPLE.COM/B'>b</a>"#
+        );
+    }
+
+    #[test]
+    fn other_schemes_and_lookalike_attributes_are_left_alone() {
+        let html = r##"<a href="mailto:me@example.com">m</a><a href="#top">t</a><a data-href="https://example.com">d</a>"##;
+        assert_eq!(rewrite_links(html, shout), html);
+    }
+
+    #[test]
+    fn escaped_ampersands_are_unescaped_before_tracking() {
+        let mut seen = Vec::new();
+        rewrite_links(
+            r#"<a href="https://example.com/?a=1&amp;b=2">x</a>"#,
+            |url| {
+                seen.push(url.to_string());
+                None
+            },
+        );
+        assert_eq!(seen, vec!["https://example.com/?a=1&b=2"]);
+    }
+
+    #[test]
+    fn tracked_links_carry_the_recipient_and_the_original_url() {
+        let secret = AuthToken::new("secret".to_string());
+        let (newsletter_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let html = add_tracking(
+            r#"<p><a href="https://example.com/post?id=1">Read</a></p>"#,
+            "http://127.0.0.1",
+            &secret,
+            newsletter_id,
+            subscriber_id,
+        );
+
+        let token = html
+            .split("/tracking/click?token=")
+            .nth(1)
+            .unwrap()
+            .split('"')
+            .next()
+            .unwrap();
+        assert_some_eq!(
+            parse_click_token(&secret, token),
+            (
+                newsletter_id,
+                subscriber_id,
+                "https://example.com/post?id=1".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn links_back_to_the_app_are_not_tracked() {
+        let secret = AuthToken::new("secret".to_string());
+        let html = add_tracking(
+            r#"<a href="http://127.0.0.1/subscriptions/unsubscribe?token=abc">Unsubscribe</a>"#,
+            "http://127.0.0.1",
+            &secret,
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+        assert!(html.contains(r#"href="http://127.0.0.1/subscriptions/unsubscribe?token=abc""#));
+        assert!(!html.contains("/tracking/click"));
+    }
+
+    #[test]
+    fn the_pixel_goes_inside_the_body() {
+        let secret = AuthToken::new("secret".to_string());
+        let html = add_tracking(
+            "<html><body><p>Hi</p></BODY></html>",
+            "http://127.0.0.1",
+            &secret,
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+        assert!(html.contains(r#"/></BODY></html>"#));
+        assert!(html.contains("/tracking/open?token="));
+    }
+
+    #[test]
+    fn open_and_click_tokens_are_not_interchangeable() {
+        let secret = AuthToken::new("secret".to_string());
+        let recipient = format!("{}:{}", Uuid::new_v4(), Uuid::new_v4());
+        let open = crate::signed_token::sign(&secret, "open", &recipient);
+        assert_none!(parse_click_token(&secret, &open));
+        let click = crate::signed_token::sign(&secret, "click", &recipient);
+        assert_none!(parse_open_token(&secret, &click));
+    }
+}
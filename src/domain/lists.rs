@@ -0,0 +1,178 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// The list everyone was on before there were lists, and the one forms and issues use when
+/// they don't name another.
+pub const DEFAULT_LIST: &str = "newsletter";
+
+/// Slugs end up in URLs and in signed tokens, so they're kept to lowercase letters, digits and
+/// dashes.
+pub fn parse_slug(slug: &str) -> Result<String, String> {
+    let valid = !slug.is_empty()
+        && slug.len() <= 64
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if valid {
+        Ok(slug.to_string())
+    } else {
+        Err(format!(
+            "'{}' is not a valid list slug: use up to 64 lowercase letters, digits and dashes.",
+            slug
+        ))
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct MailingList {
+    pub slug: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub confirmed: i64,
+    pub pending_confirmation: i64,
+    pub unsubscribed: i64,
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn list_lists(pool: &PgPool) -> Result<Vec<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"
+    SELECT l.slug, l.name, l.created_at,
+        count(*) FILTER (WHERE m.status = 'confirmed') AS "confirmed!",
+        count(*) FILTER (WHERE m.status = 'pending_confirmation') AS "pending_confirmation!",
+        count(*) FILTER (WHERE m.status = 'unsubscribed') AS "unsubscribed!"
+    FROM lists l
+    LEFT JOIN list_memberships m ON m.list_slug = l.slug
+    GROUP BY l.slug
+    ORDER BY l.created_at, l.slug"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns `false` if a list with this slug already exists.
+#[tracing::instrument(skip(pool))]
+pub async fn create_list(pool: &PgPool, slug: &str, name: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+    INSERT INTO lists (slug, name, created_at) VALUES ($1, $2, now())
+    ON CONFLICT (slug) DO NOTHING"#,
+        slug,
+        name
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn list_exists<'e>(
+    executor: impl PgExecutor<'e>,
+    slug: &str,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT slug FROM lists WHERE slug = $1"#, slug)
+        .fetch_optional(executor)
+        .await?;
+    Ok(row.is_some())
+}
+
+/// Puts the subscriber on the list as pending, unless they're already confirmed there. Returns
+/// `true` when they now need a confirmation link.
+#[tracing::instrument(skip(trx))]
+pub async fn join_list(
+    trx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_slug: &str,
+) -> Result<bool, sqlx::Error> {
+    let joined = sqlx::query!(
+        r#"
+    INSERT INTO list_memberships (list_slug, subscriber_id, status, subscribed_at)
+    VALUES ($1, $2, 'pending_confirmation', now())
+    ON CONFLICT (list_slug, subscriber_id) DO UPDATE
+    SET status = 'pending_confirmation',
+        subscribed_at = CASE
+            WHEN list_memberships.status = 'unsubscribed' THEN EXCLUDED.subscribed_at
+            ELSE list_memberships.subscribed_at
+        END
+    WHERE list_memberships.status <> 'confirmed'
+    RETURNING subscriber_id"#,
+        list_slug,
+        subscriber_id
+    )
+    .fetch_optional(&mut **trx)
+    .await?;
+    Ok(joined.is_some())
+}
+
+/// Sets the status of one membership, or of all of them when `list_slug` is `None`, and brings
+/// the subscriber's overall status in line.
+#[tracing::instrument(skip(trx))]
+pub async fn set_membership_status(
+    trx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_slug: Option<&str>,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+    UPDATE list_memberships SET status = $3
+    WHERE subscriber_id = $1 AND ($2::text IS NULL OR list_slug = $2)"#,
+        subscriber_id,
+        list_slug,
+        status
+    );
+    trx.execute(query).await?;
+    refresh_subscriber_status(trx, subscriber_id).await
+}
+
+/// `subscriptions.status` sums up the memberships: confirmed somewhere, else pending somewhere,
+/// else unsubscribed. Bounces and complaints are about the address and take precedence.
+#[tracing::instrument(skip(trx))]
+pub async fn refresh_subscriber_status(
+    trx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+    UPDATE subscriptions SET status = CASE
+        WHEN EXISTS (
+            SELECT 1 FROM list_memberships WHERE subscriber_id = $1 AND status = 'confirmed'
+        ) THEN 'confirmed'
+        WHEN EXISTS (
+            SELECT 1 FROM list_memberships
+            WHERE subscriber_id = $1 AND status = 'pending_confirmation'
+        ) THEN 'pending_confirmation'
+        ELSE 'unsubscribed'
+    END
+    WHERE id = $1 AND status IS DISTINCT FROM 'bounced' AND status IS DISTINCT FROM 'complained'"#,
+        subscriber_id
+    );
+    trx.execute(query).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_slug, DEFAULT_LIST};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn the_default_list_has_a_valid_slug() {
+        assert_ok!(parse_slug(DEFAULT_LIST));
+    }
+
+    #[test]
+    fn slugs_are_lowercase_letters_digits_and_dashes() {
+        assert_ok!(parse_slug("release-notes-2025"));
+        for slug in [
+            "",
+            "Release",
+            "release notes",
+            "release:notes",
+            &"a".repeat(65),
+        ] {
+            assert_err!(parse_slug(slug), "{:?} should be rejected", slug);
+        }
+    }
+}
@@ -1,8 +1,21 @@
+pub mod dead_letters;
+pub mod email_events;
+pub mod email_outbox;
+pub mod engagement;
+pub mod lists;
 mod new_subscriber;
+pub mod newsletter_deliveries;
 pub mod newsletter_queue;
 pub mod newsletters;
+pub mod preferences;
+pub mod privacy;
+pub mod segments;
 mod subscriber_email;
+pub mod subscriber_import;
 mod subscriber_name;
+pub mod subscribers;
+pub mod tags;
+pub mod unsubscribe;
 mod users;
 
 pub use new_subscriber::NewSubscriber;
@@ -0,0 +1,179 @@
+use crate::email_client::EmailReceipt;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryOutcome {
+    Sent,
+    Retrying,
+    Failed,
+    Skipped,
+}
+
+impl DeliveryOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Sent => "sent",
+            DeliveryOutcome::Retrying => "retrying",
+            DeliveryOutcome::Failed => "failed",
+            DeliveryOutcome::Skipped => "skipped",
+        }
+    }
+
+    fn is_attempt(&self) -> bool {
+        !matches!(self, DeliveryOutcome::Skipped)
+    }
+}
+
+/// Writes the latest outcome for one recipient of an issue, counting send attempts as it goes.
+/// `receipt` is what the provider said about an email it accepted.
+#[tracing::instrument(skip(trx, receipt, error))]
+pub async fn record_delivery(
+    trx: &mut Transaction<'_, Postgres>,
+    newsletter_id: Uuid,
+    subscriber_email: &str,
+    outcome: DeliveryOutcome,
+    receipt: Option<&EmailReceipt>,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let n_attempts: i16 = if outcome.is_attempt() { 1 } else { 0 };
+    let query = sqlx::query!(
+        r#"
+    INSERT INTO newsletter_deliveries (
+        newsletter_id,
+        subscriber_email,
+        n_attempts,
+        outcome,
+        provider_message_id,
+        provider,
+        last_error,
+        first_attempted_at,
+        last_attempted_at
+    )
+    VALUES ($1, $2, $3, $4, $5, $6, $7, now(), now())
+    ON CONFLICT (newsletter_id, subscriber_email) DO UPDATE
+    SET n_attempts = newsletter_deliveries.n_attempts + EXCLUDED.n_attempts,
+        outcome = EXCLUDED.outcome,
+        provider_message_id = COALESCE(
+            EXCLUDED.provider_message_id,
+            newsletter_deliveries.provider_message_id
+        ),
+        provider = COALESCE(EXCLUDED.provider, newsletter_deliveries.provider),
+        last_error = EXCLUDED.last_error,
+        last_attempted_at = EXCLUDED.last_attempted_at"#,
+        newsletter_id,
+        subscriber_email,
+        n_attempts,
+        outcome.as_str(),
+        receipt.and_then(|r| r.message_id.as_deref()),
+        receipt.and_then(|r| r.provider.as_deref()),
+        error
+    );
+    trx.execute(query).await?;
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+pub struct RecipientDelivery {
+    pub subscriber_email: String,
+    pub status: String,
+    pub n_attempts: i16,
+    pub provider_message_id: Option<String>,
+    pub provider: Option<String>,
+    pub last_error: Option<String>,
+    pub last_attempted_at: Option<DateTime<Utc>>,
+    /// When a worker took the email to send it, while it's still waiting for the result.
+    pub claimed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DeliveryReport {
+    pub newsletter_id: Uuid,
+    pub title: String,
+    pub sent: usize,
+    pub failed: usize,
+    pub pending: usize,
+    /// Handed to the provider, result not yet recorded.
+    pub claimed: usize,
+    /// Claimed for longer than the claim timeout: the worker most likely died mid-send.
+    pub stale: usize,
+    pub skipped: usize,
+    /// Sent emails per provider, for when more than one is configured.
+    pub sent_by_provider: BTreeMap<String, usize>,
+    pub recipients: Vec<RecipientDelivery>,
+}
+
+/// Anyone still in the delivery queue is pending, retries included, or claimed while a worker is
+/// sending to them, and stale once that claim is older than `claim_timeout`. Everyone else is
+/// reported with the last outcome the worker recorded. `None` means there is no such issue.
+#[tracing::instrument(skip(pool))]
+pub async fn get_delivery_report(
+    pool: &PgPool,
+    newsletter_id: Uuid,
+    claim_timeout: Duration,
+) -> Result<Option<DeliveryReport>, sqlx::Error> {
+    let title = sqlx::query!(
+        r#"SELECT title FROM newsletters WHERE newsletter_id = $1"#,
+        newsletter_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    let title = match title {
+        Some(r) => r.title,
+        None => return Ok(None),
+    };
+
+    let recipients = sqlx::query_as!(
+        RecipientDelivery,
+        r#"
+    SELECT
+        COALESCE(d.subscriber_email, q.subscriber_email) AS "subscriber_email!",
+        CASE
+            WHEN q.subscriber_email IS NULL THEN d.outcome
+            WHEN q.claimed_at IS NULL THEN 'pending'
+            WHEN q.claimed_at <= now() - make_interval(secs => $2) THEN 'stale'
+            ELSE 'claimed'
+        END AS "status!",
+        COALESCE(d.n_attempts, 0::smallint) AS "n_attempts!",
+        d.provider_message_id AS "provider_message_id?",
+        d.provider AS "provider?",
+        d.last_error AS "last_error?",
+        d.last_attempted_at AS "last_attempted_at?",
+        q.claimed_at AS "claimed_at?"
+    FROM newsletter_deliveries d
+    FULL OUTER JOIN newsletter_delivery_queue q
+        ON d.newsletter_id = q.newsletter_id AND d.subscriber_email = q.subscriber_email
+    WHERE COALESCE(d.newsletter_id, q.newsletter_id) = $1
+    ORDER BY 1"#,
+        newsletter_id,
+        claim_timeout.as_secs_f64()
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let count = |status: &str| recipients.iter().filter(|r| r.status == status).count();
+    let mut sent_by_provider = BTreeMap::new();
+    for recipient in &recipients {
+        match &recipient.provider {
+            Some(provider) if recipient.status == DeliveryOutcome::Sent.as_str() => {
+                *sent_by_provider.entry(provider.clone()).or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+    Ok(Some(DeliveryReport {
+        newsletter_id,
+        title,
+        sent: count(DeliveryOutcome::Sent.as_str()),
+        failed: count(DeliveryOutcome::Failed.as_str()),
+        pending: count("pending"),
+        claimed: count("claimed"),
+        stale: count("stale"),
+        skipped: count(DeliveryOutcome::Skipped.as_str()),
+        sent_by_provider,
+        recipients,
+    }))
+}
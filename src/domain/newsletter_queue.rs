@@ -1,21 +1,57 @@
+use crate::domain::segments::{self as segments_domain, SegmentFilter};
 use sqlx::{Executor, Postgres, Transaction};
 use uuid::Uuid;
 
+/// The channel the delivery worker listens on for new work.
+pub const WORKER_CHANNEL: &str = "delivery_queue";
+
+/// Wakes the delivery worker. Postgres holds the notification until the transaction commits, so
+/// the worker never looks before the new rows are visible.
+pub async fn notify_worker(trx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+    // pg_notify returns void, which the query! macros can't describe.
+    let query = sqlx::query("SELECT pg_notify($1, '')").bind(WORKER_CHANNEL);
+    trx.execute(query).await?;
+    Ok(())
+}
+
+/// Queues the issue for everyone confirmed on the list it was published to, narrowed to its
+/// segment if it has one. Who that is comes from the `newsletter_recipients` SQL function.
 pub async fn queue_delivery_task(
     trx: &mut Transaction<'_, Postgres>,
     newsletter_id: Uuid,
 ) -> Result<(), sqlx::Error> {
+    let segment_id = sqlx::query_scalar!(
+        r#"SELECT segment_id FROM newsletters WHERE newsletter_id = $1"#,
+        newsletter_id
+    )
+    .fetch_one(&mut **trx)
+    .await?;
+    let filter = match segment_id {
+        // An empty filter would send the issue to the whole list, so a segment that can't be
+        // found stops the send instead.
+        Some(segment_id) => segments_domain::get_segment_filter(&mut **trx, segment_id)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)?,
+        None => SegmentFilter::default(),
+    };
     let query = sqlx::query!(
         r#"
     INSERT INTO newsletter_delivery_queue (
         newsletter_id,
         subscriber_email
     )
-    SELECT $1, email
-        FROM subscriptions
-    WHERE status = 'confirmed'"#,
+    SELECT n.newsletter_id, r.email
+        FROM newsletters n,
+        newsletter_recipients(n.list_slug, $2, $3, $4, $5, $6, $7) r
+    WHERE n.newsletter_id = $1"#,
         newsletter_id,
+        &filter.tags,
+        &filter.any_tags,
+        &filter.exclude_tags,
+        filter.subscribed_within_days(),
+        filter.subscribed_after,
+        filter.subscribed_before
     );
     trx.execute(query).await?;
-    Ok(())
+    notify_worker(trx).await
 }
@@ -1,37 +1,85 @@
+use crate::domain::newsletter_queue::queue_delivery_task;
+use crate::domain::{lists, segments};
+use crate::email_client::EmailOptions;
+use crate::email_template::{Escape, Template, TemplateError};
 use chrono::{DateTime, Utc};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 pub struct NewsLetter {
-    pub newsletter_id: String,
+    pub newsletter_id: Uuid,
     pub title: String,
     pub text_content: String,
     pub html_content: String,
-    pub published_at: DateTime<Utc>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub track_engagement: bool,
+    pub list_slug: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ScheduledNewsletter {
+    pub newsletter_id: Uuid,
+    pub title: String,
+    pub send_at: DateTime<Utc>,
 }
 
 #[tracing::instrument(skip_all)]
 pub async fn get_newsletter(
-    pool: &PgPool,
+    trx: &mut Transaction<'_, Postgres>,
     newsletter_id: Uuid,
 ) -> Result<NewsLetter, anyhow::Error> {
     let issue = sqlx::query_as!(
         NewsLetter,
         r#"
-    SELECT * FROM newsletters WHERE newsletter_id = $1"#,
+    SELECT newsletter_id, title, text_content, html_content, published_at, track_engagement,
+        list_slug
+    FROM newsletters WHERE newsletter_id = $1"#,
         newsletter_id
     )
-    .fetch_one(pool)
+    .fetch_one(&mut **trx)
     .await?;
     Ok(issue)
 }
 
+/// Who an issue goes to: the confirmed members of a list, or only those in one of its segments.
+#[derive(Debug)]
+pub struct Audience<'a> {
+    pub list_slug: &'a str,
+    pub segment_id: Option<Uuid>,
+}
+
+/// Returns a message for the author if the list or the segment doesn't exist.
+#[tracing::instrument(skip(pool))]
+pub async fn check_audience(
+    pool: &PgPool,
+    audience: &Audience<'_>,
+) -> Result<Option<String>, sqlx::Error> {
+    if !lists::list_exists(pool, audience.list_slug).await? {
+        return Ok(Some(format!(
+            "There is no list called '{}'.",
+            audience.list_slug
+        )));
+    }
+    if let Some(segment_id) = audience.segment_id {
+        if segments::get_segment_filter(pool, segment_id)
+            .await?
+            .is_none()
+        {
+            return Ok(Some(format!("There is no segment with id {}.", segment_id)));
+        }
+    }
+    Ok(None)
+}
+
 #[tracing::instrument(skip_all)]
 pub async fn insert_newsletter(
     trx: &mut Transaction<'_, Postgres>,
     title: &str,
     text_content: &str,
     html_content: &str,
+    track_engagement: bool,
+    audience: &Audience<'_>,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_id = Uuid::new_v4();
 
@@ -42,15 +90,432 @@ pub async fn insert_newsletter(
             title,
             text_content,
             html_content,
+            track_engagement,
+            list_slug,
+            segment_id,
+            status,
             published_at
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, $5, $6, $7, 'sending', now())
+    "#,
+        newsletter_id,
+        title,
+        text_content,
+        html_content,
+        track_engagement,
+        audience.list_slug,
+        audience.segment_id
+    );
+    trx.execute(query).await?;
+    Ok(newsletter_id)
+}
+
+/// Stores an issue without queueing it; `enqueue_due_newsletters` picks it up at `send_at`.
+#[tracing::instrument(skip(trx, text_content, html_content))]
+pub async fn schedule_newsletter(
+    trx: &mut Transaction<'_, Postgres>,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    track_engagement: bool,
+    audience: &Audience<'_>,
+    send_at: DateTime<Utc>,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_id = Uuid::new_v4();
+
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO newsletters (
+            newsletter_id,
+            title,
+            text_content,
+            html_content,
+            track_engagement,
+            list_slug,
+            segment_id,
+            status,
+            send_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, 'scheduled', $8)
     "#,
         newsletter_id,
         title,
         text_content,
-        html_content
+        html_content,
+        track_engagement,
+        audience.list_slug,
+        audience.segment_id,
+        send_at
     );
     trx.execute(query).await?;
     Ok(newsletter_id)
 }
+
+#[tracing::instrument(skip_all)]
+pub async fn list_scheduled_newsletters(
+    pool: &PgPool,
+) -> Result<Vec<ScheduledNewsletter>, sqlx::Error> {
+    sqlx::query_as!(
+        ScheduledNewsletter,
+        r#"
+    SELECT newsletter_id, title, send_at AS "send_at!"
+    FROM newsletters
+    WHERE status = 'scheduled'
+    ORDER BY send_at"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns `false` if the issue is not (or no longer) scheduled.
+#[tracing::instrument(skip(pool))]
+pub async fn reschedule_newsletter(
+    pool: &PgPool,
+    newsletter_id: Uuid,
+    send_at: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+    UPDATE newsletters SET send_at = $2
+    WHERE newsletter_id = $1 AND status = 'scheduled'"#,
+        newsletter_id,
+        send_at
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Returns `false` if the issue is not (or no longer) scheduled.
+#[tracing::instrument(skip(pool))]
+pub async fn cancel_newsletter(pool: &PgPool, newsletter_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+    UPDATE newsletters SET status = 'cancelled'
+    WHERE newsletter_id = $1 AND status = 'scheduled'"#,
+        newsletter_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Publishes every scheduled issue whose `send_at` has passed, queueing a delivery task per
+/// confirmed member of its list as `publish_newsletter` does for immediate issues.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_due_newsletters(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let mut trx = pool.begin().await?;
+    let due = sqlx::query!(
+        r#"
+    SELECT newsletter_id FROM newsletters
+    WHERE status = 'scheduled' AND send_at <= now()
+    FOR UPDATE SKIP LOCKED"#
+    )
+    .fetch_all(&mut *trx)
+    .await?;
+    for issue in &due {
+        start_sending(&mut trx, issue.newsletter_id).await?;
+    }
+    trx.commit().await?;
+    Ok(due.len())
+}
+
+async fn start_sending(
+    trx: &mut Transaction<'_, Postgres>,
+    newsletter_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+    UPDATE newsletters SET status = 'sending', published_at = now(), updated_at = now()
+    WHERE newsletter_id = $1"#,
+        newsletter_id
+    );
+    trx.execute(query).await?;
+    queue_delivery_task(trx, newsletter_id).await?;
+    mark_sent_if_drained(trx, newsletter_id).await
+}
+
+/// Flips a `sending` issue to `sent` once nothing is left in its delivery queue. Call it in the
+/// same transaction that removes a task.
+#[tracing::instrument(skip(trx))]
+pub async fn mark_sent_if_drained(
+    trx: &mut Transaction<'_, Postgres>,
+    newsletter_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    // Lock the issue first: the next statement then runs after any concurrent worker finishing
+    // the same issue has committed, so one of us is guaranteed to see an empty queue. NO KEY
+    // UPDATE doesn't conflict with the key-share locks our delivery rows' foreign keys hold.
+    let query = sqlx::query!(
+        r#"SELECT newsletter_id FROM newsletters WHERE newsletter_id = $1 FOR NO KEY UPDATE"#,
+        newsletter_id
+    );
+    trx.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+    UPDATE newsletters SET status = 'sent', updated_at = now()
+    WHERE newsletter_id = $1
+        AND status = 'sending'
+        AND NOT EXISTS (SELECT 1 FROM newsletter_delivery_queue WHERE newsletter_id = $1)"#,
+        newsletter_id
+    );
+    trx.execute(query).await?;
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+pub struct Draft {
+    pub newsletter_id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub track_engagement: bool,
+    pub list_slug: String,
+    pub segment_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Drafts are addressed like any other issue; publishing sends them to the audience stored here.
+#[tracing::instrument(skip(pool, text_content, html_content))]
+pub async fn insert_draft(
+    pool: &PgPool,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    track_engagement: bool,
+    audience: &Audience<'_>,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+    INSERT INTO newsletters (
+        newsletter_id,
+        title,
+        text_content,
+        html_content,
+        track_engagement,
+        list_slug,
+        segment_id,
+        status
+    )
+    VALUES ($1, $2, $3, $4, $5, $6, $7, 'draft')"#,
+        newsletter_id,
+        title,
+        text_content,
+        html_content,
+        track_engagement,
+        audience.list_slug,
+        audience.segment_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(newsletter_id)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn list_drafts(pool: &PgPool) -> Result<Vec<Draft>, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+    SELECT newsletter_id, title, text_content, html_content, track_engagement, list_slug,
+        segment_id, created_at, updated_at
+    FROM newsletters
+    WHERE status = 'draft'
+    ORDER BY updated_at DESC"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_draft(pool: &PgPool, newsletter_id: Uuid) -> Result<Option<Draft>, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+    SELECT newsletter_id, title, text_content, html_content, track_engagement, list_slug,
+        segment_id, created_at, updated_at
+    FROM newsletters
+    WHERE newsletter_id = $1 AND status = 'draft'"#,
+        newsletter_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Returns `false` if there is no draft with this id; issues stop being editable once they
+/// leave the draft state.
+#[tracing::instrument(skip(pool, text_content, html_content))]
+pub async fn update_draft(
+    pool: &PgPool,
+    newsletter_id: Uuid,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    track_engagement: bool,
+    audience: &Audience<'_>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+    UPDATE newsletters
+    SET title = $2, text_content = $3, html_content = $4, track_engagement = $5,
+        list_slug = $6, segment_id = $7, updated_at = now()
+    WHERE newsletter_id = $1 AND status = 'draft'"#,
+        newsletter_id,
+        title,
+        text_content,
+        html_content,
+        track_engagement,
+        audience.list_slug,
+        audience.segment_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn delete_draft(pool: &PgPool, newsletter_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"DELETE FROM newsletters WHERE newsletter_id = $1 AND status = 'draft'"#,
+        newsletter_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+pub enum PublishDraftOutcome {
+    Published,
+    NotFound,
+    InvalidTemplate(TemplateError),
+}
+
+/// Moves a draft to `scheduled` if `send_at` is in the future, otherwise starts sending it right
+/// away. The draft is validated under the same lock, so it can't be edited in between.
+#[tracing::instrument(skip(pool))]
+pub async fn publish_draft(
+    pool: &PgPool,
+    newsletter_id: Uuid,
+    send_at: Option<DateTime<Utc>>,
+) -> Result<PublishDraftOutcome, sqlx::Error> {
+    let mut trx = pool.begin().await?;
+    let draft = sqlx::query!(
+        r#"
+    SELECT text_content, html_content FROM newsletters
+    WHERE newsletter_id = $1 AND status = 'draft'
+    FOR UPDATE"#,
+        newsletter_id
+    )
+    .fetch_optional(&mut *trx)
+    .await?;
+    let draft = match draft {
+        Some(draft) => draft,
+        None => return Ok(PublishDraftOutcome::NotFound),
+    };
+    if let Err(e) = validate_content(&draft.html_content, &draft.text_content) {
+        return Ok(PublishDraftOutcome::InvalidTemplate(e));
+    }
+    match send_at.filter(|send_at| *send_at > Utc::now()) {
+        Some(send_at) => {
+            let query = sqlx::query!(
+                r#"
+        UPDATE newsletters SET status = 'scheduled', send_at = $2, updated_at = now()
+        WHERE newsletter_id = $1"#,
+                newsletter_id,
+                send_at
+            );
+            trx.execute(query).await?;
+        }
+        None => start_sending(&mut trx, newsletter_id).await?,
+    }
+    trx.commit().await?;
+    Ok(PublishDraftOutcome::Published)
+}
+
+/// Placeholders an issue body may use.
+pub const NEWSLETTER_FIELDS: &[&str] = &[
+    "subscriber.name",
+    "subscriber.email",
+    "unsubscribe_url",
+    "preferences_url",
+    "issue.title",
+];
+
+pub fn validate_content(html_content: &str, text_content: &str) -> Result<(), TemplateError> {
+    Template::parse(html_content, NEWSLETTER_FIELDS)?;
+    Template::parse(text_content, NEWSLETTER_FIELDS)?;
+    Ok(())
+}
+
+pub struct NewsletterRecipient<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+}
+
+/// An issue as it leaves for one recipient: content with the unsubscribe footer appended, plus
+/// the tagging and one-click unsubscribe headers.
+pub struct RenderedNewsletter {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    pub options: EmailOptions,
+}
+
+/// Bodies that don't place `{{ unsubscribe_url }}` or `{{ preferences_url }}` themselves get the
+/// standard footer links for them.
+pub fn render_newsletter(
+    newsletter_id: Uuid,
+    title: &str,
+    html_content: &str,
+    text_content: &str,
+    recipient: &NewsletterRecipient,
+    unsubscribe_link: &str,
+    preferences_link: &str,
+) -> Result<RenderedNewsletter, TemplateError> {
+    let html = Template::parse(html_content, NEWSLETTER_FIELDS)?;
+    let text = Template::parse(text_content, NEWSLETTER_FIELDS)?;
+    let values = HashMap::from([
+        ("subscriber.name", recipient.name),
+        ("subscriber.email", recipient.email),
+        ("unsubscribe_url", unsubscribe_link),
+        ("preferences_url", preferences_link),
+        ("issue.title", title),
+    ]);
+
+    let mut html_body = html.render(&values, Escape::Html);
+    if !html.uses("unsubscribe_url") {
+        html_body.push_str(&format!(
+            "<p><a href=\"{}\">Unsubscribe</a></p>",
+            unsubscribe_link
+        ));
+    }
+    if !html.uses("preferences_url") {
+        html_body.push_str(&format!(
+            "<p><a href=\"{}\">Manage your preferences</a></p>",
+            preferences_link
+        ));
+    }
+    let mut text_body = text.render(&values, Escape::Text);
+    let mut footer = Vec::new();
+    if !text.uses("unsubscribe_url") {
+        footer.push(format!("Unsubscribe: {}", unsubscribe_link));
+    }
+    if !text.uses("preferences_url") {
+        footer.push(format!("Manage your preferences: {}", preferences_link));
+    }
+    if !footer.is_empty() {
+        text_body.push_str("\n\n");
+        text_body.push_str(&footer.join("\n"));
+    }
+
+    Ok(RenderedNewsletter {
+        subject: title.to_string(),
+        html_body,
+        text_body,
+        options: EmailOptions::default()
+            .tag("newsletter")
+            .metadata("newsletter_id", newsletter_id.to_string())
+            .header("List-Unsubscribe", format!("<{}>", unsubscribe_link))
+            .header("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+    })
+}
@@ -0,0 +1,275 @@
+use crate::cloneable_auth_token::SecretAuthToken;
+use crate::domain::{lists, SubscriberName};
+use crate::signed_token;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool};
+use std::str::FromStr;
+use uuid::Uuid;
+
+const PREFERENCES_PURPOSE: &str = "preferences";
+
+/// The longest pause the preference center offers.
+pub const MAX_PAUSE_WEEKS: u16 = 52;
+
+/// The `pause_weeks` value that leaves a running pause alone.
+pub const KEEP_PAUSE: &str = "keep";
+
+/// Like unsubscribe links, preference links go out with every issue and don't expire. Only
+/// someone who can read the subscriber's mail has one.
+pub fn preferences_token(secret: &SecretAuthToken, subscriber_id: Uuid) -> String {
+    signed_token::sign(secret, PREFERENCES_PURPOSE, &subscriber_id.to_string())
+}
+
+pub fn preferences_link(base_url: &str, secret: &SecretAuthToken, subscriber_id: Uuid) -> String {
+    format!(
+        "{}/subscriptions/preferences?token={}",
+        base_url,
+        preferences_token(secret, subscriber_id)
+    )
+}
+
+pub fn parse_preferences_token(secret: &SecretAuthToken, token: &str) -> Option<Uuid> {
+    signed_token::verify(secret, PREFERENCES_PURPOSE, token).and_then(|id| Uuid::from_str(&id).ok())
+}
+
+pub struct ListChoice {
+    pub slug: String,
+    pub name: String,
+    pub subscribed: bool,
+}
+
+pub struct Preferences {
+    pub name: String,
+    pub email: String,
+    pub paused_until: Option<DateTime<Utc>>,
+    /// Every list, ticked where the subscriber is confirmed.
+    pub lists: Vec<ListChoice>,
+}
+
+pub struct PreferencesUpdate {
+    pub name: SubscriberName,
+    pub list_slugs: Vec<String>,
+    /// Zero resumes deliveries, `None` leaves any current pause as it is.
+    pub pause_weeks: Option<u16>,
+}
+
+impl PreferencesUpdate {
+    /// The form has a checkbox per list, so `list` comes once per ticked box. `pause_weeks` is
+    /// `keep` while a pause is running and the subscriber leaves it alone.
+    pub fn from_form(fields: Vec<(String, String)>) -> Result<Self, String> {
+        let mut name = None;
+        let mut list_slugs = Vec::new();
+        let mut pause_weeks = None;
+        for (key, value) in fields {
+            match key.as_str() {
+                "name" => name = Some(value),
+                "list" => list_slugs.push(value),
+                "pause_weeks" if value == KEEP_PAUSE => pause_weeks = None,
+                "pause_weeks" => {
+                    pause_weeks = value
+                        .parse()
+                        .ok()
+                        .filter(|weeks| *weeks <= MAX_PAUSE_WEEKS)
+                        .map(Some)
+                        .ok_or_else(|| {
+                            format!(
+                                "Deliveries can be paused for 0 to {} weeks.",
+                                MAX_PAUSE_WEEKS
+                            )
+                        })?
+                }
+                _ => {}
+            }
+        }
+        let name = name.ok_or_else(|| "Your name is missing.".to_string())?;
+        Ok(PreferencesUpdate {
+            name: SubscriberName::parse(name)?,
+            list_slugs,
+            pause_weeks,
+        })
+    }
+}
+
+/// `None` means there is no such subscriber.
+#[tracing::instrument(skip(pool))]
+pub async fn get_preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Preferences>, sqlx::Error> {
+    let subscriber = sqlx::query!(
+        r#"SELECT name, email, paused_until FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Ok(None),
+    };
+    let lists = sqlx::query_as!(
+        ListChoice,
+        r#"
+    SELECT l.slug, l.name, COALESCE(m.status = 'confirmed', false) AS "subscribed!"
+    FROM lists l
+    LEFT JOIN list_memberships m ON m.list_slug = l.slug AND m.subscriber_id = $1
+    ORDER BY l.created_at, l.slug"#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Some(Preferences {
+        name: subscriber.name,
+        email: subscriber.email,
+        paused_until: subscriber.paused_until,
+        lists,
+    }))
+}
+
+/// Applies the preference form. Ticked lists are joined straight away: following the link
+/// already proves the subscriber reads this mailbox, so there is nothing left to confirm. They
+/// leave any unticked list they were confirmed on. Returns `false` if there is no such
+/// subscriber.
+#[tracing::instrument(skip(pool, update))]
+pub async fn update_preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    update: &PreferencesUpdate,
+) -> Result<bool, sqlx::Error> {
+    let mut trx = pool.begin().await?;
+    let query = sqlx::query!(
+        r#"
+    UPDATE subscriptions SET
+        name = $2,
+        paused_until = CASE
+            WHEN $3::int IS NULL THEN paused_until
+            WHEN $3 = 0 THEN NULL
+            ELSE now() + make_interval(weeks => $3)
+        END
+    WHERE id = $1"#,
+        subscriber_id,
+        update.name.as_ref(),
+        update.pause_weeks.map(i32::from)
+    );
+    if trx.execute(query).await?.rows_affected() == 0 {
+        return Ok(false);
+    }
+    let query = sqlx::query!(
+        r#"
+    INSERT INTO list_memberships (list_slug, subscriber_id, status, subscribed_at)
+    SELECT slug, $1, 'confirmed', now() FROM lists WHERE slug = ANY($2)
+    ON CONFLICT (list_slug, subscriber_id) DO UPDATE
+    SET status = 'confirmed',
+        subscribed_at = CASE
+            WHEN list_memberships.status = 'unsubscribed' THEN EXCLUDED.subscribed_at
+            ELSE list_memberships.subscribed_at
+        END
+    WHERE list_memberships.status <> 'confirmed'"#,
+        subscriber_id,
+        &update.list_slugs
+    );
+    trx.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+    UPDATE list_memberships SET status = 'unsubscribed'
+    WHERE subscriber_id = $1 AND status = 'confirmed' AND NOT (list_slug = ANY($2))"#,
+        subscriber_id,
+        &update.list_slugs
+    );
+    trx.execute(query).await?;
+    lists::refresh_subscriber_status(&mut trx, subscriber_id).await?;
+    trx.commit().await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_preferences_token, preferences_link, preferences_token, PreferencesUpdate};
+    use crate::cloneable_auth_token::AuthToken;
+    use claims::{assert_none, assert_ok, assert_some_eq};
+    use uuid::Uuid;
+
+    fn form(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn a_preferences_token_resolves_to_its_subscriber() {
+        let secret = AuthToken::new("secret".to_string());
+        let subscriber_id = Uuid::new_v4();
+        let token = preferences_token(&secret, subscriber_id);
+        assert_some_eq!(parse_preferences_token(&secret, &token), subscriber_id);
+    }
+
+    #[test]
+    fn an_unsubscribe_token_is_not_a_preferences_token() {
+        let secret = AuthToken::new("secret".to_string());
+        let token =
+            crate::domain::unsubscribe::unsubscribe_token(&secret, Uuid::new_v4(), "newsletter");
+        assert_none!(parse_preferences_token(&secret, &token));
+    }
+
+    #[test]
+    fn preference_links_point_at_the_preference_center() {
+        let secret = AuthToken::new("secret".to_string());
+        let subscriber_id = Uuid::new_v4();
+        assert_eq!(
+            preferences_link("http://127.0.0.1", &secret, subscriber_id),
+            format!(
+                "http://127.0.0.1/subscriptions/preferences?token={}",
+                preferences_token(&secret, subscriber_id)
+            )
+        );
+    }
+
+    #[test]
+    fn every_ticked_list_is_kept() {
+        let update = assert_ok!(PreferencesUpdate::from_form(form(&[
+            ("name", "le guin"),
+            ("list", "newsletter"),
+            ("list", "release-notes"),
+            ("pause_weeks", "4"),
+        ])));
+        assert_eq!(update.list_slugs, ["newsletter", "release-notes"]);
+        assert_eq!(update.pause_weeks, Some(4));
+    }
+
+    #[test]
+    fn no_ticked_lists_and_no_pause_is_fine() {
+        let update = assert_ok!(PreferencesUpdate::from_form(form(&[("name", "le guin")])));
+        assert!(update.list_slugs.is_empty());
+        assert_eq!(update.pause_weeks, None);
+    }
+
+    #[test]
+    fn a_running_pause_can_be_kept_or_cancelled() {
+        let keep = assert_ok!(PreferencesUpdate::from_form(form(&[
+            ("name", "le guin"),
+            ("pause_weeks", "keep"),
+        ])));
+        assert_eq!(keep.pause_weeks, None);
+        let resume = assert_ok!(PreferencesUpdate::from_form(form(&[
+            ("name", "le guin"),
+            ("pause_weeks", "0"),
+        ])));
+        assert_eq!(resume.pause_weeks, Some(0));
+    }
+
+    #[test]
+    fn names_and_pauses_are_validated() {
+        for pairs in [
+            &[("list", "newsletter")][..],
+            &[("name", "")],
+            &[("name", "le guin"), ("pause_weeks", "53")],
+            &[("name", "le guin"), ("pause_weeks", "forever")],
+        ] {
+            assert!(
+                PreferencesUpdate::from_form(form(pairs)).is_err(),
+                "{:?} should be rejected",
+                pairs
+            );
+        }
+    }
+}
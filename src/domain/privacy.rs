@@ -0,0 +1,258 @@
+use crate::cloneable_auth_token::SecretAuthToken;
+use crate::domain::email_events::{email_hash, is_suppressed};
+use crate::domain::subscribers::{get_subscriber_history, SubscriberHistory};
+use crate::signed_token;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::str::FromStr;
+use std::time::Duration;
+use uuid::Uuid;
+
+const PRIVACY_PURPOSE: &str = "privacy";
+
+/// Unlike unsubscribe links, privacy links hand out personal data, so they expire.
+pub fn privacy_token(
+    secret: &SecretAuthToken,
+    subscriber_id: Uuid,
+    expires_at: DateTime<Utc>,
+) -> String {
+    signed_token::sign(
+        secret,
+        PRIVACY_PURPOSE,
+        &format!("{}:{}", subscriber_id, expires_at.timestamp()),
+    )
+}
+
+pub fn parse_privacy_token(
+    secret: &SecretAuthToken,
+    token: &str,
+    now: DateTime<Utc>,
+) -> Option<Uuid> {
+    let payload = signed_token::verify(secret, PRIVACY_PURPOSE, token)?;
+    let (subscriber_id, expires_at) = payload.split_once(':')?;
+    if now.timestamp() >= expires_at.parse::<i64>().ok()? {
+        return None;
+    }
+    Uuid::from_str(subscriber_id).ok()
+}
+
+/// At most one privacy link goes out per address in this long.
+pub const PRIVACY_LINK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Records that a privacy link is going out to `email` and returns who it is for. `None` when
+/// there is no such subscriber, the address must not be mailed, or it was sent a link less than
+/// `PRIVACY_LINK_INTERVAL` ago.
+#[tracing::instrument(skip(trx, secret, email))]
+pub async fn record_privacy_link(
+    trx: &mut Transaction<'_, Postgres>,
+    secret: &SecretAuthToken,
+    email: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    if is_suppressed(&mut **trx, secret, email).await? {
+        return Ok(None);
+    }
+    sqlx::query_scalar!(
+        r#"
+    UPDATE subscriptions SET privacy_link_sent_at = now()
+    WHERE email = $1
+        AND status IS DISTINCT FROM 'bounced'
+        AND status IS DISTINCT FROM 'complained'
+        AND (
+            privacy_link_sent_at IS NULL
+            OR privacy_link_sent_at <= now() - make_interval(secs => $2)
+        )
+    RETURNING id"#,
+        email,
+        PRIVACY_LINK_INTERVAL.as_secs_f64()
+    )
+    .fetch_optional(&mut **trx)
+    .await
+}
+
+/// Confirmation tokens are listed without their value: that is a credential, not data about
+/// the subscriber.
+#[derive(serde::Serialize)]
+pub struct ConfirmationTokenRecord {
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct QueuedDelivery {
+    pub newsletter_id: Uuid,
+    pub title: String,
+    pub execute_after: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberData {
+    #[serde(flatten)]
+    pub history: SubscriberHistory,
+    pub confirmation_tokens: Vec<ConfirmationTokenRecord>,
+    pub queued_deliveries: Vec<QueuedDelivery>,
+    pub exported_at: DateTime<Utc>,
+}
+
+/// Everything held about one subscriber, for them to download. `None` means there is no such
+/// subscriber.
+#[tracing::instrument(skip(pool))]
+pub async fn get_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberData>, sqlx::Error> {
+    let history = match get_subscriber_history(pool, subscriber_id).await? {
+        Some(history) => history,
+        None => return Ok(None),
+    };
+    let confirmation_tokens = sqlx::query_as!(
+        ConfirmationTokenRecord,
+        r#"
+    SELECT created_at, expires_at, consumed_at
+    FROM subscription_tokens
+    WHERE subscriber_id = $1
+    ORDER BY created_at DESC"#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let queued_deliveries = sqlx::query_as!(
+        QueuedDelivery,
+        r#"
+    SELECT q.newsletter_id, n.title, q.execute_after
+    FROM newsletter_delivery_queue q
+    JOIN newsletters n ON n.newsletter_id = q.newsletter_id
+    WHERE q.subscriber_email = $1
+    ORDER BY q.execute_after"#,
+        history.subscriber.email
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Some(SubscriberData {
+        history,
+        confirmation_tokens,
+        queued_deliveries,
+        exported_at: Utc::now(),
+    }))
+}
+
+/// Deletes the subscriber, anything still waiting to be sent to them and their sending history.
+/// All that's left is a keyed hash of the address on the suppression list, so an old list can't
+/// bring them back. Returns `false` if there is no such subscriber.
+#[tracing::instrument(skip(pool, secret))]
+pub async fn erase_subscriber(
+    pool: &PgPool,
+    secret: &SecretAuthToken,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let mut trx = pool.begin().await?;
+    let email = sqlx::query_scalar!(
+        r#"SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *trx)
+    .await?;
+    let email = match email {
+        Some(email) => email,
+        None => return Ok(false),
+    };
+    let hash = email_hash(secret, &email);
+
+    let query = sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    );
+    trx.execute(query).await?;
+    let query = sqlx::query!(
+        r#"DELETE FROM newsletter_delivery_queue WHERE subscriber_email = $1"#,
+        email
+    );
+    trx.execute(query).await?;
+    // Dead letters could be requeued, so they count as still waiting.
+    let query = sqlx::query!(
+        r#"DELETE FROM newsletter_delivery_dead_letters WHERE subscriber_email = $1"#,
+        email
+    );
+    trx.execute(query).await?;
+    let query = sqlx::query!(r#"DELETE FROM email_outbox WHERE recipient = $1"#, email);
+    trx.execute(query).await?;
+    let query = sqlx::query!(
+        r#"DELETE FROM newsletter_deliveries WHERE subscriber_email = $1"#,
+        email
+    );
+    trx.execute(query).await?;
+    let query = sqlx::query!(
+        r#"DELETE FROM email_events WHERE lower(email) = lower($1)"#,
+        email
+    );
+    trx.execute(query).await?;
+    let query = sqlx::query!(
+        r#"DELETE FROM suppressed_emails WHERE email = lower($1)"#,
+        email
+    );
+    trx.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+    INSERT INTO suppressed_emails (email, reason, suppressed_at)
+    VALUES ($1, 'erased', now())
+    ON CONFLICT (email) DO NOTHING"#,
+        hash
+    );
+    trx.execute(query).await?;
+    // Engagement rows go with the subscriber, on cascade.
+    let query = sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id);
+    trx.execute(query).await?;
+    trx.commit().await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_privacy_token, privacy_token};
+    use crate::cloneable_auth_token::AuthToken;
+    use crate::domain::email_events::email_hash;
+    use chrono::{Duration, Utc};
+    use claims::{assert_none, assert_some_eq};
+    use uuid::Uuid;
+
+    #[test]
+    fn a_privacy_token_resolves_to_its_subscriber_until_it_expires() {
+        let secret = AuthToken::new("secret".to_string());
+        let subscriber_id = Uuid::new_v4();
+        let now = Utc::now();
+        let token = privacy_token(&secret, subscriber_id, now + Duration::hours(1));
+
+        assert_some_eq!(parse_privacy_token(&secret, &token, now), subscriber_id);
+        assert_none!(parse_privacy_token(
+            &secret,
+            &token,
+            now + Duration::hours(2)
+        ));
+    }
+
+    #[test]
+    fn an_unsubscribe_token_is_not_a_privacy_token() {
+        let secret = AuthToken::new("secret".to_string());
+        let token =
+            crate::domain::unsubscribe::unsubscribe_token(&secret, Uuid::new_v4(), "newsletter");
+        assert_none!(parse_privacy_token(&secret, &token, Utc::now()));
+    }
+
+    #[test]
+    fn email_hashes_ignore_case() {
+        let secret = AuthToken::new("secret".to_string());
+        assert_eq!(
+            email_hash(&secret, "Ursula@Example.com"),
+            email_hash(&secret, "ursula@example.com")
+        );
+        assert_eq!(email_hash(&secret, "ursula@example.com").len(), 64);
+    }
+
+    #[test]
+    fn email_hashes_depend_on_the_secret() {
+        assert_ne!(
+            email_hash(&AuthToken::new("secret".to_string()), "ursula@example.com"),
+            email_hash(&AuthToken::new("other".to_string()), "ursula@example.com")
+        );
+    }
+}
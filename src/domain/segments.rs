@@ -0,0 +1,175 @@
+use crate::domain::tags::parse_tag;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// Who a segment picks out of a list. Every condition that is set has to hold, so
+/// "tag = beta AND subscribed in the last 30 days" is
+/// `{"tags": ["beta"], "subscribed_within_days": 30}`. An empty filter matches everyone.
+#[derive(serde::Deserialize, serde::Serialize, Default, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SegmentFilter {
+    /// Tags a subscriber must all have.
+    pub tags: Vec<String>,
+    /// Tags a subscriber must have at least one of.
+    pub any_tags: Vec<String>,
+    /// Tags that rule a subscriber out.
+    pub exclude_tags: Vec<String>,
+    pub subscribed_within_days: Option<u16>,
+    pub subscribed_after: Option<DateTime<Utc>>,
+    pub subscribed_before: Option<DateTime<Utc>>,
+}
+
+impl SegmentFilter {
+    /// Normalises the tags the way they're stored and rejects conditions that can't match.
+    pub fn parse(self) -> Result<Self, String> {
+        let parse_tags = |tags: Vec<String>| -> Result<Vec<String>, String> {
+            let mut tags = tags
+                .iter()
+                .map(|tag| parse_tag(tag))
+                .collect::<Result<Vec<_>, _>>()?;
+            tags.sort();
+            tags.dedup();
+            Ok(tags)
+        };
+        if self.subscribed_within_days == Some(0) {
+            return Err("`subscribed_within_days` must be at least 1.".to_string());
+        }
+        Ok(SegmentFilter {
+            tags: parse_tags(self.tags)?,
+            any_tags: parse_tags(self.any_tags)?,
+            exclude_tags: parse_tags(self.exclude_tags)?,
+            ..self
+        })
+    }
+
+    pub fn subscribed_within_days(&self) -> Option<i32> {
+        self.subscribed_within_days.map(i32::from)
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct Segment {
+    pub segment_id: Uuid,
+    pub name: String,
+    pub filter: Json<SegmentFilter>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn list_segments(pool: &PgPool) -> Result<Vec<Segment>, sqlx::Error> {
+    sqlx::query_as!(
+        Segment,
+        r#"
+    SELECT segment_id, name, filter AS "filter: Json<SegmentFilter>", created_at
+    FROM segments
+    ORDER BY name"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns `None` if a segment with this name already exists.
+#[tracing::instrument(skip(pool))]
+pub async fn create_segment(
+    pool: &PgPool,
+    name: &str,
+    filter: &SegmentFilter,
+) -> Result<Option<Segment>, sqlx::Error> {
+    sqlx::query_as!(
+        Segment,
+        r#"
+    INSERT INTO segments (segment_id, name, filter, created_at)
+    VALUES ($1, $2, $3, now())
+    ON CONFLICT (name) DO NOTHING
+    RETURNING segment_id, name, filter AS "filter: Json<SegmentFilter>", created_at"#,
+        Uuid::new_v4(),
+        name,
+        Json(filter) as _
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn get_segment_filter<'e>(
+    executor: impl PgExecutor<'e>,
+    segment_id: Uuid,
+) -> Result<Option<SegmentFilter>, sqlx::Error> {
+    let filter = sqlx::query_scalar!(
+        r#"SELECT filter AS "filter: Json<SegmentFilter>" FROM segments WHERE segment_id = $1"#,
+        segment_id
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(filter.map(|filter| filter.0))
+}
+
+/// How many subscribers an issue sent to `list_slug` and narrowed by `filter` would go to right
+/// now. `queue_delivery_task` picks them with the same `newsletter_recipients` function.
+#[tracing::instrument(skip(pool))]
+pub async fn count_recipients(
+    pool: &PgPool,
+    list_slug: &str,
+    filter: &SegmentFilter,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+    SELECT count(*) AS "count!"
+    FROM newsletter_recipients($1, $2, $3, $4, $5, $6, $7)"#,
+        list_slug,
+        &filter.tags,
+        &filter.any_tags,
+        &filter.exclude_tags,
+        filter.subscribed_within_days(),
+        filter.subscribed_after,
+        filter.subscribed_before
+    )
+    .fetch_one(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SegmentFilter;
+    use claims::{assert_err, assert_ok_eq};
+
+    fn filter(json: serde_json::Value) -> Result<SegmentFilter, String> {
+        serde_json::from_value::<SegmentFilter>(json)
+            .map_err(|e| e.to_string())?
+            .parse()
+    }
+
+    #[test]
+    fn an_empty_filter_matches_everyone() {
+        assert_ok_eq!(filter(serde_json::json!({})), SegmentFilter::default());
+    }
+
+    #[test]
+    fn tags_are_normalised() {
+        assert_ok_eq!(
+            filter(serde_json::json!({
+                "tags": ["Beta", "beta", " alpha"],
+                "subscribed_within_days": 30,
+            })),
+            SegmentFilter {
+                tags: vec!["alpha".to_string(), "beta".to_string()],
+                subscribed_within_days: Some(30),
+                ..SegmentFilter::default()
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_conditions_and_bad_values_are_rejected() {
+        for json in [
+            serde_json::json!({ "tag": "beta" }),
+            serde_json::json!({ "tags": ["beta testers"] }),
+            serde_json::json!({ "exclude_tags": [""] }),
+            serde_json::json!({ "subscribed_within_days": 0 }),
+            serde_json::json!({ "subscribed_within_days": -1 }),
+        ] {
+            assert_err!(filter(json.clone()), "{} should be rejected", json);
+        }
+    }
+}
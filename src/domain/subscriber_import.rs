@@ -0,0 +1,418 @@
+use crate::cloneable_auth_token::SecretAuthToken;
+use crate::domain::{email_events, lists, NewSubscriber, SubscriberEmail, SubscriberName};
+use chrono::{DateTime, Utc};
+use csv_core::ReadRecordResult;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+/// Turns CSV arriving in arbitrary chunks into records, so uploads never have to be held in
+/// memory whole.
+pub struct CsvRecords {
+    reader: csv_core::Reader,
+    output: Vec<u8>,
+    ends: Vec<usize>,
+    output_len: usize,
+    ends_len: usize,
+}
+
+impl Default for CsvRecords {
+    fn default() -> Self {
+        Self {
+            reader: csv_core::Reader::new(),
+            output: vec![0; 1024],
+            ends: vec![0; 16],
+            output_len: 0,
+            ends_len: 0,
+        }
+    }
+}
+
+impl CsvRecords {
+    /// Parses `input`, returning the records it completed. Whatever is left of a record is kept
+    /// for the next chunk.
+    pub fn feed(&mut self, input: &[u8]) -> Vec<Vec<Vec<u8>>> {
+        let mut records = Vec::new();
+        let mut input = input;
+        loop {
+            let (result, n_in, n_out, n_ends) = self.reader.read_record(
+                input,
+                &mut self.output[self.output_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[n_in..];
+            self.output_len += n_out;
+            self.ends_len += n_ends;
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return records,
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => records.push(self.take_record()),
+            }
+        }
+    }
+
+    /// Flushes the last record when the file doesn't end with a newline.
+    pub fn finish(&mut self) -> Vec<Vec<Vec<u8>>> {
+        self.feed(&[])
+    }
+
+    fn take_record(&mut self) -> Vec<Vec<u8>> {
+        let mut start = 0;
+        let fields = self.ends[..self.ends_len]
+            .iter()
+            .map(|&end| {
+                let field = self.output[start..end].to_vec();
+                start = end;
+                field
+            })
+            .collect();
+        self.output_len = 0;
+        self.ends_len = 0;
+        fields
+    }
+}
+
+/// Where each known column sits in the file. Any other column becomes a custom field.
+#[derive(Debug)]
+pub struct ImportColumns {
+    email: usize,
+    name: usize,
+    status: Option<usize>,
+    subscribed_at: Option<usize>,
+    custom: Vec<(usize, String)>,
+}
+
+impl ImportColumns {
+    pub fn from_header(header: &[String]) -> Result<Self, String> {
+        let position = |column: &str| {
+            header
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(column))
+        };
+        let email = position("email").ok_or("The header has no 'email' column.")?;
+        let name = position("name").ok_or("The header has no 'name' column.")?;
+        let status = position("status");
+        let subscribed_at = position("subscribed_at");
+        let known = [Some(email), Some(name), status, subscribed_at];
+        let custom = header
+            .iter()
+            .enumerate()
+            .filter(|(i, h)| !known.contains(&Some(*i)) && !h.trim().is_empty())
+            .map(|(i, h)| (i, h.trim().to_string()))
+            .collect();
+        Ok(Self {
+            email,
+            name,
+            status,
+            subscribed_at,
+            custom,
+        })
+    }
+
+    /// How many fields a record needs to cover every column we read.
+    fn n_fields(&self) -> usize {
+        [
+            Some(self.email),
+            Some(self.name),
+            self.status,
+            self.subscribed_at,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.custom.iter().map(|(i, _)| *i))
+        .max()
+        .unwrap_or(0)
+            + 1
+    }
+
+    /// Validates one record the way the subscription form would.
+    pub fn parse(&self, record: &[String]) -> Result<ImportRow, String> {
+        if record.len() < self.n_fields() {
+            return Err(format!(
+                "Expected {} fields, found {}.",
+                self.n_fields(),
+                record.len()
+            ));
+        }
+        let subscriber = NewSubscriber {
+            email: SubscriberEmail::new(record[self.email].trim().to_string())?,
+            name: SubscriberName::parse(record[self.name].trim().to_string())?,
+        };
+        let status = match self.status.map(|i| record[i].trim()) {
+            None | Some("") => None,
+            Some(status) => Some(ImportStatus::parse(status)?),
+        };
+        let subscribed_at = match self.subscribed_at.map(|i| record[i].trim()) {
+            None | Some("") => None,
+            Some(value) => Some(
+                DateTime::parse_from_rfc3339(value)
+                    .map_err(|_| format!("'{}' is not an RFC 3339 timestamp.", value))?
+                    .with_timezone(&Utc),
+            ),
+        };
+        let attributes = self
+            .custom
+            .iter()
+            .filter(|(i, _)| !record[*i].is_empty())
+            .map(|(i, name)| (name.clone(), record[*i].clone().into()))
+            .collect();
+        Ok(ImportRow {
+            subscriber,
+            status,
+            subscribed_at,
+            attributes,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl ImportStatus {
+    /// Exports carry bounces and complaints too. They come back as opt-outs, and the suppression
+    /// list decides whether the address can be imported at all.
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "pending_confirmation" => Ok(ImportStatus::PendingConfirmation),
+            "confirmed" => Ok(ImportStatus::Confirmed),
+            "unsubscribed" | "bounced" | "complained" => Ok(ImportStatus::Unsubscribed),
+            other => Err(format!(
+                "Unknown status '{}', expected pending_confirmation, confirmed or unsubscribed.",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportStatus::PendingConfirmation => "pending_confirmation",
+            ImportStatus::Confirmed => "confirmed",
+            ImportStatus::Unsubscribed => "unsubscribed",
+        }
+    }
+}
+
+pub struct ImportRow {
+    pub subscriber: NewSubscriber,
+    pub status: Option<ImportStatus>,
+    pub subscribed_at: Option<DateTime<Utc>>,
+    pub attributes: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ImportRow {
+    /// Contacts only skip double opt-in when the admin vouches for their consent, and never
+    /// if the file itself says they haven't confirmed yet. Opt-outs are always kept.
+    pub fn status(&self, skip_confirmation: bool) -> ImportStatus {
+        match self.status {
+            Some(ImportStatus::Unsubscribed) => ImportStatus::Unsubscribed,
+            Some(ImportStatus::PendingConfirmation) => ImportStatus::PendingConfirmation,
+            Some(ImportStatus::Confirmed) | None if skip_confirmation => ImportStatus::Confirmed,
+            Some(ImportStatus::Confirmed) | None => ImportStatus::PendingConfirmation,
+        }
+    }
+}
+
+pub enum ImportOutcome {
+    Imported(Uuid),
+    AlreadySubscribed,
+    Suppressed,
+}
+
+/// Adds one imported contact to `list_slug`. Contacts already on that list are left untouched,
+/// so running the same import twice is harmless; known contacts new to the list just join it.
+#[tracing::instrument(skip_all, fields(subscriber_email = %row.subscriber.email.as_ref()))]
+pub async fn import_subscriber(
+    trx: &mut Transaction<'_, Postgres>,
+    hmac_secret: &SecretAuthToken,
+    row: &ImportRow,
+    status: ImportStatus,
+    list_slug: &str,
+) -> Result<ImportOutcome, sqlx::Error> {
+    if email_events::is_suppressed(&mut **trx, hmac_secret, row.subscriber.email.as_ref()).await? {
+        return Ok(ImportOutcome::Suppressed);
+    }
+    let inserted = sqlx::query_scalar!(
+        r#"
+    INSERT INTO subscriptions (id, email, name, subscribed_at, status, attributes)
+    VALUES ($1, $2, $3, COALESCE($4, now()), $5, $6)
+    ON CONFLICT (email) DO NOTHING
+    RETURNING id"#,
+        Uuid::new_v4(),
+        row.subscriber.email.as_ref(),
+        row.subscriber.name.as_ref(),
+        row.subscribed_at,
+        status.as_str(),
+        serde_json::Value::Object(row.attributes.clone())
+    )
+    .fetch_optional(&mut **trx)
+    .await?;
+    let subscriber_id = match inserted {
+        Some(id) => id,
+        None => {
+            sqlx::query_scalar!(
+                r#"SELECT id FROM subscriptions WHERE email = $1"#,
+                row.subscriber.email.as_ref()
+            )
+            .fetch_one(&mut **trx)
+            .await?
+        }
+    };
+    let joined = sqlx::query_scalar!(
+        r#"
+    INSERT INTO list_memberships (list_slug, subscriber_id, status, subscribed_at)
+    VALUES ($1, $2, $3, COALESCE($4, now()))
+    ON CONFLICT (list_slug, subscriber_id) DO NOTHING
+    RETURNING subscriber_id"#,
+        list_slug,
+        subscriber_id,
+        status.as_str(),
+        row.subscribed_at
+    )
+    .fetch_optional(&mut **trx)
+    .await?;
+    if joined.is_none() {
+        return Ok(ImportOutcome::AlreadySubscribed);
+    }
+    lists::refresh_subscriber_status(trx, subscriber_id).await?;
+    Ok(ImportOutcome::Imported(subscriber_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CsvRecords, ImportColumns, ImportStatus};
+    use claims::{assert_err, assert_ok};
+
+    fn strings(fields: &[&str]) -> Vec<String> {
+        fields.iter().map(|f| f.to_string()).collect()
+    }
+
+    fn text(records: Vec<Vec<Vec<u8>>>) -> Vec<Vec<String>> {
+        records
+            .into_iter()
+            .map(|r| {
+                r.into_iter()
+                    .map(|f| String::from_utf8(f).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn records_split_across_chunks_are_reassembled() {
+        let mut parser = CsvRecords::default();
+        let mut records = text(parser.feed(b"email,name\nursula@exam"));
+        records.extend(text(parser.feed(b"ple.com,\"Le Guin, Ursula\"\n")));
+
+        assert_eq!(
+            records,
+            vec![
+                strings(&["email", "name"]),
+                strings(&["ursula@example.com", "Le Guin, Ursula"])
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_newlines_stay_inside_their_field() {
+        let mut parser = CsvRecords::default();
+        let records = text(parser.feed(b"\"line one\nline two\",x\n"));
+        assert_eq!(records, vec![strings(&["line one\nline two", "x"])]);
+    }
+
+    #[test]
+    fn the_last_record_is_flushed_without_a_trailing_newline() {
+        let mut parser = CsvRecords::default();
+        assert!(parser.feed(b"a,b").is_empty());
+        assert_eq!(text(parser.finish()), vec![strings(&["a", "b"])]);
+    }
+
+    #[test]
+    fn long_records_grow_the_buffers() {
+        let mut parser = CsvRecords::default();
+        let long = "x".repeat(5000);
+        let line = format!("{}\n", vec![long.as_str(); 40].join(","));
+        let records = parser.feed(line.as_bytes());
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].len(), 40);
+        assert_eq!(records[0][39].len(), 5000);
+    }
+
+    #[test]
+    fn the_header_must_name_email_and_name() {
+        assert_err!(ImportColumns::from_header(&strings(&["email", "status"])));
+        assert_err!(ImportColumns::from_header(&strings(&["name"])));
+        assert_ok!(ImportColumns::from_header(&strings(&[" Name ", "EMAIL"])));
+    }
+
+    #[test]
+    fn unknown_columns_become_custom_fields() {
+        let columns =
+            ImportColumns::from_header(&strings(&["email", "company", "name", "city"])).unwrap();
+        let row = columns
+            .parse(&strings(&["ursula@example.com", "Earthsea", "Ursula", ""]))
+            .unwrap();
+        assert_eq!(
+            serde_json::Value::Object(row.attributes),
+            serde_json::json!({"company": "Earthsea"})
+        );
+    }
+
+    #[test]
+    fn rows_are_validated() {
+        let columns =
+            ImportColumns::from_header(&strings(&["email", "name", "status", "subscribed_at"]))
+                .unwrap();
+        for record in [
+            strings(&["not-an-email", "Ursula", "", ""]),
+            strings(&["ursula@example.com", "", "", ""]),
+            strings(&["ursula@example.com", "Ursula", "famous", ""]),
+            strings(&["ursula@example.com", "Ursula", "", "last tuesday"]),
+            strings(&["ursula@example.com", "Ursula"]),
+        ] {
+            assert!(
+                columns.parse(&record).is_err(),
+                "{:?} should be rejected",
+                record
+            );
+        }
+    }
+
+    #[test]
+    fn double_opt_in_is_only_skipped_when_asked_to() {
+        let columns = ImportColumns::from_header(&strings(&["email", "name", "status"])).unwrap();
+        let status = |status: &str, skip_confirmation: bool| {
+            columns
+                .parse(&strings(&["ursula@example.com", "Ursula", status]))
+                .unwrap()
+                .status(skip_confirmation)
+        };
+
+        assert_eq!(status("", false), ImportStatus::PendingConfirmation);
+        assert_eq!(
+            status("confirmed", false),
+            ImportStatus::PendingConfirmation
+        );
+        assert_eq!(status("", true), ImportStatus::Confirmed);
+        assert_eq!(status("confirmed", true), ImportStatus::Confirmed);
+        assert_eq!(
+            status("pending_confirmation", true),
+            ImportStatus::PendingConfirmation
+        );
+        assert_eq!(status("unsubscribed", true), ImportStatus::Unsubscribed);
+    }
+
+    #[test]
+    fn bounces_and_complaints_are_imported_as_opt_outs() {
+        let columns = ImportColumns::from_header(&strings(&["email", "name", "status"])).unwrap();
+        for status in ["bounced", "complained"] {
+            let row = columns
+                .parse(&strings(&["ursula@example.com", "Ursula", status]))
+                .unwrap();
+            assert_eq!(row.status(true), ImportStatus::Unsubscribed);
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use crate::cloneable_auth_token::SecretAuthToken;
+use crate::domain::{email_events, lists};
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+/// Every status a subscriber can be in.
+pub const SUBSCRIBER_STATUSES: &[&str] = &[
+    "pending_confirmation",
+    "confirmed",
+    "unsubscribed",
+    "bounced",
+    "complained",
+];
+
+#[derive(serde::Serialize)]
+pub struct Subscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: Option<String>,
+    pub subscribed_at: DateTime<Utc>,
+    /// Set while the subscriber has paused deliveries from the preference center.
+    pub paused_until: Option<DateTime<Utc>>,
+    /// Custom fields brought in by CSV imports.
+    pub attributes: serde_json::Value,
+}
+
+/// `None` fields match everything.
+#[derive(Default, Debug, Clone)]
+pub struct SubscriberFilter {
+    pub status: Option<String>,
+    pub subscribed_after: Option<DateTime<Utc>>,
+    pub subscribed_before: Option<DateTime<Utc>>,
+    /// Case-insensitive substring of the email or the name.
+    pub search: Option<String>,
+}
+
+// `search` is matched literally, so LIKE wildcards typed by the admin are escaped.
+fn like_pattern(search: &str) -> String {
+    let escaped = search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// Returns one page of subscribers, newest first, and how many match the filter overall.
+#[tracing::instrument(skip(pool))]
+pub async fn list_subscribers(
+    pool: &PgPool,
+    filter: &SubscriberFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<Subscriber>, i64), sqlx::Error> {
+    let search = filter.search.as_deref().map(like_pattern);
+    let subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+    SELECT id, email, name, status, subscribed_at, paused_until, attributes
+    FROM subscriptions
+    WHERE ($1::text IS NULL OR status = $1)
+        AND ($2::timestamptz IS NULL OR subscribed_at >= $2)
+        AND ($3::timestamptz IS NULL OR subscribed_at < $3)
+        AND ($4::text IS NULL OR email ILIKE $4 OR name ILIKE $4)
+    ORDER BY subscribed_at DESC, id
+    LIMIT $5 OFFSET $6"#,
+        filter.status.as_deref(),
+        filter.subscribed_after,
+        filter.subscribed_before,
+        search,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+    let total = sqlx::query_scalar!(
+        r#"
+    SELECT count(*) AS "count!"
+    FROM subscriptions
+    WHERE ($1::text IS NULL OR status = $1)
+        AND ($2::timestamptz IS NULL OR subscribed_at >= $2)
+        AND ($3::timestamptz IS NULL OR subscribed_at < $3)
+        AND ($4::text IS NULL OR email ILIKE $4 OR name ILIKE $4)"#,
+        filter.status.as_deref(),
+        filter.subscribed_after,
+        filter.subscribed_before,
+        search
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok((subscribers, total))
+}
+
+/// Subscribers matching `filter` in signup order, starting after the `after` cursor. Unlike
+/// offsets, the cursor doesn't skip or repeat rows when subscribers join mid-export.
+#[tracing::instrument(skip(pool))]
+pub async fn list_subscribers_after(
+    pool: &PgPool,
+    filter: &SubscriberFilter,
+    after: Option<(DateTime<Utc>, Uuid)>,
+    limit: i64,
+) -> Result<Vec<Subscriber>, sqlx::Error> {
+    let search = filter.search.as_deref().map(like_pattern);
+    let (after_subscribed_at, after_id) = after.unzip();
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+    SELECT id, email, name, status, subscribed_at, paused_until, attributes
+    FROM subscriptions
+    WHERE ($1::text IS NULL OR status = $1)
+        AND ($2::timestamptz IS NULL OR subscribed_at >= $2)
+        AND ($3::timestamptz IS NULL OR subscribed_at < $3)
+        AND ($4::text IS NULL OR email ILIKE $4 OR name ILIKE $4)
+        AND ($5::timestamptz IS NULL OR (subscribed_at, id) > ($5, $6::uuid))
+    ORDER BY subscribed_at, id
+    LIMIT $7"#,
+        filter.status.as_deref(),
+        filter.subscribed_after,
+        filter.subscribed_before,
+        search,
+        after_subscribed_at,
+        after_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Every custom field name used by a subscriber matching `filter`, sorted.
+#[tracing::instrument(skip(pool))]
+pub async fn list_attribute_names(
+    pool: &PgPool,
+    filter: &SubscriberFilter,
+) -> Result<Vec<String>, sqlx::Error> {
+    let search = filter.search.as_deref().map(like_pattern);
+    sqlx::query_scalar!(
+        r#"
+    SELECT DISTINCT jsonb_object_keys(attributes) AS "name!"
+    FROM subscriptions
+    WHERE ($1::text IS NULL OR status = $1)
+        AND ($2::timestamptz IS NULL OR subscribed_at >= $2)
+        AND ($3::timestamptz IS NULL OR subscribed_at < $3)
+        AND ($4::text IS NULL OR email ILIKE $4 OR name ILIKE $4)
+    ORDER BY 1"#,
+        filter.status.as_deref(),
+        filter.subscribed_after,
+        filter.subscribed_before,
+        search
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(serde::Serialize)]
+pub struct DeliveryHistory {
+    pub newsletter_id: Uuid,
+    pub title: String,
+    pub outcome: String,
+    pub n_attempts: i16,
+    pub last_attempted_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct EngagementHistory {
+    pub newsletter_id: Uuid,
+    pub kind: String,
+    pub url: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct EmailEventHistory {
+    pub kind: String,
+    pub detail: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct MembershipHistory {
+    pub list_slug: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberHistory {
+    #[serde(flatten)]
+    pub subscriber: Subscriber,
+    /// Why the address is on the suppression list, if it is.
+    pub suppressed: Option<String>,
+    pub tags: Vec<String>,
+    pub lists: Vec<MembershipHistory>,
+    pub deliveries: Vec<DeliveryHistory>,
+    pub engagement: Vec<EngagementHistory>,
+    pub email_events: Vec<EmailEventHistory>,
+}
+
+/// Everything we know happened to one subscriber, most recent first. `None` means there is no
+/// such subscriber.
+#[tracing::instrument(skip(pool))]
+pub async fn get_subscriber_history(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberHistory>, sqlx::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+    SELECT id, email, name, status, subscribed_at, paused_until, attributes
+    FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Ok(None),
+    };
+
+    let suppressed = sqlx::query_scalar!(
+        r#"SELECT reason FROM suppressed_emails WHERE email = lower($1)"#,
+        subscriber.email
+    )
+    .fetch_optional(pool)
+    .await?;
+    let tags = sqlx::query_scalar!(
+        r#"SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag"#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let lists = sqlx::query_as!(
+        MembershipHistory,
+        r#"
+    SELECT list_slug, status, subscribed_at
+    FROM list_memberships
+    WHERE subscriber_id = $1
+    ORDER BY subscribed_at DESC"#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let deliveries = sqlx::query_as!(
+        DeliveryHistory,
+        r#"
+    SELECT d.newsletter_id, n.title, d.outcome, d.n_attempts, d.last_attempted_at
+    FROM newsletter_deliveries d
+    JOIN newsletters n ON n.newsletter_id = d.newsletter_id
+    WHERE d.subscriber_email = $1
+    ORDER BY d.last_attempted_at DESC"#,
+        subscriber.email
+    )
+    .fetch_all(pool)
+    .await?;
+    let engagement = sqlx::query_as!(
+        EngagementHistory,
+        r#"
+    SELECT newsletter_id, kind, url, occurred_at
+    FROM newsletter_engagement
+    WHERE subscriber_id = $1
+    ORDER BY occurred_at DESC"#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let email_events = sqlx::query_as!(
+        EmailEventHistory,
+        r#"
+    SELECT kind, detail, occurred_at
+    FROM email_events
+    WHERE lower(email) = lower($1)
+    ORDER BY occurred_at DESC"#,
+        subscriber.email
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Some(SubscriberHistory {
+        subscriber,
+        suppressed,
+        tags,
+        lists,
+        deliveries,
+        engagement,
+        email_events,
+    }))
+}
+
+pub enum ConfirmOutcome {
+    Confirmed,
+    NotFound,
+    Suppressed,
+}
+
+/// Confirms a subscriber on every list they're waiting to join without a confirmation link,
+/// revoking any link still out there. Suppressed addresses stay as they are.
+#[tracing::instrument(skip(pool, hmac_secret))]
+pub async fn confirm_subscriber(
+    pool: &PgPool,
+    hmac_secret: &SecretAuthToken,
+    subscriber_id: Uuid,
+) -> Result<ConfirmOutcome, sqlx::Error> {
+    let mut trx = pool.begin().await?;
+    let email = sqlx::query_scalar!(
+        r#"SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *trx)
+    .await?;
+    let email = match email {
+        Some(email) => email,
+        None => return Ok(ConfirmOutcome::NotFound),
+    };
+    if email_events::is_suppressed(&mut *trx, hmac_secret, &email).await? {
+        return Ok(ConfirmOutcome::Suppressed);
+    }
+    // Lists they left stay left: confirming stands in for the link, not for a new opt-in.
+    let query = sqlx::query!(
+        r#"
+    UPDATE list_memberships SET status = 'confirmed'
+    WHERE subscriber_id = $1 AND status = 'pending_confirmation'"#,
+        subscriber_id
+    );
+    trx.execute(query).await?;
+    lists::refresh_subscriber_status(&mut trx, subscriber_id).await?;
+    let query = sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    );
+    trx.execute(query).await?;
+    trx.commit().await?;
+    Ok(ConfirmOutcome::Confirmed)
+}
+
+/// Takes the subscriber off every list. A bounced or complained address keeps that status, as the
+/// reason it must not be mailed again. Returns `false` if there is no such subscriber.
+#[tracing::instrument(skip(pool))]
+pub async fn unsubscribe_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let mut trx = pool.begin().await?;
+    let found = sqlx::query_scalar!(
+        r#"SELECT id FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *trx)
+    .await?;
+    if found.is_none() {
+        return Ok(false);
+    }
+    lists::set_membership_status(&mut trx, subscriber_id, None, "unsubscribed").await?;
+    trx.commit().await?;
+    Ok(true)
+}
+
+/// Removes the subscriber and their tokens. Delivery records are keyed by address and kept.
+/// Returns `false` if there is no such subscriber.
+#[tracing::instrument(skip(pool))]
+pub async fn delete_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, sqlx::Error> {
+    let mut trx = pool.begin().await?;
+    let query = sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    );
+    trx.execute(query).await?;
+    let query = sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id);
+    let deleted = trx.execute(query).await?.rows_affected() == 1;
+    trx.commit().await?;
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::like_pattern;
+
+    #[test]
+    fn searches_are_wrapped_in_wildcards() {
+        assert_eq!(like_pattern("ursula"), "%ursula%");
+    }
+
+    #[test]
+    fn like_wildcards_in_searches_are_escaped() {
+        assert_eq!(like_pattern(r"50%_off\"), r"%50\%\_off\\%");
+    }
+}
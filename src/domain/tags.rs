@@ -0,0 +1,91 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Tags are compared as typed in segments, so they're stored trimmed and lowercased.
+pub fn parse_tag(tag: &str) -> Result<String, String> {
+    let tag = tag.trim().to_lowercase();
+    let valid = !tag.is_empty()
+        && tag.chars().count() <= 64
+        && tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(tag)
+    } else {
+        Err(format!(
+            "'{}' is not a valid tag: use up to 64 letters, digits, dashes and underscores.",
+            tag
+        ))
+    }
+}
+
+/// Tags the subscriber. Tags they already have are left as they are. Returns `false` if there
+/// is no such subscriber.
+#[tracing::instrument(skip(pool))]
+pub async fn add_tags(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    tags: &[String],
+) -> Result<bool, sqlx::Error> {
+    let mut trx = pool.begin().await?;
+    let exists = sqlx::query_scalar!(
+        r#"SELECT id FROM subscriptions WHERE id = $1 FOR SHARE"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *trx)
+    .await?
+    .is_some();
+    if exists {
+        sqlx::query!(
+            r#"
+    INSERT INTO subscriber_tags (subscriber_id, tag, tagged_at)
+    SELECT $1, tag, now() FROM UNNEST($2::text[]) AS tag
+    ON CONFLICT (subscriber_id, tag) DO NOTHING"#,
+            subscriber_id,
+            tags
+        )
+        .execute(&mut *trx)
+        .await?;
+    }
+    trx.commit().await?;
+    Ok(exists)
+}
+
+/// Returns `false` if the subscriber didn't have the tag.
+#[tracing::instrument(skip(pool))]
+pub async fn remove_tag(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    tag: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag = $2"#,
+        subscriber_id,
+        tag
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_tag;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        assert_ok_eq!(parse_tag("  Beta "), "beta".to_string());
+        assert_ok_eq!(
+            parse_tag("early_adopter-2025"),
+            "early_adopter-2025".to_string()
+        );
+    }
+
+    #[test]
+    fn tags_are_single_words() {
+        for tag in ["", "   ", "beta testers", "beta,alpha", &"a".repeat(65)] {
+            assert_err!(parse_tag(tag), "{:?} should be rejected", tag);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::cloneable_auth_token::SecretAuthToken;
+use crate::domain::lists as lists_domain;
+use crate::signed_token;
+use sqlx::PgPool;
+use std::str::FromStr;
+use uuid::Uuid;
+
+const UNSUBSCRIBE_PURPOSE: &str = "unsubscribe";
+
+/// Who wants out of what. Links minted before there were lists carry no list and unsubscribe
+/// from everything, which is what they promised at the time.
+#[derive(Debug, PartialEq)]
+pub struct UnsubscribeRequest {
+    pub subscriber_id: Uuid,
+    pub list_slug: Option<String>,
+}
+
+pub fn unsubscribe_token(secret: &SecretAuthToken, subscriber_id: Uuid, list_slug: &str) -> String {
+    signed_token::sign(
+        secret,
+        UNSUBSCRIBE_PURPOSE,
+        &format!("{}:{}", subscriber_id, list_slug),
+    )
+}
+
+pub fn unsubscribe_link(
+    base_url: &str,
+    secret: &SecretAuthToken,
+    subscriber_id: Uuid,
+    list_slug: &str,
+) -> String {
+    format!(
+        "{}/subscriptions/unsubscribe?token={}",
+        base_url,
+        unsubscribe_token(secret, subscriber_id, list_slug)
+    )
+}
+
+pub fn parse_unsubscribe_token(
+    secret: &SecretAuthToken,
+    token: &str,
+) -> Option<UnsubscribeRequest> {
+    let payload = signed_token::verify(secret, UNSUBSCRIBE_PURPOSE, token)?;
+    let (subscriber_id, list_slug) = match payload.split_once(':') {
+        Some((subscriber_id, list_slug)) => (subscriber_id, Some(list_slug.to_string())),
+        None => (payload.as_str(), None),
+    };
+    Some(UnsubscribeRequest {
+        subscriber_id: Uuid::from_str(subscriber_id).ok()?,
+        list_slug,
+    })
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+pub async fn unsubscribe_subscriber(
+    pool: &PgPool,
+    request: &UnsubscribeRequest,
+) -> Result<(), sqlx::Error> {
+    let mut trx = pool.begin().await?;
+    lists_domain::set_membership_status(
+        &mut trx,
+        request.subscriber_id,
+        request.list_slug.as_deref(),
+        "unsubscribed",
+    )
+    .await?;
+    trx.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_unsubscribe_token, unsubscribe_link, unsubscribe_token, UnsubscribeRequest};
+    use crate::cloneable_auth_token::AuthToken;
+    use claims::{assert_none, assert_some_eq};
+    use uuid::Uuid;
+
+    #[test]
+    fn an_unsubscribe_token_resolves_to_its_subscriber_and_list() {
+        let secret = AuthToken::new("secret".to_string());
+        let subscriber_id = Uuid::new_v4();
+        let token = unsubscribe_token(&secret, subscriber_id, "newsletter");
+        assert_some_eq!(
+            parse_unsubscribe_token(&secret, &token),
+            UnsubscribeRequest {
+                subscriber_id,
+                list_slug: Some("newsletter".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn a_token_from_before_lists_unsubscribes_from_everything() {
+        let secret = AuthToken::new("secret".to_string());
+        let subscriber_id = Uuid::new_v4();
+        let token = crate::signed_token::sign(&secret, "unsubscribe", &subscriber_id.to_string());
+        assert_some_eq!(
+            parse_unsubscribe_token(&secret, &token),
+            UnsubscribeRequest {
+                subscriber_id,
+                list_slug: None
+            }
+        );
+    }
+
+    #[test]
+    fn a_token_that_does_not_hold_a_subscriber_id_is_rejected() {
+        let secret = AuthToken::new("secret".to_string());
+        let token = crate::signed_token::sign(&secret, "unsubscribe", "not-a-uuid");
+        assert_none!(parse_unsubscribe_token(&secret, &token));
+    }
+
+    #[test]
+    fn the_unsubscribe_link_points_at_the_unsubscribe_endpoint() {
+        let secret = AuthToken::new("secret".to_string());
+        let subscriber_id = Uuid::new_v4();
+        let link = unsubscribe_link("http://127.0.0.1", &secret, subscriber_id, "newsletter");
+        assert_eq!(
+            link,
+            format!(
+                "http://127.0.0.1/subscriptions/unsubscribe?token={}",
+                unsubscribe_token(&secret, subscriber_id, "newsletter")
+            )
+        );
+    }
+}
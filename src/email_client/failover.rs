@@ -0,0 +1,324 @@
+use super::{EmailError, EmailMessage, EmailReceipt, EmailTransport};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Sends through the first healthy provider, moving down the list when one answers with a
+/// transient error (5xx, 429, timeouts). Each provider has a circuit breaker: after
+/// `failure_threshold` consecutive failures it is skipped for `cooldown`, then tried again.
+///
+/// Permanent errors are returned straight away, as the next provider would refuse the same
+/// email for the same reason.
+pub struct FailoverTransport {
+    providers: Vec<Provider>,
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+struct Provider {
+    name: String,
+    transport: Arc<dyn EmailTransport>,
+    health: Mutex<Health>,
+}
+
+#[derive(Default)]
+struct Health {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl FailoverTransport {
+    pub fn new(
+        providers: Vec<(String, Arc<dyn EmailTransport>)>,
+        failure_threshold: u32,
+        cooldown: Duration,
+    ) -> Self {
+        Self {
+            providers: providers
+                .into_iter()
+                .map(|(name, transport)| Provider {
+                    name,
+                    transport,
+                    health: Mutex::default(),
+                })
+                .collect(),
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+        }
+    }
+
+    fn available(&self) -> impl Iterator<Item = &Provider> {
+        let now = Instant::now();
+        self.providers.iter().filter(move |provider| {
+            match provider.health.lock().unwrap().open_until {
+                Some(open_until) => open_until <= now,
+                None => true,
+            }
+        })
+    }
+
+    fn succeeded(&self, provider: &Provider) {
+        *provider.health.lock().unwrap() = Health::default();
+    }
+
+    fn failed(&self, provider: &Provider, error: &EmailError) {
+        let mut health = provider.health.lock().unwrap();
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= self.failure_threshold {
+            health.open_until = Some(Instant::now() + self.cooldown);
+            tracing::error!(
+                provider = %provider.name,
+                consecutive_failures = health.consecutive_failures,
+                error.message = %error,
+                "Email provider keeps failing, skipping it for {:?}",
+                self.cooldown,
+            );
+        } else {
+            tracing::warn!(
+                provider = %provider.name,
+                error.message = %error,
+                "Email provider failed, trying the next one",
+            );
+        }
+    }
+}
+
+fn is_transient_failure(result: &Result<EmailReceipt, EmailError>) -> bool {
+    matches!(result, Err(e) if e.is_transient())
+}
+
+fn stamp(mut receipt: EmailReceipt, provider: &Provider) -> EmailReceipt {
+    receipt.provider = Some(provider.name.clone());
+    receipt
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FailoverTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<EmailReceipt, EmailError> {
+        let mut last_error = EmailError::NoProviderAvailable;
+        for provider in self.available() {
+            match provider.transport.send(message).await {
+                Ok(receipt) => {
+                    self.succeeded(provider);
+                    return Ok(stamp(receipt, provider));
+                }
+                Err(e) if e.is_transient() => {
+                    self.failed(provider, &e);
+                    last_error = e;
+                }
+                Err(e) => {
+                    // The provider is up, it just won't take this email.
+                    self.succeeded(provider);
+                    return Err(e);
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn send_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<Result<EmailReceipt, EmailError>>, EmailError> {
+        let mut last_error = EmailError::NoProviderAvailable;
+        for provider in self.available() {
+            match provider.transport.send_batch(messages).await {
+                // Transports without a batch API report an outage message by message.
+                Ok(results) if results.iter().all(is_transient_failure) && !results.is_empty() => {
+                    let e = results.into_iter().find_map(Result::err).unwrap();
+                    self.failed(provider, &e);
+                    last_error = e;
+                }
+                Ok(results) => {
+                    self.succeeded(provider);
+                    return Ok(results
+                        .into_iter()
+                        .map(|result| result.map(|receipt| stamp(receipt, provider)))
+                        .collect());
+                }
+                Err(e) if e.is_transient() => {
+                    self.failed(provider, &e);
+                    last_error = e;
+                }
+                Err(e) => {
+                    self.succeeded(provider);
+                    return Err(e);
+                }
+            }
+        }
+        Err(last_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FailoverTransport;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        EmailError, EmailMessage, EmailOptions, EmailReceipt, EmailTransport, InMemoryTransport,
+    };
+    use claims::assert_ok;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Fails every send with a transient or a permanent error, counting attempts.
+    struct Failing {
+        transient: bool,
+        attempts: AtomicUsize,
+    }
+
+    impl Failing {
+        fn new(transient: bool) -> Arc<Self> {
+            Arc::new(Self {
+                transient,
+                attempts: AtomicUsize::new(0),
+            })
+        }
+
+        fn attempts(&self) -> usize {
+            self.attempts.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl EmailTransport for Failing {
+        async fn send(&self, _message: &EmailMessage) -> Result<EmailReceipt, EmailError> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            if self.transient {
+                Err(EmailError::Io(std::io::Error::other("connection reset")))
+            } else {
+                Err(EmailError::InvalidMessage("bad address".into()))
+            }
+        }
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            from: SubscriberEmail::new("sender@example.com".into()).unwrap(),
+            to: SubscriberEmail::new("someone@example.com".into()).unwrap(),
+            subject: "Subject".into(),
+            html_body: "<p>html</p>".into(),
+            text_body: "text".into(),
+            options: EmailOptions::default(),
+        }
+    }
+
+    fn failover(
+        providers: Vec<(&str, Arc<dyn EmailTransport>)>,
+        failure_threshold: u32,
+    ) -> FailoverTransport {
+        FailoverTransport::new(
+            providers
+                .into_iter()
+                .map(|(name, transport)| (name.to_string(), transport))
+                .collect(),
+            failure_threshold,
+            Duration::from_secs(30),
+        )
+    }
+
+    #[tokio::test]
+    async fn the_primary_is_used_while_it_is_healthy() {
+        let primary = InMemoryTransport::new();
+        let secondary = InMemoryTransport::new();
+        let transport = failover(
+            vec![
+                ("primary", Arc::new(primary.clone())),
+                ("secondary", Arc::new(secondary.clone())),
+            ],
+            3,
+        );
+
+        let receipt = transport.send(&message()).await.unwrap();
+
+        assert_eq!(receipt.provider.as_deref(), Some("primary"));
+        assert_eq!(primary.messages().len(), 1);
+        assert!(secondary.messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn transient_failures_fail_over_to_the_next_provider() {
+        let secondary = InMemoryTransport::new();
+        let transport = failover(
+            vec![
+                ("primary", Failing::new(true)),
+                ("secondary", Arc::new(secondary.clone())),
+            ],
+            3,
+        );
+
+        let receipt = transport.send(&message()).await.unwrap();
+
+        assert_eq!(receipt.provider.as_deref(), Some("secondary"));
+        assert_eq!(secondary.messages().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn permanent_failures_do_not_fail_over() {
+        let secondary = InMemoryTransport::new();
+        let transport = failover(
+            vec![
+                ("primary", Failing::new(false)),
+                ("secondary", Arc::new(secondary.clone())),
+            ],
+            3,
+        );
+
+        let error = transport.send(&message()).await.unwrap_err();
+
+        assert!(!error.is_transient());
+        assert!(secondary.messages().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_failing_provider_is_skipped_until_its_cooldown_is_over() {
+        let primary = Failing::new(true);
+        let transport = failover(
+            vec![
+                ("primary", primary.clone()),
+                ("secondary", Arc::new(InMemoryTransport::new())),
+            ],
+            2,
+        );
+
+        for _ in 0..5 {
+            assert_ok!(transport.send(&message()).await);
+        }
+        assert_eq!(primary.attempts(), 2);
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert_ok!(transport.send(&message()).await);
+        assert_eq!(primary.attempts(), 3);
+    }
+
+    #[tokio::test]
+    async fn with_every_circuit_open_sends_fail_transiently() {
+        let transport = failover(vec![("primary", Failing::new(true))], 1);
+        transport.send(&message()).await.unwrap_err();
+
+        let error = transport.send(&message()).await.unwrap_err();
+
+        assert!(matches!(error, EmailError::NoProviderAvailable));
+        assert!(error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn batches_fail_over_as_a_whole() {
+        let secondary = InMemoryTransport::new();
+        let transport = failover(
+            vec![
+                ("primary", Failing::new(true)),
+                ("secondary", Arc::new(secondary.clone())),
+            ],
+            3,
+        );
+
+        let results = transport.send_batch(&[message(), message()]).await.unwrap();
+
+        assert!(results
+            .iter()
+            .all(|r| r.as_ref().unwrap().provider.as_deref() == Some("secondary")));
+        assert_eq!(secondary.messages().len(), 2);
+    }
+}
@@ -0,0 +1,80 @@
+use super::{EmailError, EmailMessage, EmailReceipt, EmailTransport};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Writes every email into a maildir, so local development never reaches a real inbox. Point a
+/// mail client at the directory to read them.
+pub struct FileTransport {
+    directory: PathBuf,
+}
+
+impl FileTransport {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<EmailReceipt, EmailError> {
+        let mime = message.to_mime()?;
+        let message_id = mime.headers().get_raw("Message-ID").map(str::to_string);
+
+        // Maildir delivery: write under tmp/ and rename into new/, so readers never see a
+        // partially written file.
+        let tmp = self.directory.join("tmp");
+        let new = self.directory.join("new");
+        tokio::fs::create_dir_all(&tmp).await?;
+        tokio::fs::create_dir_all(&new).await?;
+        tokio::fs::create_dir_all(self.directory.join("cur")).await?;
+        let file_name = format!(
+            "{}.{}.zero2prod",
+            chrono::Utc::now().timestamp(),
+            Uuid::new_v4()
+        );
+        tokio::fs::write(tmp.join(&file_name), mime.formatted()).await?;
+        tokio::fs::rename(tmp.join(&file_name), new.join(&file_name)).await?;
+
+        Ok(EmailReceipt {
+            message_id,
+            provider: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileTransport;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailMessage, EmailOptions, EmailTransport};
+
+    #[tokio::test]
+    async fn emails_are_delivered_into_the_new_folder() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let transport = FileTransport::new(&directory);
+        let message = EmailMessage {
+            from: SubscriberEmail::new("from@example.com".into()).unwrap(),
+            to: SubscriberEmail::new("to@example.com".into()).unwrap(),
+            subject: "Maildir".into(),
+            html_body: "<p>html</p>".into(),
+            text_body: "text".into(),
+            options: EmailOptions::default(),
+        };
+
+        let receipt = transport.send(&message).await.unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(directory.join("new"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        let contents = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(contents.contains("Subject: Maildir"));
+        assert!(contents.contains("To: to@example.com"));
+        assert!(receipt.message_id.is_some());
+        assert_eq!(std::fs::read_dir(directory.join("tmp")).unwrap().count(), 0);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,31 @@
+use super::{EmailError, EmailMessage, EmailReceipt, EmailTransport};
+use std::sync::{Arc, Mutex};
+
+/// Keeps every email it is given. Clones share the same mailbox, so a test can hold on to one
+/// while the code under test sends through another.
+#[derive(Clone, Default)]
+pub struct InMemoryTransport {
+    sent: Arc<Mutex<Vec<EmailMessage>>>,
+}
+
+impl InMemoryTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn messages(&self) -> Vec<EmailMessage> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for InMemoryTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<EmailReceipt, EmailError> {
+        let mut sent = self.sent.lock().unwrap();
+        sent.push(message.clone());
+        Ok(EmailReceipt {
+            message_id: Some(format!("in-memory-{}", sent.len())),
+            provider: None,
+        })
+    }
+}
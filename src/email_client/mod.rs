@@ -0,0 +1,673 @@
+mod failover;
+mod file;
+mod in_memory;
+mod postmark;
+mod rate_limit;
+mod smtp;
+mod stdout;
+
+use crate::cloneable_auth_token::SecretAuthToken;
+use crate::domain::SubscriberEmail;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub use failover::FailoverTransport;
+pub use file::FileTransport;
+pub use in_memory::InMemoryTransport;
+pub use postmark::PostmarkTransport;
+pub use rate_limit::{RateLimitedTransport, TokenBucket};
+pub use smtp::SmtpTransport;
+pub use stdout::StdoutTransport;
+
+/// Something that can hand an email over for delivery.
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, message: &EmailMessage) -> Result<EmailReceipt, EmailError>;
+
+    /// Sends several emails, returning one result per message in the same order. An outer error
+    /// means none of them went out. Transports without a batch API send them one at a time.
+    async fn send_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<Result<EmailReceipt, EmailError>>, EmailError> {
+        let mut results = Vec::with_capacity(messages.len());
+        for message in messages {
+            results.push(self.send(message).await);
+        }
+        Ok(results)
+    }
+}
+
+/// The most messages `EmailClient::send_batch` accepts in one call, Postmark's batch limit.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+#[derive(Clone)]
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Arc<dyn EmailTransport>,
+}
+
+impl EmailClient {
+    /// A client for the Postmark HTTP API.
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        auth_token: SecretAuthToken,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self::with_transport(
+            sender,
+            Arc::new(PostmarkTransport::new(base_url, auth_token, timeout)),
+        )
+    }
+
+    pub fn with_transport(sender: SubscriberEmail, transport: Arc<dyn EmailTransport>) -> Self {
+        Self { sender, transport }
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), EmailError> {
+        self.send_email_with_options(
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            &EmailOptions::default(),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn send_email_with_options(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        options: &EmailOptions,
+    ) -> Result<EmailReceipt, EmailError> {
+        let message = self.message(recipient, subject, html_content, text_content, options);
+        self.transport.send(&message).await
+    }
+
+    /// Addresses an email from our sender, ready for `send_batch`.
+    pub fn message(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        options: &EmailOptions,
+    ) -> EmailMessage {
+        EmailMessage {
+            from: self.sender.clone(),
+            to: recipient.clone(),
+            subject: subject.to_string(),
+            html_body: html_content.to_string(),
+            text_body: text_content.to_string(),
+            options: options.clone(),
+        }
+    }
+
+    pub async fn send_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<Result<EmailReceipt, EmailError>>, EmailError> {
+        if messages.len() > MAX_BATCH_SIZE {
+            return Err(EmailError::InvalidMessage(format!(
+                "A batch can hold at most {} emails, got {}.",
+                MAX_BATCH_SIZE,
+                messages.len()
+            )));
+        }
+        self.transport.send_batch(messages).await
+    }
+}
+
+/// A fully addressed email, as handed to a transport.
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub from: SubscriberEmail,
+    pub to: SubscriberEmail,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    pub options: EmailOptions,
+}
+
+impl EmailMessage {
+    /// The message as a MIME document. Tags, metadata and message streams are Postmark
+    /// features with no standard header, so they are left out.
+    pub fn to_mime(&self) -> Result<lettre::Message, EmailError> {
+        let mut builder = lettre::Message::builder()
+            .from(mailbox(&self.from)?)
+            .to(mailbox(&self.to)?)
+            .subject(&self.subject)
+            .message_id(None);
+        if let Some(reply_to) = &self.options.reply_to {
+            builder = builder.reply_to(mailbox(reply_to)?);
+        }
+        for header in &self.options.headers {
+            let name = HeaderName::new_from_ascii(header.name.clone())
+                .map_err(|e| EmailError::InvalidMessage(e.to_string()))?;
+            builder = builder.raw_header(HeaderValue::new(name, header.value.clone()));
+        }
+        builder
+            .multipart(MultiPart::alternative_plain_html(
+                self.text_body.clone(),
+                self.html_body.clone(),
+            ))
+            .map_err(|e| EmailError::InvalidMessage(e.to_string()))
+    }
+}
+
+fn mailbox(email: &SubscriberEmail) -> Result<Mailbox, EmailError> {
+    email
+        .as_ref()
+        .parse()
+        .map_err(|e: lettre::address::AddressError| EmailError::InvalidMessage(e.to_string()))
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EmailError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("Failed to write the email out: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The email could not be built: {0}")]
+    InvalidMessage(String),
+    #[error("The provider rejected the email (error code {code}): {message}")]
+    Rejected { code: i64, message: String },
+    #[error("Every email provider is currently failing.")]
+    NoProviderAvailable,
+}
+
+impl EmailError {
+    /// Whether sending the same email again later might succeed.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            // Timeouts and connection errors carry no status and are worth another attempt, as
+            // are 5xx and 429; any other rejection will fail the same way every time.
+            EmailError::Http(e) => match e.status() {
+                Some(status) => {
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                }
+                None => true,
+            },
+            // 5xx replies are permanent in SMTP; 4xx replies and connection problems are not.
+            EmailError::Smtp(e) => !e.is_permanent(),
+            EmailError::Io(_) => true,
+            EmailError::InvalidMessage(_) => false,
+            // Per-message rejections in a batch are about that message, e.g. an inactive
+            // recipient, except when the provider is down for maintenance.
+            EmailError::Rejected { code, .. } => *code == postmark::MAINTENANCE_ERROR_CODE,
+            EmailError::NoProviderAvailable => true,
+        }
+    }
+}
+
+/// What the provider told us about an email it accepted.
+#[derive(Debug)]
+pub struct EmailReceipt {
+    pub message_id: Option<String>,
+    /// Which configured provider took the email, when sending through several.
+    pub provider: Option<String>,
+}
+
+/// Optional fields of an outgoing email, on top of what `send_email` always sets.
+#[derive(Default, Debug, Clone)]
+pub struct EmailOptions {
+    pub reply_to: Option<SubscriberEmail>,
+    pub message_stream: Option<String>,
+    pub tag: Option<String>,
+    pub metadata: HashMap<String, String>,
+    pub headers: Vec<EmailHeader>,
+}
+
+impl EmailOptions {
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push(EmailHeader {
+            name: name.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cloneable_auth_token::AuthToken;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        EmailClient, EmailError, EmailMessage, EmailOptions, InMemoryTransport, MAX_BATCH_SIZE,
+    };
+    use claims::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Dummy, Fake, Faker};
+    use rand::Rng;
+    use serde_json::{from_slice, Value};
+    use std::sync::Arc;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::new(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        EmailClient::new(
+            base_url,
+            email(),
+            AuthToken::new(Faker.fake()),
+            std::time::Duration::from_millis(300),
+        )
+    }
+
+    impl Dummy<Faker> for AuthToken {
+        fn dummy(_config: &Faker) -> Self {
+            Faker.fake()
+        }
+
+        fn dummy_with_rng<R: Rng + ?Sized>(_config: &Faker, _rng: &mut R) -> Self {
+            Faker.fake()
+        }
+    }
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<Value, _> = from_slice(&request.body);
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome)
+    }
+
+    #[tokio::test]
+    async fn send_email_omits_options_that_are_not_set() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await
+            .unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: Value = from_slice(&request.body).unwrap();
+        for field in ["ReplyTo", "MessageStream", "Tag", "Metadata", "Headers"] {
+            assert!(body.get(field).is_none(), "{} should be omitted", field);
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_with_options_passes_them_through() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let reply_to = email();
+        let options = EmailOptions {
+            reply_to: Some(reply_to.clone()),
+            message_stream: Some("broadcast".into()),
+            ..Default::default()
+        }
+        .tag("newsletter")
+        .metadata("newsletter_id", "42")
+        .header("List-Unsubscribe", "<https://example.com/unsubscribe>")
+        .header("List-Unsubscribe-Post", "List-Unsubscribe=One-Click");
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        email_client
+            .send_email_with_options(&email(), &subject(), &content(), &content(), &options)
+            .await
+            .unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: Value = from_slice(&request.body).unwrap();
+        assert_eq!(body["ReplyTo"], reply_to.as_ref());
+        assert_eq!(body["MessageStream"], "broadcast");
+        assert_eq!(body["Tag"], "newsletter");
+        assert_eq!(body["Metadata"]["newsletter_id"], "42");
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([
+                {"Name": "List-Unsubscribe", "Value": "<https://example.com/unsubscribe>"},
+                {"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_with_options_returns_the_provider_message_id() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "To": "receiver@example.com",
+                "SubmittedAt": "2014-02-17T07:25:01.4178645-05:00",
+                "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d",
+                "ErrorCode": 0,
+                "Message": "OK"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let receipt = email_client
+            .send_email_with_options(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                &EmailOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            receipt.message_id.as_deref(),
+            Some("0a129aee-e1cd-480d-b08d-4f48548ff48d")
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_200() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_transient_and_client_errors_are_not() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        for (status, transient) in [(500, true), (503, true), (429, true), (422, false)] {
+            mock_server.reset().await;
+            Mock::given(any())
+                .respond_with(ResponseTemplate::new(status))
+                .mount(&mock_server)
+                .await;
+
+            let error = email_client
+                .send_email(&email(), &subject(), &content(), &content())
+                .await
+                .unwrap_err();
+
+            assert_eq!(error.is_transient(), transient, "status {}", status);
+        }
+    }
+
+    fn message(client: &EmailClient) -> EmailMessage {
+        client.message(
+            &email(),
+            &subject(),
+            &content(),
+            &content(),
+            &EmailOptions::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn batches_go_to_the_batch_endpoint_and_map_results_back_in_order() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let messages: Vec<_> = (0..3).map(|_| message(&email_client)).collect();
+
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 0, "Message": "OK", "MessageID": "first"},
+                {"ErrorCode": 406, "Message": "Inactive recipient"},
+                {"ErrorCode": 0, "Message": "OK", "MessageID": "third"},
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(&messages).await.unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: Value = from_slice(&request.body).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 3);
+        assert_eq!(body[1]["To"], messages[1].to.as_ref());
+        assert_eq!(
+            results[0].as_ref().unwrap().message_id.as_deref(),
+            Some("first")
+        );
+        let rejection = results[1].as_ref().unwrap_err();
+        assert!(matches!(rejection, EmailError::Rejected { code: 406, .. }));
+        assert!(!rejection.is_transient());
+        assert_eq!(
+            results[2].as_ref().unwrap().message_id.as_deref(),
+            Some("third")
+        );
+    }
+
+    #[tokio::test]
+    async fn a_batch_of_one_uses_the_single_email_endpoint() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client
+            .send_batch(&[message(&email_client)])
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_ok!(&results[0]);
+    }
+
+    #[tokio::test]
+    async fn a_failed_batch_request_fails_the_whole_batch() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let error = email_client
+            .send_batch(&[message(&email_client), message(&email_client)])
+            .await
+            .unwrap_err();
+
+        assert!(error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn batches_larger_than_the_provider_limit_are_refused() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let messages: Vec<_> = (0..MAX_BATCH_SIZE + 1)
+            .map(|_| message(&email_client))
+            .collect();
+
+        assert_err!(email_client.send_batch(&messages).await);
+        assert!(mock_server.received_requests().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn transports_without_a_batch_api_send_one_by_one() {
+        let transport = InMemoryTransport::new();
+        let client = EmailClient::with_transport(email(), Arc::new(transport.clone()));
+        let messages: Vec<_> = (0..3).map(|_| message(&client)).collect();
+
+        let results = client.send_batch(&messages).await.unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(transport.messages().len(), 3);
+    }
+
+    #[test]
+    fn mime_messages_carry_both_bodies_and_custom_headers() {
+        let message = EmailMessage {
+            from: email(),
+            to: email(),
+            subject: "Subject".into(),
+            html_body: "<p>html body</p>".into(),
+            text_body: "text body".into(),
+            options: EmailOptions::default().header("List-Unsubscribe", "<https://x/u>"),
+        };
+
+        let formatted = String::from_utf8(message.to_mime().unwrap().formatted()).unwrap();
+
+        assert!(formatted.contains("List-Unsubscribe: <https://x/u>"));
+        assert!(formatted.contains("text/plain"));
+        assert!(formatted.contains("text body"));
+        assert!(formatted.contains("text/html"));
+        assert!(formatted.contains("<p>html body</p>"));
+    }
+
+    #[tokio::test]
+    async fn clients_send_through_the_transport_they_are_given() {
+        let transport = InMemoryTransport::new();
+        let sender = email();
+        let client = EmailClient::with_transport(sender.clone(), Arc::new(transport.clone()));
+        let recipient = email();
+
+        client
+            .send_email(&recipient, "Subject", "<p>html</p>", "text")
+            .await
+            .unwrap();
+
+        let sent = transport.messages();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].from.as_ref(), sender.as_ref());
+        assert_eq!(sent[0].to.as_ref(), recipient.as_ref());
+        assert_eq!(sent[0].subject, "Subject");
+    }
+}
@@ -0,0 +1,158 @@
+use super::{EmailError, EmailHeader, EmailMessage, EmailReceipt, EmailTransport};
+use crate::cloneable_auth_token::SecretAuthToken;
+use reqwest::Client;
+use secrecy::ExposeSecret;
+use std::collections::HashMap;
+
+/// Postmark's error code while the API is down for maintenance.
+pub(super) const MAINTENANCE_ERROR_CODE: i64 = 100;
+
+/// Sends through Postmark's `/email` and `/email/batch` JSON APIs.
+pub struct PostmarkTransport {
+    http_client: Client,
+    base_url: String,
+    auth_token: SecretAuthToken,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: String,
+        auth_token: SecretAuthToken,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            auth_token,
+        }
+    }
+
+    async fn post<T: serde::Serialize + ?Sized>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> Result<reqwest::Response, EmailError> {
+        let url = format!("{}{}", self.base_url, path);
+        let response = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.auth_token.expose_secret().clone().token.as_str(),
+            )
+            .json(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response)
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<EmailReceipt, EmailError> {
+        let response_body = self
+            .post("/email", &SendEmailRequest::from(message))
+            .await?
+            .bytes()
+            .await?;
+        // The message id is only used for bookkeeping, a body we can't parse isn't a failure.
+        let message_id = serde_json::from_slice::<SendEmailResponse>(&response_body)
+            .ok()
+            .and_then(|r| r.message_id);
+        Ok(EmailReceipt {
+            message_id,
+            provider: None,
+        })
+    }
+
+    async fn send_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<Result<EmailReceipt, EmailError>>, EmailError> {
+        // The single-message endpoint is cheaper and reports failures with a proper status.
+        if let [message] = messages {
+            return Ok(vec![self.send(message).await]);
+        }
+        if messages.is_empty() {
+            return Ok(Vec::new());
+        }
+        let request_body: Vec<_> = messages.iter().map(SendEmailRequest::from).collect();
+        let response_body = self
+            .post("/email/batch", &request_body)
+            .await?
+            .bytes()
+            .await?;
+        // Postmark answers 200 with one entry per message, in order. If we can't read them,
+        // assume everything was accepted rather than send the whole batch again.
+        let responses: Vec<SendEmailResponse> =
+            serde_json::from_slice(&response_body).unwrap_or_default();
+        let mut responses = responses.into_iter();
+        Ok(messages
+            .iter()
+            .map(|_| match responses.next() {
+                Some(r) if r.error_code != 0 => Err(EmailError::Rejected {
+                    code: r.error_code,
+                    message: r.message.unwrap_or_default(),
+                }),
+                Some(r) => Ok(EmailReceipt {
+                    message_id: r.message_id,
+                    provider: None,
+                }),
+                None => Ok(EmailReceipt {
+                    message_id: None,
+                    provider: None,
+                }),
+            })
+            .collect())
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    #[serde(rename = "ErrorCode", default)]
+    error_code: i64,
+    #[serde(rename = "Message")]
+    message: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_stream: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<&'a str>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    metadata: &'a HashMap<String, String>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader],
+}
+
+impl<'a> From<&'a EmailMessage> for SendEmailRequest<'a> {
+    fn from(message: &'a EmailMessage) -> Self {
+        let options = &message.options;
+        Self {
+            from: message.from.as_ref(),
+            to: message.to.as_ref(),
+            subject: &message.subject,
+            html_body: &message.html_body,
+            text_body: &message.text_body,
+            reply_to: options.reply_to.as_ref().map(AsRef::as_ref),
+            message_stream: options.message_stream.as_deref(),
+            tag: options.tag.as_deref(),
+            metadata: &options.metadata,
+            headers: &options.headers,
+        }
+    }
+}
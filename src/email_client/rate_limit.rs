@@ -0,0 +1,232 @@
+use super::{EmailError, EmailMessage, EmailReceipt, EmailTransport};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A token bucket refilled at a steady rate, holding at most one second's worth of tokens.
+///
+/// Callers reserve tokens up front and sleep off any deficit, so concurrent senders queue up
+/// behind each other and a batch larger than the bucket simply waits longer.
+pub struct TokenBucket {
+    per_second: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// Configured rates are checked by `RateLimitSettings::validate` before they get here.
+    pub fn per_second(per_second: f64) -> Self {
+        assert!(per_second > 0., "Rate limits must be positive.");
+        Self {
+            per_second,
+            state: Mutex::new(BucketState {
+                tokens: per_second.max(1.),
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes `n` tokens and returns how long to wait before using them.
+    pub fn reserve(&self, n: usize) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.per_second).min(self.per_second.max(1.));
+        state.refilled_at = now;
+        state.tokens -= n as f64;
+        if state.tokens >= 0. {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.per_second)
+        }
+    }
+}
+
+/// Holds emails back so they never leave faster than the configured rates: one cap across
+/// everything, and optional caps per recipient domain. Clones of the client share the buckets,
+/// so the limits hold across all worker tasks.
+pub struct RateLimitedTransport {
+    inner: Arc<dyn EmailTransport>,
+    global: Option<TokenBucket>,
+    per_domain: HashMap<String, TokenBucket>,
+}
+
+impl RateLimitedTransport {
+    pub fn new(
+        inner: Arc<dyn EmailTransport>,
+        messages_per_second: Option<f64>,
+        per_domain: HashMap<String, f64>,
+    ) -> Self {
+        Self {
+            inner,
+            global: messages_per_second.map(TokenBucket::per_second),
+            per_domain: per_domain
+                .into_iter()
+                .map(|(domain, rate)| (domain.to_lowercase(), TokenBucket::per_second(rate)))
+                .collect(),
+        }
+    }
+
+    async fn wait_for(&self, messages: &[EmailMessage]) {
+        let mut wait = self
+            .global
+            .as_ref()
+            .map(|bucket| bucket.reserve(messages.len()))
+            .unwrap_or_default();
+        let mut per_domain: HashMap<String, usize> = HashMap::new();
+        for message in messages {
+            *per_domain.entry(domain(message)).or_default() += 1;
+        }
+        for (domain, n) in per_domain {
+            if let Some(bucket) = self.per_domain.get(&domain) {
+                wait = wait.max(bucket.reserve(n));
+            }
+        }
+        if !wait.is_zero() {
+            tracing::debug!("Rate limited, holding emails back for {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+fn domain(message: &EmailMessage) -> String {
+    let address: &str = message.to.as_ref();
+    address
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_lowercase())
+        .unwrap_or_default()
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for RateLimitedTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<EmailReceipt, EmailError> {
+        self.wait_for(std::slice::from_ref(message)).await;
+        self.inner.send(message).await
+    }
+
+    async fn send_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<Result<EmailReceipt, EmailError>>, EmailError> {
+        self.wait_for(messages).await;
+        self.inner.send_batch(messages).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimitedTransport, TokenBucket};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailMessage, EmailOptions, EmailTransport, InMemoryTransport};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    fn message_to(recipient: &str) -> EmailMessage {
+        EmailMessage {
+            from: SubscriberEmail::new("sender@example.com".into()).unwrap(),
+            to: SubscriberEmail::new(recipient.into()).unwrap(),
+            subject: "Subject".into(),
+            html_body: "<p>html</p>".into(),
+            text_body: "text".into(),
+            options: EmailOptions::default(),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_full_bucket_allows_a_burst_and_then_spaces_requests_out() {
+        let bucket = TokenBucket::per_second(2.);
+
+        assert_eq!(bucket.reserve(1), Duration::ZERO);
+        assert_eq!(bucket.reserve(1), Duration::ZERO);
+        assert_eq!(bucket.reserve(1), Duration::from_millis(500));
+        assert_eq!(bucket.reserve(1), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn buckets_refill_over_time() {
+        let bucket = TokenBucket::per_second(2.);
+        bucket.reserve(2);
+
+        tokio::time::advance(Duration::from_millis(500)).await;
+
+        assert_eq!(bucket.reserve(1), Duration::ZERO);
+        assert_eq!(bucket.reserve(1), Duration::from_millis(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reservations_larger_than_the_bucket_wait_for_the_difference() {
+        let bucket = TokenBucket::per_second(10.);
+
+        assert_eq!(bucket.reserve(30), Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_global_limit_applies_to_every_email() {
+        let inner = InMemoryTransport::new();
+        let transport =
+            RateLimitedTransport::new(Arc::new(inner.clone()), Some(1.), HashMap::new());
+        let start = Instant::now();
+
+        for recipient in ["a@one.com", "b@two.com", "c@three.com"] {
+            transport.send(&message_to(recipient)).await.unwrap();
+        }
+
+        assert_eq!(inner.messages().len(), 3);
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn domain_limits_only_slow_down_their_own_domain() {
+        let inner = InMemoryTransport::new();
+        let transport = RateLimitedTransport::new(
+            Arc::new(inner),
+            None,
+            HashMap::from([("Gmail.com".to_string(), 1.)]),
+        );
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            transport
+                .send(&message_to("someone@example.com"))
+                .await
+                .unwrap();
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            transport
+                .send(&message_to("someone@gmail.com"))
+                .await
+                .unwrap();
+        }
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batches_wait_for_their_slowest_domain() {
+        let inner = InMemoryTransport::new();
+        let transport = RateLimitedTransport::new(
+            Arc::new(inner.clone()),
+            Some(100.),
+            HashMap::from([("outlook.com".to_string(), 2.)]),
+        );
+        let batch: Vec<_> = ["a@outlook.com", "b@outlook.com", "c@outlook.com", "d@x.com"]
+            .into_iter()
+            .map(message_to)
+            .collect();
+
+        let start = Instant::now();
+        transport.send_batch(&batch).await.unwrap();
+
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+        assert_eq!(inner.messages().len(), 4);
+    }
+}
@@ -0,0 +1,161 @@
+use super::{EmailError, EmailMessage, EmailReceipt, EmailTransport};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use std::time::Duration;
+
+/// Relays through an SMTP server, upgrading the connection with STARTTLS unless told not to.
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, String)>,
+        starttls: bool,
+        timeout: Duration,
+    ) -> Result<Self, EmailError> {
+        // Without STARTTLS everything, credentials included, goes over the wire in the clear.
+        // That is only meant for local catch-all servers.
+        let mut builder = if starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        };
+        builder = builder.port(port).timeout(Some(timeout));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Ok(Self {
+            mailer: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<EmailReceipt, EmailError> {
+        let mime = message.to_mime()?;
+        let message_id = mime.headers().get_raw("Message-ID").map(str::to_string);
+        self.mailer.send(mime).await?;
+        Ok(EmailReceipt {
+            message_id,
+            provider: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmtpTransport;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailMessage, EmailOptions, EmailTransport};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Just enough of an SMTP server to accept one message, answering RCPT TO with `rcpt_reply`.
+    /// Returns every command the client sent.
+    async fn fake_smtp_server(rcpt_reply: &'static str) -> (u16, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut transcript = String::new();
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            let mut in_data = false;
+            while let Some(line) = lines.next_line().await.unwrap() {
+                transcript.push_str(&line);
+                transcript.push('\n');
+                let reply: &str = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    in_data = false;
+                    "250 queued\r\n"
+                } else if line.starts_with("EHLO") {
+                    "250-localhost\r\n250 AUTH PLAIN LOGIN\r\n"
+                } else if line.starts_with("AUTH") {
+                    "235 authenticated\r\n"
+                } else if line.starts_with("MAIL") {
+                    "250 ok\r\n"
+                } else if line.starts_with("RCPT") {
+                    rcpt_reply
+                } else if line == "DATA" {
+                    in_data = true;
+                    "354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    "250 ok\r\n"
+                };
+                writer.write_all(reply.as_bytes()).await.unwrap();
+                if reply.starts_with('5') {
+                    break;
+                }
+            }
+            transcript
+        });
+        (port, handle)
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            from: SubscriberEmail::new("from@example.com".into()).unwrap(),
+            to: SubscriberEmail::new("to@example.com".into()).unwrap(),
+            subject: "Over SMTP".into(),
+            html_body: "<p>html</p>".into(),
+            text_body: "text".into(),
+            options: EmailOptions::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn messages_are_relayed_with_authentication() {
+        let (port, server) = fake_smtp_server("250 ok\r\n").await;
+        let transport = SmtpTransport::new(
+            "127.0.0.1",
+            port,
+            Some(("user".into(), "password".into())),
+            false,
+            Duration::from_secs(5),
+        )
+        .unwrap();
+
+        let receipt = transport.send(&message()).await.unwrap();
+
+        drop(transport);
+        let transcript = server.await.unwrap();
+        assert!(transcript.contains("AUTH"));
+        assert!(transcript.contains("MAIL FROM:<from@example.com>"));
+        assert!(transcript.contains("RCPT TO:<to@example.com>"));
+        assert!(transcript.contains("Subject: Over SMTP"));
+        assert!(receipt.message_id.is_some());
+    }
+
+    #[tokio::test]
+    async fn rejected_recipients_are_permanent_failures() {
+        let (port, _server) = fake_smtp_server("550 no such user\r\n").await;
+        let transport =
+            SmtpTransport::new("127.0.0.1", port, None, false, Duration::from_secs(5)).unwrap();
+
+        let error = transport.send(&message()).await.unwrap_err();
+
+        assert!(!error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn mailbox_busy_replies_are_transient_failures() {
+        let (port, _server) = fake_smtp_server("450 mailbox busy\r\n").await;
+        let transport =
+            SmtpTransport::new("127.0.0.1", port, None, false, Duration::from_secs(5)).unwrap();
+
+        let error = transport.send(&message()).await.unwrap_err();
+
+        assert!(error.is_transient());
+    }
+}
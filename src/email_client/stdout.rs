@@ -0,0 +1,22 @@
+use super::{EmailError, EmailMessage, EmailReceipt, EmailTransport};
+use tokio::io::AsyncWriteExt;
+
+/// Prints each email as a MIME document to stdout.
+pub struct StdoutTransport;
+
+#[async_trait::async_trait]
+impl EmailTransport for StdoutTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<EmailReceipt, EmailError> {
+        let mime = message.to_mime()?;
+        let message_id = mime.headers().get_raw("Message-ID").map(str::to_string);
+        let mut output = mime.formatted();
+        output.extend_from_slice(b"\n\n");
+        let mut stdout = tokio::io::stdout();
+        stdout.write_all(&output).await?;
+        stdout.flush().await?;
+        Ok(EmailReceipt {
+            message_id,
+            provider: None,
+        })
+    }
+}
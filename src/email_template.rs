@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+/// How substituted values are written into the output. Literal template text is never escaped:
+/// it was written by an editor, not a subscriber.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Escape {
+    Html,
+    Text,
+}
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(String),
+}
+
+/// A body with `{{ name }}` placeholders, parsed once and rendered per recipient.
+#[derive(Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum TemplateError {
+    #[error("Unclosed placeholder starting at byte {0}.")]
+    Unclosed(usize),
+    #[error("Empty placeholder at byte {0}.")]
+    Empty(usize),
+    #[error("Unknown placeholder `{{{{ {0} }}}}`.")]
+    UnknownField(String),
+}
+
+impl Template {
+    /// Parses `source`, rejecting any placeholder that is not one of `fields`.
+    pub fn parse(source: &str, fields: &[&str]) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+        let mut offset = 0;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let after_open = &rest[start + 2..];
+            let end = after_open
+                .find("}}")
+                .ok_or(TemplateError::Unclosed(offset + start))?;
+            let name = after_open[..end].trim();
+            if name.is_empty() {
+                return Err(TemplateError::Empty(offset + start));
+            }
+            if !fields.contains(&name) {
+                return Err(TemplateError::UnknownField(name.to_string()));
+            }
+            parts.push(Part::Field(name.to_string()));
+            let consumed = start + 2 + end + 2;
+            rest = &rest[consumed..];
+            offset += consumed;
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    pub fn uses(&self, field: &str) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Field(name) if name == field))
+    }
+
+    /// Fields missing from `values` render as empty strings; `parse` already guaranteed they are
+    /// known names.
+    pub fn render(&self, values: &HashMap<&str, &str>, escape: Escape) -> String {
+        let mut output = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => output.push_str(text),
+                Part::Field(name) => {
+                    let value = values.get(name.as_str()).copied().unwrap_or_default();
+                    match escape {
+                        Escape::Html => output.push_str(&escape_html(value)),
+                        Escape::Text => output.push_str(value),
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+pub fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Escape, Template, TemplateError};
+    use claims::{assert_err_eq, assert_ok};
+    use std::collections::HashMap;
+
+    const FIELDS: &[&str] = &["name", "url"];
+
+    fn values<'a>(pairs: &[(&'a str, &'a str)]) -> HashMap<&'a str, &'a str> {
+        pairs.iter().copied().collect()
+    }
+
+    #[test]
+    fn placeholders_are_replaced_with_values() {
+        let template = Template::parse("Hi {{ name }}, see {{url}}.", FIELDS).unwrap();
+        let rendered = template.render(
+            &values(&[("name", "Ursula"), ("url", "http://x")]),
+            Escape::Text,
+        );
+        assert_eq!(rendered, "Hi Ursula, see http://x.");
+    }
+
+    #[test]
+    fn templates_without_placeholders_render_verbatim() {
+        let template = Template::parse("<p>Plain & simple</p>", FIELDS).unwrap();
+        assert_eq!(
+            template.render(&values(&[]), Escape::Html),
+            "<p>Plain & simple</p>"
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html_but_not_in_text() {
+        let template = Template::parse("<p>{{ name }}</p>", FIELDS).unwrap();
+        let name = values(&[("name", "<b>Tom & \"Jerry\"</b>")]);
+        assert_eq!(
+            template.render(&name, Escape::Html),
+            "<p>&lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt;</p>"
+        );
+        assert_eq!(
+            template.render(&name, Escape::Text),
+            "<p><b>Tom & \"Jerry\"</b></p>"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected() {
+        assert_err_eq!(
+            Template::parse("Hi {{ subscriber.age }}", FIELDS),
+            TemplateError::UnknownField("subscriber.age".to_string())
+        );
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_rejected() {
+        assert_err_eq!(
+            Template::parse("Hi {{ name", FIELDS),
+            TemplateError::Unclosed(3)
+        );
+    }
+
+    #[test]
+    fn empty_placeholders_are_rejected() {
+        assert_err_eq!(Template::parse("Hi {{ }}", FIELDS), TemplateError::Empty(3));
+    }
+
+    #[test]
+    fn single_braces_are_left_alone() {
+        assert_ok!(Template::parse("a { b } c }}", FIELDS));
+    }
+
+    #[test]
+    fn uses_reports_placeholders_in_the_template() {
+        let template = Template::parse("{{ url }}", FIELDS).unwrap();
+        assert!(template.uses("url"));
+        assert!(!template.uses("name"));
+    }
+}
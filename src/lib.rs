@@ -3,10 +3,13 @@ pub mod cloneable_auth_token;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_template;
 pub mod idempotency;
 pub mod newsletter_delivery_worker;
 pub mod routes;
 pub mod session_state;
+pub mod signed_token;
 pub mod startup;
+pub mod subscription_sweeper;
 pub mod telemetry;
 pub mod utils;
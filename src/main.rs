@@ -1,8 +1,11 @@
+use std::collections::HashMap;
 use std::fmt::{Debug, Display};
-use tokio::task::JoinError;
+use tokio::task::{Id, JoinError, JoinSet};
+use tokio_util::sync::CancellationToken;
 use zero2prod::configuration::get_configuration;
 use zero2prod::newsletter_delivery_worker::run_worker_until_stopped;
 use zero2prod::startup::Application;
+use zero2prod::subscription_sweeper::run_sweeper_until_stopped;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
@@ -11,35 +14,91 @@ async fn main() -> anyhow::Result<()> {
     init_subscriber(subscriber);
 
     let config = get_configuration().expect("Failed to read config file");
+    let shutdown_deadline = config.application.shutdown_deadline();
+    let shutdown = CancellationToken::new();
 
-    let application_task = tokio::spawn(
-        Application::build(config.clone())
-            .await?
-            .run_until_stopped(),
-    );
-    let worker_task = tokio::spawn(run_worker_until_stopped(config));
+    let application = Application::build(config.clone()).await?;
+    let server = application.handle();
 
+    let mut tasks = JoinSet::new();
+    let mut task_names = HashMap::new();
+    let id = tasks
+        .spawn(async move {
+            application
+                .run_until_stopped()
+                .await
+                .map_err(anyhow::Error::from)
+        })
+        .id();
+    task_names.insert(id, "API");
+    let id = tasks
+        .spawn(run_worker_until_stopped(config.clone(), shutdown.clone()))
+        .id();
+    task_names.insert(id, "Background Worker");
+    let id = tasks
+        .spawn(run_sweeper_until_stopped(config, shutdown.clone()))
+        .id();
+    task_names.insert(id, "Subscription Sweeper");
+
+    // Shut everything down on a signal, or as soon as any task exits on its own.
     tokio::select! {
-        o=application_task => report_exit("API", o),
-        o=worker_task => report_exit("Backgroun Worker", o),
+        _ = shutdown_signal() => tracing::info!("Received a shutdown signal"),
+        Some(outcome) = tasks.join_next_with_id() => report_exit(&task_names, outcome),
+    }
+    shutdown.cancel();
+    let drain = async {
+        // Stops accepting connections, then waits for in-flight requests.
+        server.stop(true).await;
+        while let Some(outcome) = tasks.join_next_with_id().await {
+            report_exit(&task_names, outcome);
+        }
+    };
+    if tokio::time::timeout(shutdown_deadline, drain)
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            "Shutdown took longer than {:?}, abandoning the remaining tasks",
+            shutdown_deadline
+        );
     }
     Ok(())
 }
 
-fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+async fn shutdown_signal() {
+    let ctrl_c = tokio::signal::ctrl_c();
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .expect("Failed to install the SIGTERM handler");
+        tokio::select! {
+            _ = ctrl_c => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = ctrl_c.await;
+}
+
+fn report_exit(
+    task_names: &HashMap<Id, &str>,
+    outcome: Result<(Id, Result<(), impl Debug + Display>), JoinError>,
+) {
+    let task_name = |id| task_names.get(&id).copied().unwrap_or("Unknown task");
     match outcome {
-        Ok(Ok(())) => {
-            tracing::info!("{} has exited", task_name)
+        Ok((id, Ok(()))) => {
+            tracing::info!("{} has exited", task_name(id))
         }
-        Ok(Err(e)) => {
-            tracing::error!(error.cause_chain=?e,error.message=%e, "{} failed", task_name)
+        Ok((id, Err(e))) => {
+            tracing::error!(error.cause_chain=?e,error.message=%e, "{} failed", task_name(id))
         }
         Err(e) => {
             tracing::error!(
                 error.cause_chain = ?e,
                     error.message = %e,
                     "{} task failed to complete",
-                    task_name,
+                    task_name(e.id()),
             )
         }
     }
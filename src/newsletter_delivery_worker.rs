@@ -1,8 +1,24 @@
-use crate::domain::{newsletters as newsletters_domain, SubscriberEmail};
-use crate::email_client::EmailClient;
-use crate::{configuration::Settings, startup::get_connection_pool};
+use crate::cloneable_auth_token::SecretAuthToken;
+use crate::configuration::{DatabaseSettings, Settings, WorkerSettings};
+use crate::domain::newsletter_deliveries::{self as deliveries_domain, DeliveryOutcome};
+use crate::domain::newsletter_queue::WORKER_CHANNEL;
+use crate::domain::{
+    dead_letters as dead_letters_domain, engagement as engagement_domain,
+    newsletters as newsletters_domain, preferences as preferences_domain,
+    unsubscribe as unsubscribe_domain, SubscriberEmail,
+};
+use crate::email_client::{EmailClient, EmailMessage, EmailOptions, EmailReceipt, MAX_BATCH_SIZE};
+use chrono::Utc;
+use rand::Rng;
+use sqlx::postgres::{PgListener, PgPoolOptions};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 use tracing::{field::display, Span};
 use uuid::Uuid;
 
@@ -11,63 +27,540 @@ pub enum ExecutionOutcome {
     EmptyQueue,
 }
 
-#[tracing::instrument(skip_all, fields(newsletter_id=tracing::field::Empty, subscriber_email=tracing::field::Empty,), err)]
+/// Failed deliveries are retried this many times before the task is dead-lettered.
+pub const MAX_DELIVERY_RETRIES: i16 = 5;
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+struct DeliveryTask {
+    newsletter_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+}
+
+/// Delivers a single queued newsletter email.
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &SecretAuthToken,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
+    try_execute_batch(pool, email_client, base_url, hmac_secret, 1).await
+}
+
+/// What happened to one task of a batch, applied to the queue once the batch has been sent.
+enum Delivery {
+    Skipped,
+    Undeliverable(String),
+    Pending,
+    Sent(EmailReceipt),
+    Failed { error: String, transient: bool },
+}
+
+/// Claims up to `batch_size` queued newsletter emails and sends them in a single provider call.
+/// Each row is then deleted, retried or dead-lettered according to its own result.
+///
+/// The claim is committed before the call and the results are settled in a second transaction,
+/// so the provider is never waited on with queue rows locked. If the worker dies in between, the
+/// claimed rows are not sent again but dead-lettered once the claim times out: emails may be
+/// missed, never doubled.
+#[tracing::instrument(skip_all, fields(batch_size=tracing::field::Empty), err)]
+pub async fn try_execute_batch(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &SecretAuthToken,
+    batch_size: usize,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut trx = pool.begin().await?;
+    let tasks = dequeue_tasks(&mut trx, batch_size.clamp(1, MAX_BATCH_SIZE)).await?;
+    if tasks.is_empty() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (trx, newsletter_id, email) = task.unwrap();
-    Span::current()
-        .record("newsletter_id", &display(newsletter_id))
-        .record("subscriber_email", &display(&email));
-
-    match SubscriberEmail::new(email.clone()) {
-        Ok(email) => {
-            let newsletter = newsletters_domain::get_newsletter(pool, newsletter_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &newsletter.title,
-                    &newsletter.html_content,
-                    &newsletter.text_content,
-                )
-                .await
-            {
+    Span::current().record("batch_size", tasks.len());
+
+    let subscribers = get_confirmed_subscribers(&mut trx, &tasks).await?;
+    let mut newsletters = HashMap::new();
+    let mut deliveries = Vec::with_capacity(tasks.len());
+    let mut messages = Vec::new();
+    for task in &tasks {
+        let key = (task.newsletter_id, task.subscriber_email.clone());
+        let delivery = match subscribers.get(&key) {
+            Some(subscriber) => {
+                if let Entry::Vacant(entry) = newsletters.entry(task.newsletter_id) {
+                    entry.insert(
+                        newsletters_domain::get_newsletter(&mut trx, task.newsletter_id).await?,
+                    );
+                }
+                let newsletter = &newsletters[&task.newsletter_id];
+                match compose(
+                    email_client,
+                    base_url,
+                    hmac_secret,
+                    task,
+                    subscriber,
+                    newsletter,
+                ) {
+                    Ok(message) => {
+                        messages.push(message);
+                        Delivery::Pending
+                    }
+                    Err(error) => Delivery::Undeliverable(error),
+                }
+            }
+            None => Delivery::Skipped,
+        };
+        deliveries.push(delivery);
+    }
+
+    // Tasks come back ordered by issue, so workers always lock issues in the same order when
+    // checking whether they're done.
+    let mut claimed = Vec::new();
+    for (task, delivery) in tasks.iter().zip(&deliveries) {
+        match delivery {
+            Delivery::Skipped => {
+                tracing::info!(
+                    newsletter_id = %task.newsletter_id,
+                    subscriber_email = %task.subscriber_email,
+                    "Skipping a subscriber who can no longer be mailed or has paused deliveries."
+                );
+                record_delivery(&mut trx, task, DeliveryOutcome::Skipped, None, None).await?;
+                delete_task(&mut trx, task).await?;
+            }
+            Delivery::Undeliverable(error) => {
                 tracing::error!(
-                    error.cause_chain = ?e,
-                    error.message = %e,
-                    "Failed to deliver newsletter to a confirmed subscriber. Skipping",
+                    newsletter_id = %task.newsletter_id,
+                    subscriber_email = %task.subscriber_email,
+                    error.message = %error,
+                    "Dead-lettering an email that can't be built.",
                 );
+                give_up_on_task(&mut trx, task, error).await?;
+            }
+            Delivery::Pending => claimed.push(task),
+            Delivery::Sent(_) | Delivery::Failed { .. } => {
+                unreachable!("nothing has been sent yet")
+            }
+        }
+    }
+    claim_tasks(&mut trx, &claimed).await?;
+    trx.commit().await?;
+    if claimed.is_empty() {
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+
+    let pending = deliveries
+        .iter_mut()
+        .filter(|delivery| matches!(delivery, Delivery::Pending));
+    match email_client.send_batch(&messages).await {
+        Ok(results) => {
+            for (delivery, result) in pending.zip(results) {
+                *delivery = match result {
+                    Ok(receipt) => Delivery::Sent(receipt),
+                    Err(e) => Delivery::Failed {
+                        error: e.to_string(),
+                        transient: e.is_transient(),
+                    },
+                };
+            }
+        }
+        Err(e) => {
+            tracing::warn!(error.cause_chain = ?e, "Failed to send a batch of newsletter emails.");
+            for delivery in pending {
+                *delivery = Delivery::Failed {
+                    error: e.to_string(),
+                    transient: e.is_transient(),
+                };
+            }
+        }
+    }
+
+    let mut trx = pool.begin().await?;
+    let still_claimed = lock_claimed_tasks(&mut trx, &claimed).await?;
+    for (task, delivery) in tasks.iter().zip(deliveries) {
+        if !still_claimed.contains(&(task.newsletter_id, task.subscriber_email.clone())) {
+            continue;
+        }
+        match delivery {
+            Delivery::Sent(receipt) => {
+                record_delivery(&mut trx, task, DeliveryOutcome::Sent, Some(&receipt), None)
+                    .await?;
+                delete_task(&mut trx, task).await?;
+            }
+            Delivery::Failed { error, transient } => {
+                handle_failed_delivery(&mut trx, task, &error, transient).await?
+            }
+            Delivery::Pending => unreachable!("every pending email gets a result"),
+            Delivery::Skipped | Delivery::Undeliverable(_) => {}
+        }
+    }
+    trx.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+fn compose(
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &SecretAuthToken,
+    task: &DeliveryTask,
+    subscriber: &ConfirmedSubscriber,
+    newsletter: &newsletters_domain::NewsLetter,
+) -> Result<EmailMessage, String> {
+    let email = SubscriberEmail::new(task.subscriber_email.clone())?;
+    let unsubscribe_link = unsubscribe_domain::unsubscribe_link(
+        base_url,
+        hmac_secret,
+        subscriber.id,
+        &newsletter.list_slug,
+    );
+    let preferences_link =
+        preferences_domain::preferences_link(base_url, hmac_secret, subscriber.id);
+    let recipient = newsletters_domain::NewsletterRecipient {
+        name: &subscriber.name,
+        email: email.as_ref(),
+    };
+    // Publishing validates templates, so only issues stored before that check fail here.
+    let rendered = newsletters_domain::render_newsletter(
+        task.newsletter_id,
+        &newsletter.title,
+        &newsletter.html_content,
+        &newsletter.text_content,
+        &recipient,
+        &unsubscribe_link,
+        &preferences_link,
+    )
+    .map_err(|e| e.to_string())?;
+    let html_body = if newsletter.track_engagement {
+        engagement_domain::add_tracking(
+            &rendered.html_body,
+            base_url,
+            hmac_secret,
+            task.newsletter_id,
+            subscriber.id,
+        )
+    } else {
+        rendered.html_body
+    };
+    Ok(email_client.message(
+        &email,
+        &rendered.subject,
+        &html_body,
+        &rendered.text_body,
+        &rendered.options,
+    ))
+}
+
+struct OutboxEmail {
+    email_id: Uuid,
+    recipient: String,
+    subject: String,
+    html_content: String,
+    text_content: String,
+    n_retries: i16,
+}
+
+/// Delivers one email from the transactional outbox, such as a subscription confirmation.
+#[tracing::instrument(skip_all, fields(email_id=tracing::field::Empty, recipient=tracing::field::Empty, provider=tracing::field::Empty), err)]
+pub async fn try_execute_outbox_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let task = dequeue_outbox_email(pool).await?;
+    if task.is_none() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    let (trx, email) = task.unwrap();
+    Span::current()
+        .record("email_id", display(email.email_id))
+        .record("recipient", display(&email.recipient));
+
+    let recipient = match SubscriberEmail::new(email.recipient.clone()) {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            tracing::error!(error.message = %e, "Giving up on an email to an invalid address.");
+            fail_outbox_email(trx, &email, &e).await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+
+    match email_client
+        .send_email_with_options(
+            &recipient,
+            &email.subject,
+            &email.html_content,
+            &email.text_content,
+            &EmailOptions::default(),
+        )
+        .await
+    {
+        Ok(receipt) => {
+            if let Some(provider) = &receipt.provider {
+                Span::current().record("provider", display(provider));
             }
+            delete_outbox_email(trx, email.email_id).await?
+        }
+        Err(e) if e.is_transient() && email.n_retries < MAX_DELIVERY_RETRIES => {
+            let delay = retry_delay(email.n_retries);
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                n_retries = email.n_retries,
+                "Failed to deliver an outbox email. Retrying in {:?}",
+                delay,
+            );
+            reschedule_outbox_email(trx, &email, delay, &e.to_string()).await?
         }
         Err(e) => {
             tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
-                "Skipping a confirmed subscriber, their details are invalid.",
+                n_retries = email.n_retries,
+                "Failed to deliver an outbox email. Giving up",
             );
+            fail_outbox_email(trx, &email, &e.to_string()).await?
         }
     }
-    delete_task(trx, newsletter_id, &email).await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+async fn handle_failed_delivery(
+    trx: &mut PgTransaction,
+    task: &DeliveryTask,
+    error: &str,
+    transient: bool,
+) -> Result<(), anyhow::Error> {
+    if transient && task.n_retries < MAX_DELIVERY_RETRIES {
+        let delay = retry_delay(task.n_retries);
+        tracing::warn!(
+            newsletter_id = %task.newsletter_id,
+            subscriber_email = %task.subscriber_email,
+            error.message = %error,
+            n_retries = task.n_retries,
+            "Failed to deliver newsletter to a confirmed subscriber. Retrying in {:?}",
+            delay,
+        );
+        record_delivery(trx, task, DeliveryOutcome::Retrying, None, Some(error)).await?;
+        reschedule_task(trx, task, delay).await
+    } else {
+        tracing::error!(
+            newsletter_id = %task.newsletter_id,
+            subscriber_email = %task.subscriber_email,
+            error.message = %error,
+            n_retries = task.n_retries,
+            "Failed to deliver newsletter to a confirmed subscriber. Dead-lettering",
+        );
+        give_up_on_task(trx, task, error).await
+    }
+}
+
+async fn give_up_on_task(
+    trx: &mut PgTransaction,
+    task: &DeliveryTask,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    record_delivery(trx, task, DeliveryOutcome::Failed, None, Some(error)).await?;
+    dead_letters_domain::dead_letter_task(
+        trx,
+        task.newsletter_id,
+        &task.subscriber_email,
+        task.n_retries,
+        error,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn record_delivery(
+    trx: &mut PgTransaction,
+    task: &DeliveryTask,
+    outcome: DeliveryOutcome,
+    receipt: Option<&EmailReceipt>,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    deliveries_domain::record_delivery(
+        trx,
+        task.newsletter_id,
+        &task.subscriber_email,
+        outcome,
+        receipt,
+        error,
+    )
+    .await
+}
+
+// Exponential backoff with "equal jitter": half the delay is fixed, the other half random, so
+// tasks that failed together don't all come back at once.
+fn retry_delay(n_retries: i16) -> Duration {
+    let exponential = BASE_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(n_retries.max(0) as u32))
+        .min(MAX_RETRY_DELAY);
+    let half = exponential / 2;
+    half + half.mul_f64(rand::thread_rng().gen::<f64>())
+}
+
 type PgTransaction = Transaction<'static, Postgres>;
 
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+async fn dequeue_tasks(
+    trx: &mut PgTransaction,
+    batch_size: usize,
+) -> Result<Vec<DeliveryTask>, anyhow::Error> {
+    let r = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT newsletter_id, subscriber_email, n_retries
+            FROM newsletter_delivery_queue
+        WHERE execute_after <= now() AND claimed_at IS NULL
+        ORDER BY newsletter_id
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT $1
+    "#,
+        batch_size as i64
+    )
+    .fetch_all(&mut **trx)
+    .await?;
+    Ok(r)
+}
+
+struct ConfirmedSubscriber {
+    newsletter_id: Uuid,
+    email: String,
+    id: Uuid,
+    name: String,
+}
+
+/// The recipients of `tasks` that are still confirmed on the list each issue went to, haven't
+/// been suppressed and haven't paused deliveries since, keyed by issue and address. Leaving one
+/// list doesn't stop issues already queued for another.
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscribers(
+    trx: &mut PgTransaction,
+    tasks: &[DeliveryTask],
+) -> Result<HashMap<(Uuid, String), ConfirmedSubscriber>, anyhow::Error> {
+    let newsletter_ids: Vec<Uuid> = tasks.iter().map(|t| t.newsletter_id).collect();
+    let emails: Vec<String> = tasks.iter().map(|t| t.subscriber_email.clone()).collect();
+    let rows = sqlx::query_as!(
+        ConfirmedSubscriber,
+        r#"
+    SELECT t.newsletter_id AS "newsletter_id!", s.email, s.id, s.name
+    FROM UNNEST($1::uuid[], $2::text[]) AS t (newsletter_id, email)
+    JOIN newsletters n ON n.newsletter_id = t.newsletter_id
+    JOIN subscriptions s ON s.email = t.email
+    JOIN list_memberships m ON m.subscriber_id = s.id AND m.list_slug = n.list_slug
+    WHERE s.status = 'confirmed'
+        AND m.status = 'confirmed'
+        AND NOT EXISTS (SELECT 1 FROM suppressed_emails WHERE email = lower(s.email))
+        AND (s.paused_until IS NULL OR s.paused_until <= now())"#,
+        &newsletter_ids,
+        &emails
+    )
+    .fetch_all(&mut **trx)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|subscriber| {
+            (
+                (subscriber.newsletter_id, subscriber.email.clone()),
+                subscriber,
+            )
+        })
+        .collect())
+}
+
+#[tracing::instrument(skip_all)]
+async fn claim_tasks(
+    trx: &mut PgTransaction,
+    tasks: &[&DeliveryTask],
+) -> Result<(), anyhow::Error> {
+    let newsletter_ids: Vec<Uuid> = tasks.iter().map(|t| t.newsletter_id).collect();
+    let emails: Vec<String> = tasks.iter().map(|t| t.subscriber_email.clone()).collect();
+    let query = sqlx::query!(
+        r#"
+    UPDATE newsletter_delivery_queue SET claimed_at = now()
+    WHERE (newsletter_id, subscriber_email) IN (
+        SELECT * FROM UNNEST($1::uuid[], $2::text[])
+    )"#,
+        &newsletter_ids,
+        &emails
+    );
+    trx.execute(query).await?;
+    Ok(())
+}
+
+/// The claimed tasks whose rows are still there to settle. Erasing a subscriber deletes theirs
+/// while the provider call is under way, and nothing about them may be written back afterwards.
+#[tracing::instrument(skip_all)]
+async fn lock_claimed_tasks(
+    trx: &mut PgTransaction,
+    tasks: &[&DeliveryTask],
+) -> Result<HashSet<(Uuid, String)>, anyhow::Error> {
+    let newsletter_ids: Vec<Uuid> = tasks.iter().map(|t| t.newsletter_id).collect();
+    let emails: Vec<String> = tasks.iter().map(|t| t.subscriber_email.clone()).collect();
+    let rows = sqlx::query!(
+        r#"
+    SELECT newsletter_id, subscriber_email FROM newsletter_delivery_queue
+    WHERE (newsletter_id, subscriber_email) IN (
+        SELECT * FROM UNNEST($1::uuid[], $2::text[])
+    )
+    ORDER BY newsletter_id, subscriber_email
+    FOR UPDATE"#,
+        &newsletter_ids,
+        &emails
+    )
+    .fetch_all(&mut **trx)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.newsletter_id, row.subscriber_email))
+        .collect())
+}
+
+#[tracing::instrument(skip_all)]
+async fn reschedule_task(
+    trx: &mut PgTransaction,
+    task: &DeliveryTask,
+    delay: Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + delay;
+    let query = sqlx::query!(
+        r#"
+    UPDATE newsletter_delivery_queue
+    SET n_retries = n_retries + 1, execute_after = $3, claimed_at = NULL
+    WHERE newsletter_id = $1 AND subscriber_email = $2"#,
+        task.newsletter_id,
+        task.subscriber_email,
+        execute_after
+    );
+    trx.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(trx: &mut PgTransaction, task: &DeliveryTask) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+    DELETE FROM newsletter_delivery_queue WHERE newsletter_id = $1 AND subscriber_email = $2"#,
+        task.newsletter_id,
+        task.subscriber_email
+    );
+    trx.execute(query).await?;
+    newsletters_domain::mark_sent_if_drained(trx, task.newsletter_id).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_outbox_email(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+) -> Result<Option<(PgTransaction, OutboxEmail)>, anyhow::Error> {
     let mut trx = pool.begin().await?;
 
-    let r = sqlx::query!(
+    let r = sqlx::query_as!(
+        OutboxEmail,
         r#"
-        SELECT newsletter_id, subscriber_email
-            FROM newsletter_delivery_queue
+        SELECT email_id, recipient, subject, html_content, text_content, n_retries
+            FROM email_outbox
+        WHERE failed_at IS NULL AND execute_after <= now()
+        ORDER BY created_at
         FOR UPDATE
         SKIP LOCKED
         LIMIT 1
@@ -75,46 +568,229 @@ async fn dequeue_task(
     )
     .fetch_optional(&mut *trx)
     .await?;
-    if let Some(r) = r {
-        Ok(Some((trx, r.newsletter_id, r.subscriber_email)))
-    } else {
-        Ok(None)
-    }
+    Ok(r.map(|email| (trx, email)))
 }
 
 #[tracing::instrument(skip_all)]
-async fn delete_task(
+async fn reschedule_outbox_email(
     mut trx: PgTransaction,
-    newsletter_id: Uuid,
-    email: &str,
+    email: &OutboxEmail,
+    delay: Duration,
+    error: &str,
 ) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + delay;
     let query = sqlx::query!(
         r#"
-    DELETE FROM newsletter_delivery_queue WHERE newsletter_id = $1 AND subscriber_email = $2"#,
-        newsletter_id,
-        email
+    UPDATE email_outbox
+    SET n_retries = n_retries + 1, execute_after = $2, last_error = $3
+    WHERE email_id = $1"#,
+        email.email_id,
+        execute_after,
+        error
     );
     trx.execute(query).await?;
     trx.commit().await?;
     Ok(())
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+// Failed emails stay in the outbox, out of the worker's way, so they can be inspected.
+#[tracing::instrument(skip_all)]
+async fn fail_outbox_email(
+    mut trx: PgTransaction,
+    email: &OutboxEmail,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+    UPDATE email_outbox
+    SET failed_at = now(), last_error = $2
+    WHERE email_id = $1"#,
+        email.email_id,
+        error
+    );
+    trx.execute(query).await?;
+    trx.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_outbox_email(mut trx: PgTransaction, email_id: Uuid) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(r#"DELETE FROM email_outbox WHERE email_id = $1"#, email_id);
+    trx.execute(query).await?;
+    trx.commit().await?;
+    Ok(())
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_secret: SecretAuthToken,
+    settings: WorkerSettings,
+    new_work: Arc<Notify>,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    // Shutdown is only checked between iterations: abandoning a batch after its claim would leave
+    // its rows claimed and its emails possibly unsent.
+    while !shutdown.is_cancelled() {
+        // Registered before looking at the queues, so work announced while we're busy still
+        // wakes us up afterwards.
+        let wake_up = new_work.notified();
+        tokio::pin!(wake_up);
+        wake_up.as_mut().enable();
+
+        if let Err(e) = newsletters_domain::enqueue_due_newsletters(&pool).await {
+            tracing::error!(error.cause_chain = ?e, "Failed to enqueue scheduled newsletters");
+        }
+        match dead_letters_domain::dead_letter_stale_claims(&pool, settings.claim_timeout()).await {
+            Ok(0) => {}
+            Ok(n) => tracing::warn!(n, "Dead-lettered emails left claimed by a stopped worker"),
+            Err(e) => tracing::error!(error.cause_chain = ?e, "Failed to sweep stale claims"),
+        }
+        let outbox = try_execute_outbox_task(&pool, &email_client).await;
+        let newsletters = try_execute_batch(
+            &pool,
+            &email_client,
+            &base_url,
+            &hmac_secret,
+            settings.batch_size,
+        )
+        .await;
+        match (outbox, newsletters) {
+            (Ok(ExecutionOutcome::EmptyQueue), Ok(ExecutionOutcome::EmptyQueue)) => {
+                // Polling still catches notifications lost while the listener was reconnecting.
+                tokio::select! {
+                    _ = wake_up => {}
+                    _ = shutdown.cancelled() => {}
+                    _ = tokio::time::sleep(settings.idle_poll_interval()) => {}
+                }
+            }
+            (Err(_), _) | (_, Err(_)) => {
+                tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = tokio::time::sleep(settings.error_backoff()) => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Forwards notifications on `WORKER_CHANNEL` to idle consumers. It uses a connection of its own
+/// so it never competes with the consumers for the pool.
+async fn listen_for_new_work(
+    database: DatabaseSettings,
+    new_work: Arc<Notify>,
+    error_backoff: Duration,
+) -> Result<(), anyhow::Error> {
+    let listener_pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect_lazy_with(database.connect_options());
     loop {
-        match try_execute_task(&pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+        let mut listener = match PgListener::connect_with(&listener_pool).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, "Failed to connect the queue listener");
+                tokio::time::sleep(error_backoff).await;
+                continue;
             }
-            Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+        };
+        if let Err(e) = listener.listen(WORKER_CHANNEL).await {
+            tracing::error!(error.cause_chain = ?e, "Failed to listen for queued work");
+            tokio::time::sleep(error_backoff).await;
+            continue;
+        }
+        loop {
+            match listener.try_recv().await {
+                // `None` means the connection dropped and was re-established. Anything sent in
+                // between is lost, so have the consumers look at the queues either way.
+                Ok(_) => new_work.notify_waiters(),
+                Err(e) => {
+                    tracing::error!(error.cause_chain = ?e, "Lost the queue listener");
+                    tokio::time::sleep(error_backoff).await;
+                    break;
+                }
             }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
         }
     }
 }
 
-pub async fn run_worker_until_stopped(config: Settings) -> Result<(), anyhow::Error> {
-    let conn_pool = get_connection_pool(&config.database);
+/// Runs `worker.concurrency` consumers side by side. `SKIP LOCKED` keeps them off each other's
+/// rows, so they need no coordination beyond sharing the pool. Returns once every consumer has
+/// finished its current task after `shutdown` is cancelled.
+pub async fn run_worker_until_stopped(
+    config: Settings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let settings = config.worker;
+    let concurrency = settings.concurrency.max(1);
+    // Each consumer holds at most one connection at a time, its current transaction.
+    let conn_pool = PgPoolOptions::new()
+        .max_connections(concurrency as u32)
+        .connect_lazy_with(config.database.connect_options());
+    let email_client = config.email_client.client();
+    let new_work = Arc::new(Notify::new());
+
+    // A child token, so a failing consumer can stop its siblings without stopping the app.
+    let shutdown = shutdown.child_token();
 
-    worker_loop(conn_pool, config.email_client.client()).await
+    let listener = tokio::spawn(listen_for_new_work(
+        config.database.clone(),
+        new_work.clone(),
+        settings.error_backoff(),
+    ));
+    let mut consumers = JoinSet::new();
+    for _ in 0..concurrency {
+        consumers.spawn(worker_loop(
+            conn_pool.clone(),
+            email_client.clone(),
+            config.application.base_url.clone(),
+            config.application.hmac_secret.clone(),
+            settings.clone(),
+            new_work.clone(),
+            shutdown.clone(),
+        ));
+    }
+    let mut outcome = Ok(());
+    while let Some(joined) = consumers.join_next().await {
+        if let Err(e) = joined.map_err(anyhow::Error::from).and_then(|r| r) {
+            // Let the others finish what they're doing rather than abort them mid-send.
+            shutdown.cancel();
+            if outcome.is_ok() {
+                outcome = Err(e);
+            }
+        }
+    }
+    listener.abort();
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_delay, BASE_RETRY_DELAY, MAX_RETRY_DELAY};
+
+    #[test]
+    fn the_first_retry_waits_between_half_and_all_of_the_base_delay() {
+        for _ in 0..100 {
+            let delay = retry_delay(0);
+            assert!(delay >= BASE_RETRY_DELAY / 2 && delay <= BASE_RETRY_DELAY);
+        }
+    }
+
+    #[test]
+    fn retry_delays_grow_exponentially() {
+        for _ in 0..100 {
+            let delay = retry_delay(3);
+            assert!(delay >= BASE_RETRY_DELAY * 4 && delay <= BASE_RETRY_DELAY * 8);
+        }
+    }
+
+    #[test]
+    fn retry_delays_are_capped() {
+        for n_retries in [12, i16::MAX] {
+            let delay = retry_delay(n_retries);
+            assert!(delay >= MAX_RETRY_DELAY / 2 && delay <= MAX_RETRY_DELAY);
+        }
+    }
 }
@@ -0,0 +1,52 @@
+use crate::authentication::UserId;
+use crate::domain::dead_letters as dead_letters_domain;
+use crate::utils::{e400, e500};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[tracing::instrument(name = "List dead-lettered deliveries", skip_all, fields(user_id=%*user_id))]
+pub async fn get_dead_letters(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let dead_letters = dead_letters_domain::list_dead_letters(&pool)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(dead_letters))
+}
+
+/// Without a filter, `all` has to be set: an empty body shouldn't resend every dead letter.
+#[derive(serde::Deserialize, Debug)]
+pub struct RequeueBodyData {
+    newsletter_id: Option<Uuid>,
+    subscriber_email: Option<String>,
+    #[serde(default)]
+    all: bool,
+}
+
+#[derive(serde::Serialize)]
+struct RequeueResponse {
+    requeued: u64,
+}
+
+#[tracing::instrument(name = "Requeue dead-lettered deliveries", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn requeue_dead_letters(
+    body: web::Json<RequeueBodyData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !body.all && body.newsletter_id.is_none() && body.subscriber_email.is_none() {
+        return Err(e400(
+            "Give a newsletter_id or subscriber_email, or set all to requeue every dead letter.",
+        ));
+    }
+    let requeued = dead_letters_domain::requeue_dead_letters(
+        &pool,
+        body.newsletter_id,
+        body.subscriber_email.as_deref(),
+    )
+    .await
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(RequeueResponse { requeued }))
+}
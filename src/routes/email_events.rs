@@ -0,0 +1,203 @@
+use crate::cloneable_auth_token::SecretAuthToken;
+use crate::domain::email_events::{self as email_events_domain, EmailEvent, EmailEventKind};
+use crate::routes::error_chain_fmt;
+use crate::startup::WebhookSecret;
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+// Bounce types that mean the address will never accept mail. Everything else (full mailboxes,
+// greylisting, auto-replies...) is recorded but left alone.
+const PERMANENT_BOUNCE_TYPES: &[&str] = &["HardBounce", "BadEmailAddress"];
+
+/// The subset of Postmark's webhook payloads we act on.
+#[derive(serde::Deserialize)]
+#[serde(tag = "RecordType")]
+enum ProviderEvent {
+    Bounce(BounceRecord),
+    SpamComplaint(BounceRecord),
+    Delivery(DeliveryRecord),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BounceRecord {
+    email: String,
+    #[serde(default)]
+    r#type: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    description: Option<String>,
+    bounced_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DeliveryRecord {
+    recipient: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    details: Option<String>,
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+impl ProviderEvent {
+    fn into_email_event(self) -> Option<EmailEvent> {
+        let event = match self {
+            ProviderEvent::Bounce(record) => {
+                let kind = if PERMANENT_BOUNCE_TYPES.contains(&record.r#type.as_str()) {
+                    EmailEventKind::HardBounce
+                } else {
+                    EmailEventKind::SoftBounce
+                };
+                record.into_email_event(kind)
+            }
+            ProviderEvent::SpamComplaint(record) => {
+                record.into_email_event(EmailEventKind::Complaint)
+            }
+            ProviderEvent::Delivery(record) => EmailEvent {
+                kind: EmailEventKind::Delivery,
+                email: record.recipient,
+                provider_message_id: record.message_id,
+                detail: record.details,
+                occurred_at: record.delivered_at.unwrap_or_else(Utc::now),
+            },
+            ProviderEvent::Other => return None,
+        };
+        Some(event)
+    }
+}
+
+impl BounceRecord {
+    fn into_email_event(self, kind: EmailEventKind) -> EmailEvent {
+        EmailEvent {
+            kind,
+            email: self.email,
+            provider_message_id: self.message_id,
+            detail: self.description,
+            occurred_at: self.bounced_at.unwrap_or_else(Utc::now),
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            WebhookError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            WebhookError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut resp = HttpResponse::new(self.status_code());
+        if let WebhookError::AuthError(_) = self {
+            let header_val = HeaderValue::from_str(r#"Basic realm="webhooks""#).unwrap();
+            resp.headers_mut()
+                .insert(header::WWW_AUTHENTICATE, header_val);
+        }
+        resp
+    }
+}
+
+/// Bounce, complaint and delivery notifications from the email provider. Hard bounces and
+/// complaints suppress the address for good.
+#[tracing::instrument(name = "Receive an email event", skip_all)]
+pub async fn receive_email_event(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    secret: web::Data<WebhookSecret>,
+) -> Result<HttpResponse, WebhookError> {
+    authenticate(request.headers(), &body, &secret.0).map_err(WebhookError::AuthError)?;
+
+    let event: ProviderEvent = serde_json::from_slice(&body)
+        .map_err(|e| WebhookError::ValidationError(format!("Malformed event: {}", e)))?;
+    // Acknowledge record types we don't care about, or the provider keeps retrying them.
+    let Some(event) = event.into_email_event() else {
+        return Ok(HttpResponse::Ok().finish());
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    email_events_domain::record_email_event(&mut transaction, &event)
+        .await
+        .context("Failed to record an email event.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit transaction to record an email event.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Accepts either an HMAC-SHA256 signature of the raw body, or Basic credentials whose password
+/// is the shared secret (Postmark can only do the latter).
+fn authenticate(
+    headers: &HeaderMap,
+    body: &[u8],
+    secret: &SecretAuthToken,
+) -> Result<(), anyhow::Error> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.expose_secret().token.as_bytes());
+
+    if let Some(signature) = headers.get(SIGNATURE_HEADER) {
+        let signature = base64::engine::general_purpose::STANDARD
+            .decode(signature.as_bytes())
+            .context("The signature is not valid base64.")?;
+        return hmac::verify(&key, body, &signature)
+            .map_err(|_| anyhow::anyhow!("The signature does not match the body."));
+    }
+
+    let password = basic_password(headers)?;
+    // Comparing MACs rather than the strings themselves keeps the check constant-time.
+    let expected = hmac::sign(&key, secret.expose_secret().token.as_bytes());
+    hmac::verify(&key, password.as_bytes(), expected.as_ref())
+        .map_err(|_| anyhow::anyhow!("Invalid webhook credentials."))
+}
+
+fn basic_password(headers: &HeaderMap) -> Result<String, anyhow::Error> {
+    let encoded = headers
+        .get(header::AUTHORIZATION)
+        .context("Neither a signature nor an 'Authorization' header was provided.")?
+        .to_str()
+        .context("The 'Authorization' header was not valid UTF8.")?
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .context("Failed to decode the Basic credentials.")?;
+    let credentials =
+        String::from_utf8(decoded).context("The Basic credentials were not valid UTF8.")?;
+    let (_, password) = credentials
+        .split_once(':')
+        .context("Missing password in the Basic credentials.")?;
+    Ok(password.to_string())
+}
@@ -0,0 +1,102 @@
+use crate::authentication::UserId;
+use crate::domain::engagement::{self as engagement_domain, EngagementKind};
+use crate::startup::HmacSecret;
+use crate::utils::e500;
+use actix_web::http::header;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// The smallest transparent GIF there is.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[derive(serde::Deserialize)]
+pub struct TrackingParameters {
+    token: String,
+}
+
+// Losing an event is better than breaking the email for the reader, so recording errors are
+// only logged.
+async fn record(
+    pool: &PgPool,
+    newsletter_id: Uuid,
+    subscriber_id: Uuid,
+    kind: EngagementKind,
+    url: Option<&str>,
+) {
+    if let Err(e) =
+        engagement_domain::record_engagement(pool, newsletter_id, subscriber_id, kind, url).await
+    {
+        tracing::error!(error.cause_chain = ?e, "Failed to record an engagement event.");
+    }
+}
+
+#[tracing::instrument(name = "Track a newsletter open", skip(parameters, pool, secret))]
+pub async fn track_open(
+    parameters: web::Query<TrackingParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let (newsletter_id, subscriber_id) =
+        match engagement_domain::parse_open_token(&secret.0, &parameters.token) {
+            Some(recipient) => recipient,
+            None => return HttpResponse::NotFound().finish(),
+        };
+    record(
+        &pool,
+        newsletter_id,
+        subscriber_id,
+        EngagementKind::Open,
+        None,
+    )
+    .await;
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(PIXEL)
+}
+
+// The target comes from the signed token, never from the request, so this can't be abused as
+// an open redirect.
+#[tracing::instrument(name = "Track a newsletter click", skip(parameters, pool, secret))]
+pub async fn track_click(
+    parameters: web::Query<TrackingParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let (newsletter_id, subscriber_id, url) =
+        match engagement_domain::parse_click_token(&secret.0, &parameters.token) {
+            Some(click) => click,
+            None => return HttpResponse::NotFound().finish(),
+        };
+    record(
+        &pool,
+        newsletter_id,
+        subscriber_id,
+        EngagementKind::Click,
+        Some(&url),
+    )
+    .await;
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .finish()
+}
+
+#[tracing::instrument(name = "Get newsletter engagement report.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn get_newsletter_engagement(
+    newsletter_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match engagement_domain::get_engagement_report(&pool, newsletter_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(report) => Ok(HttpResponse::Ok().json(report)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
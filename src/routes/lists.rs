@@ -0,0 +1,42 @@
+use crate::authentication::UserId;
+use crate::domain::lists as lists_domain;
+use crate::utils::{e400, e500};
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[derive(Deserialize, Debug)]
+pub struct MailingListData {
+    slug: String,
+    name: String,
+}
+
+#[tracing::instrument(name = "List mailing lists.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn list_mailing_lists(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = lists_domain::list_lists(&pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(lists))
+}
+
+#[tracing::instrument(name = "Create mailing list.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn create_mailing_list(
+    body: web::Json<MailingListData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let slug = lists_domain::parse_slug(&body.slug).map_err(e400)?;
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(e400("A list needs a name."));
+    }
+    if lists_domain::create_list(&pool, &slug, name)
+        .await
+        .map_err(e500)?
+    {
+        Ok(HttpResponse::Created().json(serde_json::json!({ "slug": slug, "name": name })))
+    } else {
+        Ok(HttpResponse::Conflict().body(format!("There already is a list called '{}'.", slug)))
+    }
+}
@@ -1,15 +1,39 @@
+mod dead_letters;
+mod email_events;
+mod engagement;
 mod health_check;
+mod lists;
 mod login;
 mod logout;
+mod newsletter_drafts;
 mod newsletters;
 mod password;
+mod segments;
+mod subscriber_csv;
+mod subscribers;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_preferences;
+mod subscriptions_privacy;
+mod subscriptions_resend;
+mod subscriptions_unsubscribe;
 
+pub use dead_letters::*;
+pub use email_events::*;
+pub use engagement::*;
 pub use health_check::*;
+pub use lists::*;
 pub use login::*;
 pub use logout::*;
+pub use newsletter_drafts::*;
 pub use newsletters::*;
 pub use password::*;
+pub use segments::*;
+pub use subscriber_csv::*;
+pub use subscribers::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_privacy::*;
+pub use subscriptions_resend::*;
+pub use subscriptions_unsubscribe::*;
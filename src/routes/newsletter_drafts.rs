@@ -0,0 +1,271 @@
+use crate::authentication::UserId;
+use crate::domain::newsletters::{NewsletterRecipient, PublishDraftOutcome};
+use crate::domain::SubscriberEmail;
+use crate::domain::{
+    engagement as engagement_domain, lists as lists_domain, newsletters as newsletters_domain,
+    preferences as preferences_domain, unsubscribe as unsubscribe_domain,
+};
+use crate::email_client::EmailClient;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::utils::{e400, e500};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize, Debug)]
+pub struct DraftData {
+    title: String,
+    content: DraftContent,
+    #[serde(default)]
+    track_engagement: bool,
+    /// The mailing list to send to; the default list if left out.
+    list: Option<String>,
+    /// Narrows the list down to one of its segments.
+    segment: Option<Uuid>,
+}
+
+impl DraftData {
+    /// Checked the same way `publish_newsletter` checks its audience.
+    async fn audience(
+        &self,
+        pool: &PgPool,
+    ) -> Result<newsletters_domain::Audience<'_>, actix_web::Error> {
+        let audience = newsletters_domain::Audience {
+            list_slug: self.list.as_deref().unwrap_or(lists_domain::DEFAULT_LIST),
+            segment_id: self.segment,
+        };
+        match newsletters_domain::check_audience(pool, &audience)
+            .await
+            .map_err(e500)?
+        {
+            Some(problem) => Err(e400(problem)),
+            None => Ok(audience),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DraftContent {
+    html: String,
+    text: String,
+}
+
+#[tracing::instrument(name = "Create newsletter draft.", skip(body, pool, user_id), fields(user_id=%*user_id))]
+pub async fn create_draft(
+    body: web::Json<DraftData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let audience = body.audience(&pool).await?;
+    let newsletter_id = newsletters_domain::insert_draft(
+        &pool,
+        &body.title,
+        &body.content.text,
+        &body.content.html,
+        body.track_engagement,
+        &audience,
+    )
+    .await
+    .map_err(e500)?;
+    Ok(HttpResponse::Created().json(serde_json::json!({ "newsletter_id": newsletter_id })))
+}
+
+#[tracing::instrument(name = "List newsletter drafts.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn list_drafts(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let drafts = newsletters_domain::list_drafts(&pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(drafts))
+}
+
+#[tracing::instrument(name = "Get newsletter draft.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn get_draft(
+    newsletter_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match newsletters_domain::get_draft(&pool, newsletter_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(draft) => Ok(HttpResponse::Ok().json(draft)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+#[tracing::instrument(name = "Update newsletter draft.", skip(body, pool, user_id), fields(user_id=%*user_id))]
+pub async fn update_draft(
+    newsletter_id: web::Path<Uuid>,
+    body: web::Json<DraftData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let audience = body.audience(&pool).await?;
+    if newsletters_domain::update_draft(
+        &pool,
+        newsletter_id.into_inner(),
+        &body.title,
+        &body.content.text,
+        &body.content.html,
+        body.track_engagement,
+        &audience,
+    )
+    .await
+    .map_err(e500)?
+    {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
+#[tracing::instrument(name = "Delete newsletter draft.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn delete_draft(
+    newsletter_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if newsletters_domain::delete_draft(&pool, newsletter_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PublishDraftData {
+    send_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Publish newsletter draft.", skip(body, pool, user_id), fields(user_id=%*user_id))]
+pub async fn publish_draft(
+    newsletter_id: web::Path<Uuid>,
+    body: web::Json<PublishDraftData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match newsletters_domain::publish_draft(&pool, newsletter_id.into_inner(), body.send_at)
+        .await
+        .map_err(e500)?
+    {
+        PublishDraftOutcome::Published => Ok(HttpResponse::Ok().finish()),
+        PublishDraftOutcome::NotFound => Ok(HttpResponse::NotFound().finish()),
+        PublishDraftOutcome::InvalidTemplate(e) => Err(e400(e)),
+    }
+}
+
+const PREVIEW_NAME: &str = "Preview Subscriber";
+const PREVIEW_EMAIL: &str = "subscriber@example.com";
+
+// Previews and test sends aren't addressed to a subscriber, so their unsubscribe, preference and
+// tracking links point at the nil id: they look and verify like the real thing but match nobody.
+fn render_draft(
+    draft: &newsletters_domain::Draft,
+    email: &str,
+    base_url: &str,
+    hmac_secret: &HmacSecret,
+) -> Result<newsletters_domain::RenderedNewsletter, actix_web::Error> {
+    let unsubscribe_link = unsubscribe_domain::unsubscribe_link(
+        base_url,
+        &hmac_secret.0,
+        Uuid::nil(),
+        &draft.list_slug,
+    );
+    let preferences_link =
+        preferences_domain::preferences_link(base_url, &hmac_secret.0, Uuid::nil());
+    let recipient = NewsletterRecipient {
+        name: PREVIEW_NAME,
+        email,
+    };
+    let mut rendered = newsletters_domain::render_newsletter(
+        draft.newsletter_id,
+        &draft.title,
+        &draft.html_content,
+        &draft.text_content,
+        &recipient,
+        &unsubscribe_link,
+        &preferences_link,
+    )
+    .map_err(e400)?;
+    if draft.track_engagement {
+        rendered.html_body = engagement_domain::add_tracking(
+            &rendered.html_body,
+            base_url,
+            &hmac_secret.0,
+            draft.newsletter_id,
+            Uuid::nil(),
+        );
+    }
+    Ok(rendered)
+}
+
+#[tracing::instrument(name = "Preview newsletter draft.", skip(pool, base_url, hmac_secret, user_id), fields(user_id=%*user_id))]
+pub async fn preview_draft(
+    newsletter_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = match newsletters_domain::get_draft(&pool, newsletter_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let rendered = render_draft(&draft, PREVIEW_EMAIL, &base_url.0, &hmac_secret)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "subject": rendered.subject,
+        "html_body": rendered.html_body,
+        "text_body": rendered.text_body,
+        "headers": rendered.options.headers,
+    })))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TestSendData {
+    email: String,
+}
+
+#[tracing::instrument(name = "Send a test newsletter.", skip(body, pool, email_client, base_url, hmac_secret, user_id), fields(user_id=%*user_id))]
+pub async fn send_test_draft(
+    newsletter_id: web::Path<Uuid>,
+    body: web::Json<TestSendData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let recipient = SubscriberEmail::new(body.0.email).map_err(e400)?;
+    let draft = match newsletters_domain::get_draft(&pool, newsletter_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let rendered = render_draft(&draft, recipient.as_ref(), &base_url.0, &hmac_secret)?;
+    let options = rendered.options.tag("newsletter-test");
+    if let Err(e) = email_client
+        .send_email_with_options(
+            &recipient,
+            &rendered.subject,
+            &rendered.html_body,
+            &rendered.text_body,
+            &options,
+        )
+        .await
+    {
+        tracing::warn!(error.cause_chain = ?e, "Failed to send a test newsletter.");
+        return Ok(HttpResponse::BadGateway().body(e.to_string()));
+    }
+    Ok(HttpResponse::Ok().finish())
+}
@@ -1,23 +1,36 @@
 use crate::authentication::{Credentials, UserId};
 use crate::domain::{
-    get_username, newsletter_queue as newsletter_queue_domain, newsletters as newsletters_domain,
+    get_username, lists as lists_domain, newsletter_deliveries as deliveries_domain,
+    newsletter_queue as newsletter_queue_domain, newsletters as newsletters_domain,
 };
 use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
 use crate::routes::error_chain_fmt;
+use crate::startup::ClaimTimeout;
+use crate::utils::e500;
 use actix_web::http::header::{HeaderMap, HeaderValue};
 use actix_web::http::{header, StatusCode};
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
 use base64::Engine;
+use chrono::{DateTime, Utc};
 use secrecy::SecretBox;
 use serde::Deserialize;
 use sqlx::PgPool;
+use uuid::Uuid;
 
 #[derive(Deserialize, Debug)]
 pub struct BodyData {
     title: String,
     content: Content,
     idempotency_key: String,
+    send_at: Option<DateTime<Utc>>,
+    /// Rewrite links and add an open pixel so the issue gets an engagement report.
+    #[serde(default)]
+    track_engagement: bool,
+    /// The mailing list to send to; the default list if left out.
+    list: Option<String>,
+    /// Narrows the list down to one of its segments.
+    segment: Option<Uuid>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -38,6 +51,18 @@ pub async fn publish_newsletter(
         "user_id",
         tracing::field::display(*user_id.clone().into_inner()),
     );
+    newsletters_domain::validate_content(&body.content.html, &body.content.text)
+        .map_err(|e| PublishError::ValidationError(e.to_string()))?;
+    let audience = newsletters_domain::Audience {
+        list_slug: body.list.as_deref().unwrap_or(lists_domain::DEFAULT_LIST),
+        segment_id: body.segment,
+    };
+    if let Some(problem) = newsletters_domain::check_audience(&pool, &audience)
+        .await
+        .context("Failed to look up the audience.")?
+    {
+        return Err(PublishError::ValidationError(problem));
+    }
     let idempotency_key: &IdempotencyKey = &body
         .idempotency_key
         .to_owned()
@@ -53,29 +78,116 @@ pub async fn publish_newsletter(
             }
         };
 
-    let issue_id = newsletters_domain::insert_newsletter(
-        &mut trx,
-        &body.title,
-        &body.content.text,
-        &body.content.html,
-    )
-    .await
-    .context("Failed to store newsletter details.")?;
+    // A `send_at` in the past is just an issue that is already due.
+    let response = match body.send_at.filter(|send_at| *send_at > Utc::now()) {
+        Some(send_at) => {
+            let issue_id = newsletters_domain::schedule_newsletter(
+                &mut trx,
+                &body.title,
+                &body.content.text,
+                &body.content.html,
+                body.track_engagement,
+                &audience,
+                send_at,
+            )
+            .await
+            .context("Failed to store scheduled newsletter details.")?;
+            HttpResponse::Accepted().json(serde_json::json!({
+                "newsletter_id": issue_id,
+                "send_at": send_at,
+            }))
+        }
+        None => {
+            let issue_id = newsletters_domain::insert_newsletter(
+                &mut trx,
+                &body.title,
+                &body.content.text,
+                &body.content.html,
+                body.track_engagement,
+                &audience,
+            )
+            .await
+            .context("Failed to store newsletter details.")?;
 
-    newsletter_queue_domain::queue_delivery_task(&mut trx, issue_id)
-        .await
-        .context("Failed to queue delivery task.")?;
-    let response = save_response(
-        trx,
-        &idempotency_key,
-        **user_id,
-        HttpResponse::Ok().finish(),
-    )
-    .await?;
+            newsletter_queue_domain::queue_delivery_task(&mut trx, issue_id)
+                .await
+                .context("Failed to queue delivery task.")?;
+            newsletters_domain::mark_sent_if_drained(&mut trx, issue_id)
+                .await
+                .context("Failed to update the newsletter status.")?;
+            HttpResponse::Ok().finish()
+        }
+    };
+    let response = save_response(trx, idempotency_key, **user_id, response).await?;
 
     Ok(response)
 }
 
+#[tracing::instrument(name = "Get newsletter delivery report.", skip(pool, claim_timeout, user_id), fields(user_id=%*user_id))]
+pub async fn get_newsletter_report(
+    newsletter_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    claim_timeout: web::Data<ClaimTimeout>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match deliveries_domain::get_delivery_report(&pool, newsletter_id.into_inner(), claim_timeout.0)
+        .await
+        .map_err(e500)?
+    {
+        Some(report) => Ok(HttpResponse::Ok().json(report)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+#[tracing::instrument(name = "List scheduled newsletters.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn get_scheduled_newsletters(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let scheduled = newsletters_domain::list_scheduled_newsletters(&pool)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(scheduled))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RescheduleData {
+    send_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Reschedule newsletter.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn reschedule_newsletter(
+    newsletter_id: web::Path<Uuid>,
+    body: web::Json<RescheduleData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if newsletters_domain::reschedule_newsletter(&pool, newsletter_id.into_inner(), body.send_at)
+        .await
+        .map_err(e500)?
+    {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
+#[tracing::instrument(name = "Cancel scheduled newsletter.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn cancel_scheduled_newsletter(
+    newsletter_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if newsletters_domain::cancel_newsletter(&pool, newsletter_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
 // removing basic auth -- keeping for reference.
 #[allow(dead_code)]
 fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
@@ -0,0 +1,89 @@
+use crate::authentication::UserId;
+use crate::domain::lists as lists_domain;
+use crate::domain::segments::{self as segments_domain, SegmentFilter};
+use crate::utils::{e400, e500};
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize, Debug)]
+pub struct SegmentData {
+    name: String,
+    filter: SegmentFilter,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PreviewParameters {
+    /// The list the segment would be sent to; the default list if left out.
+    list: Option<String>,
+}
+
+#[tracing::instrument(name = "List segments.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn list_segments(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let segments = segments_domain::list_segments(&pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(segments))
+}
+
+#[tracing::instrument(name = "Create segment.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn create_segment(
+    body: web::Json<SegmentData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = body.into_inner();
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(e400("A segment needs a name."));
+    }
+    let filter = body.filter.parse().map_err(e400)?;
+    match segments_domain::create_segment(&pool, name, &filter)
+        .await
+        .map_err(e500)?
+    {
+        Some(segment) => Ok(HttpResponse::Created().json(segment)),
+        None => {
+            Ok(HttpResponse::Conflict()
+                .body(format!("There already is a segment called '{}'.", name)))
+        }
+    }
+}
+
+/// How many subscribers an issue sent to the segment would reach if it went out now.
+#[tracing::instrument(name = "Preview segment.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn preview_segment(
+    segment_id: web::Path<Uuid>,
+    parameters: web::Query<PreviewParameters>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let segment_id = segment_id.into_inner();
+    let filter = match segments_domain::get_segment_filter(pool.get_ref(), segment_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(filter) => filter,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let list_slug = parameters
+        .list
+        .as_deref()
+        .unwrap_or(lists_domain::DEFAULT_LIST);
+    if !lists_domain::list_exists(pool.get_ref(), list_slug)
+        .await
+        .map_err(e500)?
+    {
+        return Err(e400(format!("There is no list called '{}'.", list_slug)));
+    }
+    let count = segments_domain::count_recipients(&pool, list_slug, &filter)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "segment_id": segment_id,
+        "list": list_slug,
+        "count": count,
+    })))
+}
@@ -0,0 +1,269 @@
+use crate::authentication::UserId;
+use crate::cloneable_auth_token::SecretAuthToken;
+use crate::domain::subscriber_import::{
+    self as import_domain, CsvRecords, ImportColumns, ImportOutcome, ImportStatus,
+};
+use crate::domain::subscribers::{self as subscribers_domain, Subscriber, SubscriberFilter};
+use crate::routes::{resolve_list, send_new_confirmation, SubscriberFilterParameters};
+use crate::startup::{ApplicationBaseUrl, ConfirmationTokenTtl, HmacSecret};
+use crate::utils::{e400, e500};
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+const EXPORT_PAGE_SIZE: i64 = 1000;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ImportParameters {
+    /// The admin vouches that these contacts already opted in elsewhere.
+    #[serde(default)]
+    skip_confirmation: bool,
+    /// The mailing list to add everyone to; the default list if left out.
+    list: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct RowError {
+    /// Counted like a spreadsheet would, with the header on row 1.
+    row: usize,
+    error: String,
+}
+
+#[derive(serde::Serialize, Default)]
+struct ImportReport {
+    imported: usize,
+    already_subscribed: usize,
+    suppressed: usize,
+    errors: Vec<RowError>,
+}
+
+struct Import<'a> {
+    trx: Transaction<'static, Postgres>,
+    columns: Option<ImportColumns>,
+    skip_confirmation: bool,
+    list_slug: String,
+    base_url: &'a str,
+    hmac_secret: &'a SecretAuthToken,
+    token_ttl: chrono::Duration,
+    row: usize,
+    report: ImportReport,
+}
+
+impl Import<'_> {
+    async fn add(&mut self, record: Vec<Vec<u8>>) -> Result<(), actix_web::Error> {
+        self.row += 1;
+        let record = match record
+            .into_iter()
+            .map(String::from_utf8)
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(record) => record,
+            Err(_) => return self.reject("The row is not valid UTF-8."),
+        };
+        let columns = match &self.columns {
+            Some(columns) => columns,
+            None => {
+                let mut header = record;
+                if let Some(first) = header.first_mut() {
+                    *first = first.trim_start_matches('\u{feff}').to_string();
+                }
+                self.columns = Some(ImportColumns::from_header(&header).map_err(e400)?);
+                return Ok(());
+            }
+        };
+
+        let row = match columns.parse(&record) {
+            Ok(row) => row,
+            Err(e) => return self.reject(e),
+        };
+        let status = row.status(self.skip_confirmation);
+        match import_domain::import_subscriber(
+            &mut self.trx,
+            self.hmac_secret,
+            &row,
+            status,
+            &self.list_slug,
+        )
+        .await
+        .map_err(e500)?
+        {
+            ImportOutcome::Imported(subscriber_id) => {
+                if status == ImportStatus::PendingConfirmation {
+                    send_new_confirmation(
+                        &mut self.trx,
+                        subscriber_id,
+                        &row.subscriber.email,
+                        &self.list_slug,
+                        self.base_url,
+                        self.token_ttl,
+                    )
+                    .await
+                    .map_err(e500)?;
+                }
+                self.report.imported += 1;
+            }
+            ImportOutcome::AlreadySubscribed => self.report.already_subscribed += 1,
+            ImportOutcome::Suppressed => self.report.suppressed += 1,
+        }
+        Ok(())
+    }
+
+    fn reject(&mut self, error: impl Into<String>) -> Result<(), actix_web::Error> {
+        self.report.errors.push(RowError {
+            row: self.row,
+            error: error.into(),
+        });
+        Ok(())
+    }
+}
+
+/// Imports subscribers from a CSV upload with `email` and `name` columns, plus optional
+/// `status`, `subscribed_at` and custom columns. Invalid rows are reported and skipped; the rest
+/// is imported in one transaction, as the body streams in.
+#[tracing::instrument(name = "Import subscribers.", skip(payload, pool, base_url, hmac_secret, token_ttl, user_id), fields(user_id=%*user_id))]
+pub async fn import_subscribers(
+    mut payload: web::Payload,
+    parameters: web::Query<ImportParameters>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    token_ttl: web::Data<ConfirmationTokenTtl>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let parameters = parameters.into_inner();
+    let list_slug = resolve_list(&pool, parameters.list).await?;
+    let mut import = Import {
+        trx: pool.begin().await.map_err(e500)?,
+        columns: None,
+        skip_confirmation: parameters.skip_confirmation,
+        list_slug,
+        base_url: &base_url.0,
+        hmac_secret: &hmac_secret.0,
+        token_ttl: token_ttl.0,
+        row: 0,
+        report: ImportReport::default(),
+    };
+    let mut parser = CsvRecords::default();
+    while let Some(chunk) = payload.next().await {
+        for record in parser.feed(&chunk?) {
+            import.add(record).await?;
+        }
+    }
+    for record in parser.finish() {
+        import.add(record).await?;
+    }
+    if import.columns.is_none() {
+        return Err(e400("The file is empty."));
+    }
+    import.trx.commit().await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(import.report))
+}
+
+fn csv_row(fields: &[String]) -> Result<Vec<u8>, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(fields)?;
+    Ok(writer.into_inner()?)
+}
+
+fn export_row(subscriber: &Subscriber, attribute_names: &[String]) -> Vec<String> {
+    let mut row = vec![
+        subscriber.email.clone(),
+        subscriber.name.clone(),
+        subscriber.status.clone().unwrap_or_default(),
+        subscriber.subscribed_at.to_rfc3339(),
+    ];
+    row.extend(
+        attribute_names
+            .iter()
+            .map(|name| match subscriber.attributes.get(name) {
+                Some(serde_json::Value::String(value)) => value.clone(),
+                Some(value) => value.to_string(),
+                None => String::new(),
+            }),
+    );
+    row
+}
+
+struct Export {
+    pool: PgPool,
+    filter: SubscriberFilter,
+    attribute_names: Vec<String>,
+    after: Option<(DateTime<Utc>, Uuid)>,
+}
+
+impl Export {
+    /// The next page of rows, or `None` once every subscriber has been written.
+    async fn next_page(&mut self) -> Result<Option<web::Bytes>, anyhow::Error> {
+        let subscribers = subscribers_domain::list_subscribers_after(
+            &self.pool,
+            &self.filter,
+            self.after,
+            EXPORT_PAGE_SIZE,
+        )
+        .await?;
+        let last = match subscribers.last() {
+            Some(last) => last,
+            None => return Ok(None),
+        };
+        self.after = Some((last.subscribed_at, last.id));
+        let mut page = Vec::new();
+        for subscriber in &subscribers {
+            page.extend(csv_row(&export_row(subscriber, &self.attribute_names))?);
+        }
+        Ok(Some(page.into()))
+    }
+}
+
+/// Streams every subscriber matching the same filters as the list endpoint as CSV, in a format
+/// the import endpoint accepts back. Bounces and complaints are imported as opt-outs.
+#[tracing::instrument(name = "Export subscribers.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn export_subscribers(
+    filter: web::Query<SubscriberFilterParameters>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = filter.into_inner().filter()?;
+    let attribute_names = subscribers_domain::list_attribute_names(&pool, &filter)
+        .await
+        .map_err(e500)?;
+    let mut header: Vec<String> = ["email", "name", "status", "subscribed_at"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    header.extend(attribute_names.iter().cloned());
+    let header = csv_row(&header).map_err(e500)?;
+
+    let export = Export {
+        pool: pool.get_ref().clone(),
+        filter,
+        attribute_names,
+        after: None,
+    };
+    let pages = stream::unfold(Some(export), |export| async move {
+        let mut export = export?;
+        match export.next_page().await {
+            Ok(Some(page)) => Some((Ok(page), Some(export))),
+            Ok(None) => None,
+            // Headers are long gone, so all we can do is cut the download short.
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, "Failed to export subscribers.");
+                Some((Err(e), None))
+            }
+        }
+    });
+    let body = stream::once(async move { Ok(web::Bytes::from(header)) }).chain(pages);
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename("subscribers.csv".into())],
+            },
+        ))
+        .streaming(body))
+}
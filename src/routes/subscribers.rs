@@ -0,0 +1,192 @@
+use crate::authentication::UserId;
+use crate::domain::subscribers::{
+    self as subscribers_domain, ConfirmOutcome, SubscriberFilter, SUBSCRIBER_STATUSES,
+};
+use crate::domain::tags as tags_domain;
+use crate::startup::HmacSecret;
+use crate::utils::{e400, e500};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const DEFAULT_PER_PAGE: i64 = 50;
+const MAX_PER_PAGE: i64 = 200;
+
+#[derive(Deserialize, Debug)]
+pub struct SubscriberFilterParameters {
+    status: Option<String>,
+    subscribed_after: Option<DateTime<Utc>>,
+    subscribed_before: Option<DateTime<Utc>>,
+    search: Option<String>,
+}
+
+impl SubscriberFilterParameters {
+    pub fn filter(self) -> Result<SubscriberFilter, actix_web::Error> {
+        if let Some(status) = &self.status {
+            if !SUBSCRIBER_STATUSES.contains(&status.as_str()) {
+                return Err(e400(format!(
+                    "Unknown status '{}', expected one of: {}.",
+                    status,
+                    SUBSCRIBER_STATUSES.join(", ")
+                )));
+            }
+        }
+        Ok(SubscriberFilter {
+            status: self.status,
+            subscribed_after: self.subscribed_after,
+            subscribed_before: self.subscribed_before,
+            search: self.search.filter(|s| !s.is_empty()),
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PageParameters {
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+#[tracing::instrument(name = "List subscribers.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn list_subscribers(
+    filter: web::Query<SubscriberFilterParameters>,
+    paging: web::Query<PageParameters>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = paging.page.unwrap_or(1);
+    if page < 1 {
+        return Err(e400("`page` starts at 1."));
+    }
+    let per_page = paging.per_page.unwrap_or(DEFAULT_PER_PAGE);
+    if !(1..=MAX_PER_PAGE).contains(&per_page) {
+        return Err(e400(format!(
+            "`per_page` must be between 1 and {}.",
+            MAX_PER_PAGE
+        )));
+    }
+    let filter = filter.into_inner().filter()?;
+
+    let (subscribers, total) =
+        subscribers_domain::list_subscribers(&pool, &filter, per_page, (page - 1) * per_page)
+            .await
+            .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "subscribers": subscribers,
+        "page": page,
+        "per_page": per_page,
+        "total": total,
+    })))
+}
+
+#[tracing::instrument(name = "Get subscriber.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match subscribers_domain::get_subscriber_history(&pool, subscriber_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(history) => Ok(HttpResponse::Ok().json(history)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+#[tracing::instrument(name = "Confirm subscriber manually.", skip(pool, hmac_secret, user_id), fields(user_id=%*user_id))]
+pub async fn confirm_subscriber_by_admin(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match subscribers_domain::confirm_subscriber(&pool, &hmac_secret.0, subscriber_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        ConfirmOutcome::Confirmed => Ok(HttpResponse::Ok().finish()),
+        ConfirmOutcome::NotFound => Ok(HttpResponse::NotFound().finish()),
+        ConfirmOutcome::Suppressed => Ok(HttpResponse::Conflict()
+            .body("The address is on the suppression list and can't be confirmed.")),
+    }
+}
+
+#[tracing::instrument(name = "Unsubscribe subscriber manually.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn unsubscribe_subscriber_by_admin(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if subscribers_domain::unsubscribe_subscriber(&pool, subscriber_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TagsData {
+    tags: Vec<String>,
+}
+
+#[tracing::instrument(name = "Tag subscriber.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn tag_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<TagsData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let tags = body
+        .tags
+        .iter()
+        .map(|tag| tags_domain::parse_tag(tag))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(e400)?;
+    if tags_domain::add_tags(&pool, subscriber_id.into_inner(), &tags)
+        .await
+        .map_err(e500)?
+    {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
+#[tracing::instrument(name = "Untag subscriber.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn untag_subscriber(
+    path: web::Path<(Uuid, String)>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (subscriber_id, tag) = path.into_inner();
+    let tag = tags_domain::parse_tag(&tag).map_err(e400)?;
+    if tags_domain::remove_tag(&pool, subscriber_id, &tag)
+        .await
+        .map_err(e500)?
+    {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
+#[tracing::instrument(name = "Delete subscriber.", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if subscribers_domain::delete_subscriber(&pool, subscriber_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
@@ -1,6 +1,9 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use crate::email_client::EmailClient;
-use crate::startup::ApplicationBaseUrl;
+use crate::domain::{
+    email_events as email_events_domain, email_outbox as email_outbox_domain,
+    lists as lists_domain, NewSubscriber, SubscriberEmail, SubscriberName,
+};
+use crate::email_template::{Escape, Template};
+use crate::startup::{ApplicationBaseUrl, ConfirmationTokenTtl, HmacSecret};
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
@@ -8,6 +11,7 @@ use chrono::Utc;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -15,6 +19,8 @@ use uuid::Uuid;
 pub struct SubscriptionFormData {
     email: String,
     name: String,
+    /// The slug of the list to join; the default list when missing.
+    list: Option<String>,
 }
 
 pub fn error_chain_fmt(
@@ -41,7 +47,7 @@ impl TryFrom<SubscriptionFormData> for NewSubscriber {
 }
 
 #[tracing::instrument(name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(form, pool, base_url, hmac_secret, token_ttl),
     fields(
 subscriber_email = %form.email,
 subscriber_name = %form.name,
@@ -50,10 +56,26 @@ subscriber_name = %form.name,
 pub async fn subscribe(
     form: web::Form<SubscriptionFormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    token_ttl: web::Data<ConfirmationTokenTtl>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::Validationerror)?;
+    let list_slug = resolve_list(&pool, form.0.list.clone()).await?;
+    let new_subscriber: NewSubscriber =
+        form.0.try_into().map_err(SubscribeError::Validationerror)?;
+
+    // Addresses that bounced, complained or were erased stay off the list. They get the usual
+    // answer, but nothing is sent.
+    if email_events_domain::is_suppressed(
+        pool.get_ref(),
+        &hmac_secret.0,
+        new_subscriber.email.as_ref(),
+    )
+    .await
+    .context("Failed to check the suppression list.")?
+    {
+        return Ok(HttpResponse::Ok().finish());
+    }
 
     let mut transaction = pool
         .begin()
@@ -63,27 +85,80 @@ pub async fn subscribe(
     let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
         .await
         .context("Failed to insert new subscriber.")?;
-
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    // Confirmed members get the same response as new ones, so the endpoint can't be used to
+    // find out who is on the list.
+    if lists_domain::join_list(&mut transaction, subscriber_id, &list_slug)
+        .await
+        .context("Failed to add the subscriber to the list.")?
+    {
+        send_new_confirmation(
+            &mut transaction,
+            subscriber_id,
+            &new_subscriber.email,
+            &list_slug,
+            &base_url.0,
+            token_ttl.0,
+        )
+        .await?;
+    }
+    lists_domain::refresh_subscriber_status(&mut transaction, subscriber_id)
         .await
-        .context("Failed to store confirmation token for a new subscriber.")?;
+        .context("Failed to update the subscriber's status.")?;
 
     transaction
         .commit()
         .await
         .context("Failed to commit transaction to store new subscriber.")?;
 
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url.0,
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// The list a form asked for, checking that it exists.
+pub async fn resolve_list(pool: &PgPool, list: Option<String>) -> Result<String, SubscribeError> {
+    let list_slug = list
+        .filter(|list| !list.is_empty())
+        .unwrap_or_else(|| lists_domain::DEFAULT_LIST.to_string());
+    if !lists_domain::list_exists(pool, &list_slug)
+        .await
+        .context("Failed to look up the list.")?
+    {
+        return Err(SubscribeError::Validationerror(format!(
+            "There is no list called '{}'.",
+            list_slug
+        )));
+    }
+    Ok(list_slug)
+}
+
+/// Replaces any outstanding confirmation token for the list with a fresh one and queues the
+/// email carrying it.
+pub async fn send_new_confirmation(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email: &SubscriberEmail,
+    list_slug: &str,
+    base_url: &str,
+    token_ttl: chrono::Duration,
+) -> Result<(), anyhow::Error> {
+    delete_tokens(transaction, subscriber_id, list_slug)
+        .await
+        .context("Failed to revoke previous confirmation tokens.")?;
+
+    let subscription_token = generate_subscription_token();
+    store_token(
+        transaction,
+        subscriber_id,
+        list_slug,
         &subscription_token,
+        token_ttl,
     )
     .await
-    .context("Failed to send a confirmation link.")?;
+    .context("Failed to store confirmation token for a new subscriber.")?;
 
-    Ok(HttpResponse::Ok().finish())
+    queue_confirmation_email(transaction, email, base_url, &subscription_token)
+        .await
+        .context("Failed to queue a confirmation link.")?;
+    Ok(())
 }
 
 #[derive(thiserror::Error)]
@@ -110,39 +185,45 @@ impl std::fmt::Debug for SubscribeError {
     }
 }
 
+const CONFIRMATION_FIELDS: &[&str] = &["confirmation_url"];
+const CONFIRMATION_HTML: &str = "Welcome to our newsletter!<br />\
+    Click <a href=\"{{ confirmation_url }}\">here</a> to confirm your subscription.";
+const CONFIRMATION_TEXT: &str = "Welcome to our newsletter!\n\
+    Visit {{ confirmation_url }} to confirm your subscription.";
+
 #[tracing::instrument(
-    name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, token)
+    name = "Queue a confirmation email to a new subscriber",
+    skip(transaction, email, token)
 )]
-pub async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+pub async fn queue_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
     base_url: &str,
     token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), sqlx::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, token
     );
+    let values = HashMap::from([("confirmation_url", confirmation_link.as_str())]);
+    let html = Template::parse(CONFIRMATION_HTML, CONFIRMATION_FIELDS)
+        .expect("The confirmation HTML template is invalid.");
+    let text = Template::parse(CONFIRMATION_TEXT, CONFIRMATION_FIELDS)
+        .expect("The confirmation text template is invalid.");
 
-    email_client
-        .send_email(
-            &new_subscriber.email,
-            "Welcome to zero2prod",
-            &format!(
-                "Welcome to our newsletter!<br />\
-                Click <a href=\"{}\">here</a> to confirm your subscription.",
-                confirmation_link
-            ),
-            &format!(
-                "Welcome to our newsletter!<br />\
-                Click <a href=\"{}\">here</a> to confirm your subscription.",
-                confirmation_link
-            ),
-        )
-        .await
+    email_outbox_domain::enqueue_email(
+        transaction,
+        email,
+        "Welcome to zero2prod",
+        &html.render(&values, Escape::Html),
+        &text.render(&values, Escape::Text),
+    )
+    .await?;
+    Ok(())
 }
 
+/// Inserts the subscriber, or finds the existing one with this address. Confirmed subscribers
+/// keep their name: anyone can submit the form with their address.
 #[tracing::instrument(
     name = "Saving a new subscriber to DB",
     skip(new_subscriber, transaction)
@@ -152,19 +233,46 @@ pub async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
-    let query = sqlx::query!(
+    let row = sqlx::query!(
         r#"
     INSERT INTO subscriptions (id, email, name, subscribed_at, status)
     VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    ON CONFLICT (email) DO UPDATE
+    SET name = CASE
+            WHEN subscriptions.status = 'confirmed' THEN subscriptions.name
+            ELSE EXCLUDED.name
+        END,
+        subscribed_at = CASE
+            WHEN subscriptions.status = 'unsubscribed' THEN EXCLUDED.subscribed_at
+            ELSE subscriptions.subscribed_at
+        END
+    RETURNING id
     "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now()
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    Ok(row.id)
+}
+
+#[tracing::instrument(name = "Revoke subscription tokens", skip(transaction))]
+async fn delete_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_slug: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1 AND list_slug = $2"#,
+        subscriber_id,
+        list_slug
     );
     transaction.execute(query).await?;
-    Ok(subscriber_id)
+    Ok(())
 }
+
 fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
@@ -180,12 +288,22 @@ fn generate_subscription_token() -> String {
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_slug: &str,
     subscription_token: &str,
+    ttl: chrono::Duration,
 ) -> Result<(), StoreTokenError> {
+    let now = Utc::now();
     let query = sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)"#,
+        r#"
+    INSERT INTO subscription_tokens (
+        subscription_token, subscriber_id, list_slug, created_at, expires_at
+    )
+    VALUES ($1, $2, $3, $4, $5)"#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_slug,
+        now,
+        now + ttl
     );
     transaction.execute(query).await.map_err(StoreTokenError)?;
     Ok(())
@@ -1,5 +1,7 @@
+use crate::domain::lists as lists_domain;
 use actix_web::{web, HttpResponse};
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -9,50 +11,90 @@ pub struct Parameters {
 
 #[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
 pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
-    let id = match get_subsciber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
-    match id {
-        None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            if confirm_subscriber(&pool, subscriber_id).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
-            }
-            HttpResponse::Ok().finish()
-        }
+    let token = match get_token(&mut transaction, &parameters.subscription_token).await {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // Consumed tokens are treated like unknown ones so a leaked link can't be replayed.
+    let token = match token {
+        Some(token) if token.consumed_at.is_none() => token,
+        _ => return HttpResponse::Unauthorized().finish(),
+    };
+    if token.expires_at <= Utc::now() {
+        return HttpResponse::Gone().body("This confirmation link has expired.");
     }
+    if consume_token(&mut transaction, &parameters.subscription_token)
+        .await
+        .is_err()
+        || confirm_subscriber(&mut transaction, token.subscriber_id, &token.list_slug)
+            .await
+            .is_err()
+        || transaction.commit().await.is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().finish()
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(pool, subscriber_id))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
-        subscriber_id
-    )
-    .execute(pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-    Ok(())
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(transaction, subscriber_id)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_slug: &str,
+) -> Result<(), sqlx::Error> {
+    lists_domain::set_membership_status(transaction, subscriber_id, Some(list_slug), "confirmed")
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+}
+
+pub struct SubscriptionToken {
+    pub subscriber_id: Uuid,
+    pub list_slug: String,
+    pub expires_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
 }
 
-#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
-pub async fn get_subsciber_id_from_token(
-    pool: &PgPool,
+#[tracing::instrument(name = "Get subscription token", skip(subscription_token, transaction))]
+pub async fn get_token(
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+) -> Result<Option<SubscriptionToken>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriptionToken,
+        r#"
+    SELECT subscriber_id, list_slug, expires_at, consumed_at
+    FROM subscription_tokens
+    WHERE subscription_token = $1
+    FOR UPDATE"#,
         subscription_token
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut **transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
-    })?;
-    Ok(result.map(|r| r.subscriber_id))
+    })
+}
+
+#[tracing::instrument(name = "Consume subscription token", skip_all)]
+async fn consume_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"UPDATE subscription_tokens SET consumed_at = now() WHERE subscription_token = $1"#,
+        subscription_token
+    );
+    transaction.execute(query).await?;
+    Ok(())
 }
@@ -0,0 +1,159 @@
+use crate::domain::preferences::{self as preferences_domain, PreferencesUpdate, KEEP_PAUSE};
+use crate::domain::unsubscribe::{self as unsubscribe_domain, UnsubscribeRequest};
+use crate::email_template::escape_html;
+use crate::startup::HmacSecret;
+use crate::utils::{e400, e500};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    token: String,
+}
+
+const PAUSE_OPTIONS: &[(u16, &str)] = &[
+    (0, "Don't pause"),
+    (1, "1 week"),
+    (2, "2 weeks"),
+    (4, "4 weeks"),
+    (8, "8 weeks"),
+    (12, "12 weeks"),
+];
+
+fn subscriber_id(secret: &HmacSecret, parameters: &PreferencesParameters) -> Option<Uuid> {
+    preferences_domain::parse_preferences_token(&secret.0, &parameters.token)
+}
+
+// Like the unsubscribe page, GET only shows the form: nothing changes until it is submitted.
+#[tracing::instrument(name = "Show the preference center", skip(parameters, pool, secret))]
+pub async fn preferences_page(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match subscriber_id(&secret, &parameters) {
+        Some(id) => id,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let preferences = match preferences_domain::get_preferences(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(preferences) => preferences,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let lists: String = preferences
+        .lists
+        .iter()
+        .map(|list| {
+            format!(
+                r#"<label><input type="checkbox" name="list" value="{}"{}> {}</label><br>"#,
+                escape_html(&list.slug),
+                if list.subscribed { " checked" } else { "" },
+                escape_html(&list.name)
+            )
+        })
+        .collect();
+    let mut pause_options: String = PAUSE_OPTIONS
+        .iter()
+        .map(|(weeks, label)| format!(r#"<option value="{}">{}</option>"#, weeks, label))
+        .collect();
+    let paused = match preferences.paused_until {
+        Some(paused_until) if paused_until > chrono::Utc::now() => {
+            // Selected, so saving the form for anything else doesn't resume deliveries.
+            pause_options.insert_str(
+                0,
+                &format!(
+                    r#"<option value="{}" selected>Keep the current pause</option>"#,
+                    KEEP_PAUSE
+                ),
+            );
+            format!(
+                "<p>Deliveries are paused until {}. Choose \"Don't pause\" to resume them.</p>",
+                paused_until.format("%B %-d, %Y")
+            )
+        }
+        _ => String::new(),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Your preferences</title></head>
+<body>
+<p>Preferences for {email}</p>
+{paused}<form action="/subscriptions/preferences?token={token}" method="post">
+<label>Name <input type="text" name="name" value="{name}"></label>
+<fieldset><legend>Send me</legend>
+{lists}</fieldset>
+<label>Pause deliveries <select name="pause_weeks">{pause_options}</select></label>
+<button type="submit">Save</button>
+</form>
+<form action="/subscriptions/preferences/unsubscribe?token={token}" method="post">
+<button type="submit">Unsubscribe from everything</button>
+</form>
+</body>
+</html>"#,
+            email = escape_html(&preferences.email),
+            paused = paused,
+            token = parameters.token,
+            name = escape_html(&preferences.name),
+            lists = lists,
+            pause_options = pause_options,
+        )))
+}
+
+#[tracing::instrument(
+    name = "Update subscriber preferences",
+    skip(parameters, form, pool, secret)
+)]
+pub async fn update_preferences(
+    parameters: web::Query<PreferencesParameters>,
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match subscriber_id(&secret, &parameters) {
+        Some(id) => id,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let update = PreferencesUpdate::from_form(form.into_inner()).map_err(e400)?;
+    if !preferences_domain::update_preferences(&pool, subscriber_id, &update)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body("<p>Your preferences have been saved.</p>"))
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe from the preference center",
+    skip(parameters, pool, secret)
+)]
+pub async fn unsubscribe_from_preferences(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match subscriber_id(&secret, &parameters) {
+        Some(id) => id,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let request = UnsubscribeRequest {
+        subscriber_id,
+        list_slug: None,
+    };
+    unsubscribe_domain::unsubscribe_subscriber(&pool, &request)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body("<p>You have been unsubscribed.</p>"))
+}
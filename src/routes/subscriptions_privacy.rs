@@ -0,0 +1,158 @@
+use crate::domain::{
+    email_outbox as email_outbox_domain, privacy as privacy_domain, SubscriberEmail,
+};
+use crate::email_template::{Escape, Template};
+use crate::routes::SubscribeError;
+use crate::startup::{ApplicationBaseUrl, ConfirmationTokenTtl, HmacSecret};
+use crate::utils::e500;
+use actix_web::http::header::{self, ContentType};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct PrivacyRequestFormData {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PrivacyParameters {
+    token: String,
+}
+
+const PRIVACY_FIELDS: &[&str] = &["privacy_url"];
+const PRIVACY_HTML: &str = "Someone asked to see the data we hold about this address.<br />\
+    Click <a href=\"{{ privacy_url }}\">here</a> to download it or have it erased. \
+    If it wasn't you, you can ignore this email.";
+const PRIVACY_TEXT: &str = "Someone asked to see the data we hold about this address.\n\
+    Visit {{ privacy_url }} to download it or have it erased. \
+    If it wasn't you, you can ignore this email.";
+
+fn subscriber_id(secret: &HmacSecret, parameters: &PrivacyParameters) -> Option<Uuid> {
+    privacy_domain::parse_privacy_token(&secret.0, &parameters.token, Utc::now())
+}
+
+// Like resending a confirmation, this answers 200 for any valid address so it can't be used to
+// find out who is subscribed. The link only ever goes to the address itself, never to one that
+// bounced or complained, and at most once per `PRIVACY_LINK_INTERVAL`.
+#[tracing::instrument(name = "Request a privacy link",
+    skip(form, pool, base_url, token_ttl, secret),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn request_privacy_link(
+    form: web::Form<PrivacyRequestFormData>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    token_ttl: web::Data<ConfirmationTokenTtl>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, SubscribeError> {
+    let email = SubscriberEmail::new(form.0.email).map_err(SubscribeError::Validationerror)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    let subscriber_id =
+        match privacy_domain::record_privacy_link(&mut transaction, &secret.0, email.as_ref())
+            .await
+            .context("Failed to look up the subscriber.")?
+        {
+            Some(subscriber_id) => subscriber_id,
+            None => return Ok(HttpResponse::Ok().finish()),
+        };
+
+    let token = privacy_domain::privacy_token(&secret.0, subscriber_id, Utc::now() + token_ttl.0);
+    let privacy_link = format!("{}/subscriptions/privacy?token={}", base_url.0, token);
+    let values = HashMap::from([("privacy_url", privacy_link.as_str())]);
+    let html = Template::parse(PRIVACY_HTML, PRIVACY_FIELDS)
+        .expect("The privacy HTML template is invalid.");
+    let text = Template::parse(PRIVACY_TEXT, PRIVACY_FIELDS)
+        .expect("The privacy text template is invalid.");
+    email_outbox_domain::enqueue_email(
+        &mut transaction,
+        &email,
+        "Your data at zero2prod",
+        &html.render(&values, Escape::Html),
+        &text.render(&values, Escape::Text),
+    )
+    .await
+    .context("Failed to queue a privacy link.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit transaction to queue a privacy link.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// Erasure sits behind a POST for the same reason unsubscribing does: mail scanners follow links.
+#[tracing::instrument(name = "Show the privacy page", skip(parameters, secret))]
+pub async fn privacy_page(
+    parameters: web::Query<PrivacyParameters>,
+    secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    if subscriber_id(&secret, &parameters).is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Your data</title></head>
+<body>
+<p><a href="/subscriptions/privacy/data?token={0}">Download everything we hold about you</a></p>
+<p>Erasing your data unsubscribes you and can't be undone.</p>
+<form action="/subscriptions/privacy/erase?token={0}" method="post">
+<button type="submit">Erase my data</button>
+</form>
+</body>
+</html>"#,
+            parameters.token
+        ))
+}
+
+#[tracing::instrument(name = "Export a subscriber's data", skip(parameters, pool, secret))]
+pub async fn export_subscriber_data(
+    parameters: web::Query<PrivacyParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match subscriber_id(&secret, &parameters) {
+        Some(id) => id,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    match privacy_domain::get_subscriber_data(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(data) => Ok(HttpResponse::Ok()
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                r#"attachment; filename="my-data.json""#,
+            ))
+            .json(data)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+// Erasing twice is not an error: the second click finds nothing left, which is what was asked.
+#[tracing::instrument(name = "Erase a subscriber's data", skip(parameters, pool, secret))]
+pub async fn erase_subscriber_data(
+    parameters: web::Query<PrivacyParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match subscriber_id(&secret, &parameters) {
+        Some(id) => id,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    privacy_domain::erase_subscriber(&pool, &secret.0, subscriber_id)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body("<p>Your data has been erased.</p>"))
+}
@@ -0,0 +1,75 @@
+use crate::domain::SubscriberEmail;
+use crate::routes::{resolve_list, send_new_confirmation, SubscribeError};
+use crate::startup::{ApplicationBaseUrl, ConfirmationTokenTtl};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct ResendFormData {
+    email: String,
+    list: Option<String>,
+}
+
+// Always answers 200 for a valid address: whether anything was sent is nobody's business.
+#[tracing::instrument(name = "Resend a confirmation email",
+    skip(form, pool, base_url, token_ttl),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn resend_confirmation(
+    form: web::Form<ResendFormData>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    token_ttl: web::Data<ConfirmationTokenTtl>,
+) -> Result<HttpResponse, SubscribeError> {
+    let list_slug = resolve_list(&pool, form.0.list).await?;
+    let email = SubscriberEmail::new(form.0.email).map_err(SubscribeError::Validationerror)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+
+    if let Some(subscriber_id) = get_pending_subscriber_id(&mut transaction, &email, &list_slug)
+        .await
+        .context("Failed to look up a pending subscriber.")?
+    {
+        send_new_confirmation(
+            &mut transaction,
+            subscriber_id,
+            &email,
+            &list_slug,
+            &base_url.0,
+            token_ttl.0,
+        )
+        .await?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit transaction to resend a confirmation.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Get pending subscriber", skip(transaction, email))]
+async fn get_pending_subscriber_id(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+    list_slug: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+    SELECT s.id FROM subscriptions s
+    JOIN list_memberships m ON m.subscriber_id = s.id
+    WHERE s.email = $1 AND m.list_slug = $2 AND m.status = 'pending_confirmation'
+    FOR UPDATE OF s"#,
+        email.as_ref(),
+        list_slug
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(row.map(|r| r.id))
+}
@@ -0,0 +1,60 @@
+use crate::domain::unsubscribe as unsubscribe_domain;
+use crate::startup::HmacSecret;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    token: String,
+}
+
+// GET must not change anything: mail scanners follow links, so the page only offers a button
+// that POSTs back to the same URL.
+#[tracing::instrument(name = "Show the unsubscribe page", skip(parameters, secret))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    if unsubscribe_domain::parse_unsubscribe_token(&secret.0, &parameters.token).is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Unsubscribe</title></head>
+<body>
+<p>Do you want to stop receiving our newsletter?</p>
+<form action="/subscriptions/unsubscribe?token={}" method="post">
+<button type="submit" name="List-Unsubscribe" value="One-Click">Unsubscribe</button>
+</form>
+</body>
+</html>"#,
+            parameters.token
+        ))
+}
+
+// Handles both the button on the unsubscribe page and RFC 8058 one-click requests, whose
+// `List-Unsubscribe=One-Click` body carries nothing we need.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool, secret))]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let request = match unsubscribe_domain::parse_unsubscribe_token(&secret.0, &parameters.token) {
+        Some(request) => request,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if unsubscribe_domain::unsubscribe_subscriber(&pool, &request)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body("<p>You have been unsubscribed.</p>")
+}
@@ -0,0 +1,94 @@
+use crate::cloneable_auth_token::SecretAuthToken;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::hmac;
+use secrecy::ExposeSecret;
+
+// Tokens are `base64url(payload).base64url(hmac(purpose:payload))`. The purpose is part of
+// the signed message so a token minted for one kind of link can't be replayed against another.
+pub fn sign(secret: &SecretAuthToken, purpose: &str, payload: &str) -> String {
+    let signature = hmac::sign(&key(secret), &message(purpose, payload));
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(payload),
+        URL_SAFE_NO_PAD.encode(signature.as_ref())
+    )
+}
+
+pub fn verify(secret: &SecretAuthToken, purpose: &str, token: &str) -> Option<String> {
+    let (encoded_payload, encoded_signature) = token.split_once('.')?;
+    let payload = String::from_utf8(URL_SAFE_NO_PAD.decode(encoded_payload).ok()?).ok()?;
+    let signature = URL_SAFE_NO_PAD.decode(encoded_signature).ok()?;
+    hmac::verify(&key(secret), &message(purpose, &payload), &signature).ok()?;
+    Some(payload)
+}
+
+/// A hex digest of `payload` that only the holder of the secret can compute, so unlike a plain
+/// hash it can't be reversed by hashing a list of guesses.
+pub fn keyed_hash(secret: &SecretAuthToken, purpose: &str, payload: &str) -> String {
+    hmac::sign(&key(secret), &message(purpose, payload))
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn key(secret: &SecretAuthToken) -> hmac::Key {
+    hmac::Key::new(hmac::HMAC_SHA256, secret.expose_secret().token.as_bytes())
+}
+
+fn message(purpose: &str, payload: &str) -> Vec<u8> {
+    format!("{}:{}", purpose, payload).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign, verify};
+    use crate::cloneable_auth_token::AuthToken;
+    use claims::{assert_none, assert_some_eq};
+
+    #[test]
+    fn a_signed_payload_is_verified() {
+        let secret = AuthToken::new("secret".to_string());
+        let token = sign(&secret, "purpose", "payload");
+        assert_some_eq!(verify(&secret, "purpose", &token), "payload".to_string());
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let token = sign(&AuthToken::new("secret".to_string()), "purpose", "payload");
+        assert_none!(verify(
+            &AuthToken::new("another-secret".to_string()),
+            "purpose",
+            &token
+        ));
+    }
+
+    #[test]
+    fn a_token_signed_for_another_purpose_is_rejected() {
+        let secret = AuthToken::new("secret".to_string());
+        let token = sign(&secret, "purpose", "payload");
+        assert_none!(verify(&secret, "another-purpose", &token));
+    }
+
+    #[test]
+    fn a_tampered_payload_is_rejected() {
+        let secret = AuthToken::new("secret".to_string());
+        let token = sign(&secret, "purpose", "payload");
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = format!(
+            "{}.{}",
+            sign(&secret, "purpose", "other").split_once('.').unwrap().0,
+            signature
+        );
+        assert_none!(verify(&secret, "purpose", &forged));
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        let secret = AuthToken::new("secret".to_string());
+        for token in ["", ".", "no-separator", "!!!.!!!"] {
+            assert_none!(verify(&secret, "purpose", token));
+        }
+    }
+}
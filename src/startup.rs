@@ -3,12 +3,22 @@ use crate::cloneable_auth_token::SecretAuthToken;
 use crate::configuration::{DatabaseSettings, Settings};
 use crate::email_client::EmailClient;
 use crate::routes::{
-    change_password, confirm, health_check, login, logout, publish_newsletter, subscribe,
+    cancel_scheduled_newsletter, change_password, confirm, confirm_subscriber_by_admin,
+    create_draft, create_mailing_list, create_segment, delete_draft, delete_subscriber,
+    erase_subscriber_data, export_subscriber_data, export_subscribers, get_dead_letters, get_draft,
+    get_newsletter_engagement, get_newsletter_report, get_scheduled_newsletters, get_subscriber,
+    health_check, import_subscribers, list_drafts, list_mailing_lists, list_segments,
+    list_subscribers, login, logout, preferences_page, preview_draft, preview_segment,
+    privacy_page, publish_draft, publish_newsletter, receive_email_event, request_privacy_link,
+    requeue_dead_letters, reschedule_newsletter, resend_confirmation, send_test_draft, subscribe,
+    tag_subscriber, track_click, track_open, unsubscribe, unsubscribe_form,
+    unsubscribe_from_preferences, unsubscribe_subscriber_by_admin, untag_subscriber, update_draft,
+    update_preferences,
 };
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
-use actix_web::dev::Server;
+use actix_web::dev::{Server, ServerHandle};
 use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
 use secrecy::ExposeSecret;
@@ -31,13 +41,20 @@ impl Application {
 
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
+        let confirmation_token_ttl = config.application.confirmation_token_ttl();
+        let shutdown_deadline = config.application.shutdown_deadline();
+        let claim_timeout = config.worker.claim_timeout();
         let server = run(
             listener,
             connection_pool,
             email_client,
             config.application.base_url,
             config.application.hmac_secret,
+            config.application.webhook_secret,
+            confirmation_token_ttl,
             config.redis_uri,
+            shutdown_deadline,
+            claim_timeout,
         )
         .await?;
 
@@ -48,6 +65,12 @@ impl Application {
         self.port
     }
 
+    /// Stopping through the handle lets in-flight requests finish; the server no longer reacts to
+    /// signals on its own.
+    pub fn handle(&self) -> ServerHandle {
+        self.server.handle()
+    }
+
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
         self.server.await
     }
@@ -59,20 +82,34 @@ pub fn get_connection_pool(config: &DatabaseSettings) -> PgPool {
 
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct ConfirmationTokenTtl(pub chrono::Duration);
+
 #[derive(Clone)]
 pub struct HmacSecret(pub SecretAuthToken);
 
+pub struct WebhookSecret(pub SecretAuthToken);
+
+pub struct ClaimTimeout(pub std::time::Duration);
+
+#[allow(clippy::too_many_arguments)]
 async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: String,
     hmac_secret: SecretAuthToken,
+    webhook_secret: SecretAuthToken,
+    confirmation_token_ttl: chrono::Duration,
     redis_uri: SecretAuthToken,
+    shutdown_deadline: std::time::Duration,
+    claim_timeout: std::time::Duration,
 ) -> Result<Server, anyhow::Error> {
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let confirmation_token_ttl = web::Data::new(ConfirmationTokenTtl(confirmation_token_ttl));
+    let webhook_secret = web::Data::new(WebhookSecret(webhook_secret));
+    let claim_timeout = web::Data::new(ClaimTimeout(claim_timeout));
     let secret_key = Key::from(hmac_secret.expose_secret().token.as_bytes());
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret().clone().token).await?;
     let server = HttpServer::new(move || {
@@ -85,12 +122,223 @@ async fn run(
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/subscriptions/resend", web::post().to(resend_confirmation))
+            .route(
+                "/subscriptions/privacy",
+                web::post().to(request_privacy_link),
+            )
+            .route("/subscriptions/privacy", web::get().to(privacy_page))
+            .route(
+                "/subscriptions/privacy/data",
+                web::get().to(export_subscriber_data),
+            )
+            .route(
+                "/subscriptions/privacy/erase",
+                web::post().to(erase_subscriber_data),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(preferences_page),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(update_preferences),
+            )
+            .route(
+                "/subscriptions/preferences/unsubscribe",
+                web::post().to(unsubscribe_from_preferences),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                "/webhooks/email-events",
+                web::post().to(receive_email_event),
+            )
+            .route("/tracking/open", web::get().to(track_open))
+            .route("/tracking/click", web::get().to(track_click))
             .route(
                 "/newsletters",
                 web::post()
                     .to(publish_newsletter)
                     .wrap(from_fn(reject_anonymous_users)),
             )
+            .route(
+                "/newsletters/dead_letters",
+                web::get()
+                    .to(get_dead_letters)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/dead_letters/requeue",
+                web::post()
+                    .to(requeue_dead_letters)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/drafts",
+                web::get()
+                    .to(list_drafts)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/drafts",
+                web::post()
+                    .to(create_draft)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/drafts/{newsletter_id}",
+                web::get()
+                    .to(get_draft)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/drafts/{newsletter_id}",
+                web::put()
+                    .to(update_draft)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/drafts/{newsletter_id}",
+                web::delete()
+                    .to(delete_draft)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/drafts/{newsletter_id}/preview",
+                web::get()
+                    .to(preview_draft)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/drafts/{newsletter_id}/test",
+                web::post()
+                    .to(send_test_draft)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/drafts/{newsletter_id}/publish",
+                web::post()
+                    .to(publish_draft)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/scheduled",
+                web::get()
+                    .to(get_scheduled_newsletters)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/scheduled/{newsletter_id}",
+                web::put()
+                    .to(reschedule_newsletter)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/scheduled/{newsletter_id}",
+                web::delete()
+                    .to(cancel_scheduled_newsletter)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/{newsletter_id}/report",
+                web::get()
+                    .to(get_newsletter_report)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/newsletters/{newsletter_id}/engagement",
+                web::get()
+                    .to(get_newsletter_engagement)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/lists",
+                web::get()
+                    .to(list_mailing_lists)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/lists",
+                web::post()
+                    .to(create_mailing_list)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/segments",
+                web::get()
+                    .to(list_segments)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/segments",
+                web::post()
+                    .to(create_segment)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/segments/{segment_id}/preview",
+                web::get()
+                    .to(preview_segment)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/subscribers",
+                web::get()
+                    .to(list_subscribers)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/subscribers/export",
+                web::get()
+                    .to(export_subscribers)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/subscribers/import",
+                web::post()
+                    .to(import_subscribers)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/subscribers/{subscriber_id}",
+                web::get()
+                    .to(get_subscriber)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/subscribers/{subscriber_id}",
+                web::delete()
+                    .to(delete_subscriber)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/subscribers/{subscriber_id}/confirm",
+                web::post()
+                    .to(confirm_subscriber_by_admin)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/subscribers/{subscriber_id}/unsubscribe",
+                web::post()
+                    .to(unsubscribe_subscriber_by_admin)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/subscribers/{subscriber_id}/tags",
+                web::post()
+                    .to(tag_subscriber)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
+            .route(
+                "/subscribers/{subscriber_id}/tags/{tag}",
+                web::delete()
+                    .to(untag_subscriber)
+                    .wrap(from_fn(reject_anonymous_users)),
+            )
             .route("/login", web::post().to(login))
             .route(
                 "/password",
@@ -105,8 +353,13 @@ async fn run(
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(confirmation_token_ttl.clone())
+            .app_data(webhook_secret.clone())
+            .app_data(claim_timeout.clone())
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
     })
+    .disable_signals()
+    .shutdown_timeout(shutdown_deadline.as_secs())
     .listen(listener)?
     .run();
     Ok(server)
@@ -0,0 +1,82 @@
+use crate::configuration::Settings;
+use crate::startup::get_connection_pool;
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Deletes pending subscribers whose confirmation links have all expired, and pending list
+/// memberships likewise, along with every token that can no longer be used. Returns how many
+/// subscribers were removed.
+#[tracing::instrument(skip_all)]
+pub async fn sweep_stale_subscriptions(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let mut trx = pool.begin().await?;
+    let n_subscribers = sqlx::query!(
+        r#"
+    WITH stale AS (
+        SELECT id FROM subscriptions s
+        WHERE s.status = 'pending_confirmation'
+            AND EXISTS (SELECT 1 FROM subscription_tokens t WHERE t.subscriber_id = s.id)
+            AND NOT EXISTS (
+                SELECT 1 FROM subscription_tokens t
+                WHERE t.subscriber_id = s.id AND t.expires_at > now()
+            )
+        FOR UPDATE SKIP LOCKED
+    ),
+    tokens AS (
+        DELETE FROM subscription_tokens WHERE subscriber_id IN (SELECT id FROM stale)
+    )
+    DELETE FROM subscriptions WHERE id IN (SELECT id FROM stale)"#
+    )
+    .execute(&mut *trx)
+    .await?
+    .rows_affected();
+    // Subscribers confirmed on one list can still leave another hanging: drop those joins.
+    sqlx::query!(
+        r#"
+    DELETE FROM list_memberships m
+    WHERE m.status = 'pending_confirmation'
+        AND EXISTS (
+            SELECT 1 FROM subscription_tokens t
+            WHERE t.subscriber_id = m.subscriber_id AND t.list_slug = m.list_slug
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM subscription_tokens t
+            WHERE t.subscriber_id = m.subscriber_id
+                AND t.list_slug = m.list_slug
+                AND t.expires_at > now()
+        )"#
+    )
+    .execute(&mut *trx)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE consumed_at IS NOT NULL OR expires_at <= now()"#
+    )
+    .execute(&mut *trx)
+    .await?;
+    trx.commit().await?;
+    Ok(n_subscribers)
+}
+
+async fn sweeper_loop(pool: PgPool, shutdown: CancellationToken) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
+        match sweep_stale_subscriptions(&pool).await {
+            Ok(n) if n > 0 => tracing::info!("Removed {} stale pending subscribers", n),
+            Ok(_) => {}
+            Err(e) => tracing::error!(error.cause_chain = ?e, "Failed to sweep subscriptions"),
+        }
+        tokio::select! {
+            _ = shutdown.cancelled() => {}
+            _ = tokio::time::sleep(SWEEP_INTERVAL) => {}
+        }
+    }
+    Ok(())
+}
+
+pub async fn run_sweeper_until_stopped(
+    config: Settings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    sweeper_loop(get_connection_pool(&config.database), shutdown).await
+}
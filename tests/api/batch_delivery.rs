@@ -0,0 +1,302 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::dead_letters::{dead_letter_stale_claims, OUTCOME_UNKNOWN};
+use zero2prod::newsletter_delivery_worker::{try_execute_batch, ExecutionOutcome};
+
+async fn publish_newsletter_to(app: &TestApp, n_subscribers: usize) {
+    for _ in 0..n_subscribers {
+        create_confirmed_subscriber(app).await;
+    }
+    app.test_user.login(app).await;
+    let resp = app
+        .post_newsletters(&serde_json::json!({
+            "title": "newsletter",
+            "content": {
+                "text": "Newsletter body",
+                "html": "<p>Newsletter body</p>",
+            },
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+}
+
+async fn execute_batch(app: &TestApp, batch_size: usize) -> ExecutionOutcome {
+    try_execute_batch(
+        &app.db_pool,
+        &app.email_client,
+        &app.address,
+        &app.hmac_secret,
+        batch_size,
+    )
+    .await
+    .unwrap()
+}
+
+async fn queued_retries(app: &TestApp) -> Vec<i16> {
+    sqlx::query_scalar!("SELECT n_retries FROM newsletter_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn a_batch_is_sent_in_a_single_request() {
+    let app = spawn_app().await;
+    publish_newsletter_to(&app, 3).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK", "MessageID": "a"},
+            {"ErrorCode": 0, "Message": "OK", "MessageID": "b"},
+            {"ErrorCode": 0, "Message": "OK", "MessageID": "c"},
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    execute_batch(&app, 10).await;
+
+    assert!(queued_retries(&app).await.is_empty());
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert_eq!(body.as_array().unwrap().len(), 3);
+    let n_sent = sqlx::query_scalar!(
+        "SELECT COUNT(*) AS \"count!\" FROM newsletter_deliveries WHERE outcome = 'sent' AND provider_message_id IS NOT NULL"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_sent, 3);
+}
+
+#[tokio::test]
+async fn per_message_results_are_applied_to_their_own_rows() {
+    let app = spawn_app().await;
+    publish_newsletter_to(&app, 3).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK", "MessageID": "a"},
+            {"ErrorCode": 406, "Message": "Inactive recipient"},
+            {"ErrorCode": 100, "Message": "Maintenance"},
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    execute_batch(&app, 10).await;
+
+    // The maintenance error is retried, the inactive recipient is not.
+    assert_eq!(queued_retries(&app).await, vec![1]);
+    let dead_letters: serde_json::Value = app.get_dead_letters().await.json().await.unwrap();
+    assert_eq!(dead_letters.as_array().unwrap().len(), 1);
+    assert!(dead_letters[0]["last_error"]
+        .as_str()
+        .unwrap()
+        .contains("Inactive recipient"));
+}
+
+#[tokio::test]
+async fn a_failed_batch_request_reschedules_every_row() {
+    let app = spawn_app().await;
+    publish_newsletter_to(&app, 2).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    execute_batch(&app, 10).await;
+
+    assert_eq!(queued_retries(&app).await, vec![1, 1]);
+}
+
+#[tokio::test]
+async fn batches_claim_at_most_batch_size_rows() {
+    let app = spawn_app().await;
+    publish_newsletter_to(&app, 3).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK"},
+            {"ErrorCode": 0, "Message": "OK"},
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    execute_batch(&app, 2).await;
+
+    assert_eq!(queued_retries(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn concurrent_consumers_never_claim_the_same_row() {
+    let app = spawn_app().await;
+    publish_newsletter_to(&app, 4).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(200)))
+        .expect(4)
+        .mount(&app.email_server)
+        .await;
+
+    loop {
+        let outcomes = tokio::join!(
+            execute_batch(&app, 1),
+            execute_batch(&app, 1),
+            execute_batch(&app, 1),
+            execute_batch(&app, 1),
+        );
+        if let (
+            ExecutionOutcome::EmptyQueue,
+            ExecutionOutcome::EmptyQueue,
+            ExecutionOutcome::EmptyQueue,
+            ExecutionOutcome::EmptyQueue,
+        ) = outcomes
+        {
+            break;
+        }
+    }
+
+    let n_sent = sqlx::query_scalar!(
+        "SELECT COUNT(DISTINCT subscriber_email) AS \"count!\" FROM newsletter_deliveries WHERE outcome = 'sent'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_sent, 4);
+}
+
+async fn n_claimed(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(
+        "SELECT COUNT(*) AS \"count!\" FROM newsletter_delivery_queue WHERE claimed_at IS NOT NULL"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn a_batch_is_claimed_before_the_provider_is_called() {
+    let app = spawn_app().await;
+    publish_newsletter_to(&app, 2).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!([
+                    {"ErrorCode": 0, "Message": "OK"},
+                    {"ErrorCode": 0, "Message": "OK"},
+                ]))
+                .set_delay(std::time::Duration::from_millis(500)),
+        )
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let claimed_mid_call = async {
+        while !app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .any(|request| request.url.path() == "/email/batch")
+        {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        n_claimed(&app).await
+    };
+    let (_, claimed_mid_call) = tokio::join!(execute_batch(&app, 10), claimed_mid_call);
+
+    assert_eq!(claimed_mid_call, 2);
+    assert!(queued_retries(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn claimed_rows_are_never_sent_again() {
+    let app = spawn_app().await;
+    publish_newsletter_to(&app, 1).await;
+    // What a worker dying mid-call leaves behind.
+    sqlx::query!("UPDATE newsletter_delivery_queue SET claimed_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    assert!(matches!(
+        execute_batch(&app, 10).await,
+        ExecutionOutcome::EmptyQueue
+    ));
+    assert_eq!(n_claimed(&app).await, 1);
+}
+
+#[tokio::test]
+async fn stale_claims_are_reported_then_dead_lettered() {
+    let app = spawn_app().await;
+    publish_newsletter_to(&app, 2).await;
+    let newsletter_id = sqlx::query_scalar!("SELECT newsletter_id FROM newsletters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    // One batch still in flight, and one whose worker died a day ago.
+    sqlx::query!(
+        r#"
+    UPDATE newsletter_delivery_queue SET claimed_at = now() - interval '1 day'
+    WHERE subscriber_email = (SELECT min(subscriber_email) FROM newsletter_delivery_queue)"#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE newsletter_delivery_queue SET claimed_at = now() WHERE claimed_at IS NULL"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let report: serde_json::Value = app
+        .get_newsletter_report(newsletter_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["pending"], 0);
+    assert_eq!(report["claimed"], 1);
+    assert_eq!(report["stale"], 1);
+
+    let n_swept = dead_letter_stale_claims(&app.db_pool, app.config.worker.claim_timeout())
+        .await
+        .unwrap();
+
+    assert_eq!(n_swept, 1);
+    assert_eq!(n_claimed(&app).await, 1);
+    let dead_letters: serde_json::Value = app.get_dead_letters().await.json().await.unwrap();
+    assert_eq!(dead_letters.as_array().unwrap().len(), 1);
+    assert_eq!(dead_letters[0]["last_error"], OUTCOME_UNKNOWN);
+    let report: serde_json::Value = app
+        .get_newsletter_report(newsletter_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["claimed"], 1);
+    assert_eq!(report["stale"], 0);
+    assert_eq!(report["failed"], 1);
+}
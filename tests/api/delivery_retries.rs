@@ -0,0 +1,209 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::newsletter_delivery_worker::MAX_DELIVERY_RETRIES;
+
+async fn publish_newsletter(app: &TestApp) {
+    let resp = app
+        .post_newsletters(&serde_json::json!({
+            "title": "newsletter",
+            "content": {
+                "text": "Newsletter body",
+                "html": "<p>Newsletter body</p>",
+            },
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+}
+
+async fn make_queued_tasks_due(app: &TestApp) {
+    sqlx::query!("UPDATE newsletter_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+async fn dead_letter_a_delivery(app: &TestApp) {
+    let _mock_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_newsletter(app).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn transient_failures_are_rescheduled_with_a_backoff() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let task = sqlx::query!(
+        "SELECT n_retries, execute_after > now() AS \"in_the_future!\" FROM newsletter_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The failed delivery should still be queued.");
+    assert_eq!(task.n_retries, 1);
+    assert!(task.in_the_future);
+}
+
+#[tokio::test]
+async fn rescheduled_deliveries_are_sent_once_due() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    make_queued_tasks_due(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let n_queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn deliveries_are_dead_lettered_once_retries_are_exhausted() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    sqlx::query!(
+        "UPDATE newsletter_delivery_queue SET n_retries = $1",
+        MAX_DELIVERY_RETRIES
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let dead_letters: serde_json::Value = app.get_dead_letters().await.json().await.unwrap();
+    assert_eq!(dead_letters.as_array().unwrap().len(), 1);
+    assert_eq!(dead_letters[0]["n_retries"], MAX_DELIVERY_RETRIES);
+}
+
+#[tokio::test]
+async fn permanent_failures_are_dead_lettered_without_retrying() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    dead_letter_a_delivery(&app).await;
+
+    let dead_letters: serde_json::Value = app.get_dead_letters().await.json().await.unwrap();
+    assert_eq!(dead_letters.as_array().unwrap().len(), 1);
+    assert_eq!(dead_letters[0]["n_retries"], 0);
+    assert!(dead_letters[0]["last_error"]
+        .as_str()
+        .unwrap()
+        .contains("422"));
+}
+
+#[tokio::test]
+async fn requeued_dead_letters_are_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    dead_letter_a_delivery(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let resp = app
+        .post_requeue_dead_letters(&serde_json::json!({"all": true}))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["requeued"], 1);
+
+    app.dispatch_all_pending_emails().await;
+    let dead_letters: serde_json::Value = app.get_dead_letters().await.json().await.unwrap();
+    assert_eq!(dead_letters.as_array().unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn requeueing_can_target_a_single_recipient() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    dead_letter_a_delivery(&app).await;
+
+    let resp = app
+        .post_requeue_dead_letters(&serde_json::json!({
+            "subscriber_email": "someone-else@example.com"
+        }))
+        .await;
+    let body: serde_json::Value = resp.json().await.unwrap();
+
+    assert_eq!(body["requeued"], 0);
+    let dead_letters: serde_json::Value = app.get_dead_letters().await.json().await.unwrap();
+    assert_eq!(dead_letters.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn requeueing_without_a_filter_must_ask_for_everything() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    dead_letter_a_delivery(&app).await;
+
+    let resp = app.post_requeue_dead_letters(&serde_json::json!({})).await;
+
+    assert_eq!(resp.status().as_u16(), 400);
+    let dead_letters: serde_json::Value = app.get_dead_letters().await.json().await.unwrap();
+    assert_eq!(dead_letters.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn dead_letter_endpoints_require_authentication() {
+    let app = spawn_app().await;
+
+    let list = app.get_dead_letters().await;
+    let requeue = app.post_requeue_dead_letters(&serde_json::json!({})).await;
+
+    assert_eq!(list.status().as_u16(), 401);
+    assert_eq!(requeue.status().as_u16(), 401);
+}
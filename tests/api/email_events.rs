@@ -0,0 +1,358 @@
+use crate::helpers::{
+    create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app, TestApp,
+};
+use base64::Engine;
+use ring::hmac;
+use secrecy::ExposeSecret;
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscriber(app: &TestApp) -> (String, String) {
+    let row = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    (row.email, row.status.unwrap())
+}
+
+fn bounce(email: &str, kind: &str) -> serde_json::Value {
+    json!({
+        "RecordType": "Bounce",
+        "Type": kind,
+        "Email": email,
+        "MessageID": Uuid::new_v4().to_string(),
+        "Description": "The server was unable to deliver your message.",
+        "BouncedAt": "2025-06-08T10:15:30Z",
+    })
+}
+
+fn complaint(email: &str) -> serde_json::Value {
+    json!({
+        "RecordType": "SpamComplaint",
+        "Type": "SpamComplaint",
+        "Email": email,
+        "MessageID": Uuid::new_v4().to_string(),
+        "BouncedAt": "2025-06-08T10:15:30Z",
+    })
+}
+
+async fn publish_newsletter(app: &TestApp) {
+    app.test_user.login(app).await;
+    let resp = app
+        .post_newsletters(&json!({
+            "title": "newsletter",
+            "content": {
+                "text": "Newsletter body",
+                "html": "<p>Newsletter body</p>",
+            },
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+}
+
+async fn queued_deliveries(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM newsletter_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn events_without_credentials_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/webhooks/email-events", app.address))
+        .json(&bounce("someone@example.com", "HardBounce"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="webhooks""#
+    );
+}
+
+#[tokio::test]
+async fn events_with_the_wrong_password_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/webhooks/email-events", app.address))
+        .basic_auth("postmark", Some("not-the-secret"))
+        .json(&bounce("someone@example.com", "HardBounce"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_valid_signature_is_accepted_and_a_forged_one_is_not() {
+    let app = spawn_app().await;
+    let body = serde_json::to_vec(&bounce("someone@example.com", "HardBounce")).unwrap();
+    let key = hmac::Key::new(
+        hmac::HMAC_SHA256,
+        app.config
+            .application
+            .webhook_secret
+            .expose_secret()
+            .token
+            .as_bytes(),
+    );
+    let signature =
+        base64::engine::general_purpose::STANDARD.encode(hmac::sign(&key, &body).as_ref());
+
+    let post = |signature: String, body: Vec<u8>| {
+        app.api_client
+            .post(format!("{}/webhooks/email-events", app.address))
+            .header("Content-Type", "application/json")
+            .header("X-Webhook-Signature", signature)
+            .body(body)
+            .send()
+    };
+
+    let response = post(signature.clone(), body.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let tampered = serde_json::to_vec(&bounce("someone-else@example.com", "HardBounce")).unwrap();
+    let response = post(signature, tampered).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn malformed_events_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            json!({"Email": "someone@example.com"}),
+            "missing record type",
+        ),
+        (json!({"RecordType": "Bounce"}), "missing email"),
+        (
+            json!({"RecordType": "Delivery", "Email": "someone@example.com"}),
+            "delivery without a recipient",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_email_event(&body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject an event with {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn unknown_record_types_are_acknowledged_and_ignored() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_email_event(&json!({"RecordType": "Open", "Recipient": "someone@example.com"}))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let events = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM email_events"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(events, 0);
+}
+
+#[tokio::test]
+async fn a_hard_bounce_marks_the_subscriber_as_bounced() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email, _) = subscriber(&app).await;
+
+    let response = app.post_email_event(&bounce(&email, "HardBounce")).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber(&app).await.1, "bounced");
+    let event = sqlx::query!("SELECT kind, email, detail FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.kind, "hard_bounce");
+    assert_eq!(event.email, email);
+    assert!(event.detail.is_some());
+}
+
+#[tokio::test]
+async fn a_soft_bounce_is_recorded_but_does_not_suppress() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email, _) = subscriber(&app).await;
+
+    let response = app.post_email_event(&bounce(&email, "SoftBounce")).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber(&app).await.1, "confirmed");
+    let kind = sqlx::query_scalar!("SELECT kind FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(kind, "soft_bounce");
+    let suppressed = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM suppressed_emails"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(suppressed, 0);
+}
+
+#[tokio::test]
+async fn a_spam_complaint_marks_the_subscriber_as_complained() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email, _) = subscriber(&app).await;
+
+    app.post_email_event(&complaint(&email))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(subscriber(&app).await.1, "complained");
+}
+
+#[tokio::test]
+async fn a_complaint_after_a_bounce_takes_precedence() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email, _) = subscriber(&app).await;
+
+    app.post_email_event(&complaint(&email)).await;
+    app.post_email_event(&bounce(&email, "HardBounce")).await;
+
+    let reason = sqlx::query_scalar!("SELECT reason FROM suppressed_emails")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(reason, "complained");
+}
+
+#[tokio::test]
+async fn delivery_events_are_recorded() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_email_event(&json!({
+            "RecordType": "Delivery",
+            "Recipient": "someone@example.com",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "DeliveredAt": "2025-06-08T10:15:30Z",
+            "Details": "Test delivery webhook details",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let event = sqlx::query!("SELECT kind, provider_message_id FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.kind, "delivery");
+    assert_eq!(
+        event.provider_message_id.as_deref(),
+        Some("883953f4-6105-42a2-a16a-77a8eac79483")
+    );
+}
+
+#[tokio::test]
+async fn suppressed_subscribers_are_left_out_of_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email, _) = subscriber(&app).await;
+    app.post_email_event(&bounce(&email, "HardBounce")).await;
+    // Put them back by hand: the suppression list alone must keep them out.
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    publish_newsletter(&app).await;
+
+    assert_eq!(queued_deliveries(&app).await, 0);
+}
+
+#[tokio::test]
+async fn addresses_suppressed_after_queueing_are_not_sent_the_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email, _) = subscriber(&app).await;
+    publish_newsletter(&app).await;
+    assert_eq!(queued_deliveries(&app).await, 1);
+    // A manual suppression, which leaves the subscriber's status alone.
+    sqlx::query!(
+        r#"
+    INSERT INTO suppressed_emails (email, reason, suppressed_at)
+    VALUES (lower($1), 'bounced', now())"#,
+        email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(queued_deliveries(&app).await, 0);
+}
+
+#[tokio::test]
+async fn suppressed_addresses_cannot_subscribe_again() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let (email, _) = subscriber(&app).await;
+    app.post_email_event(&bounce(&email.to_uppercase(), "BadEmailAddress"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_urlencoded::to_string([("name", "le guin"), ("email", &email)]).unwrap();
+    let response = app.post_subscription(body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Same answer as for anyone else, so the endpoint doesn't leak who is suppressed.
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber(&app).await.1, "bounced");
+}
+
+#[tokio::test]
+async fn other_addresses_are_unaffected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_email_event(&bounce("someone-else@example.com", "HardBounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+
+    assert_eq!(subscriber(&app).await.1, "confirmed");
+    assert_eq!(queued_deliveries(&app).await, 1);
+}
@@ -0,0 +1,229 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const HTML: &str = r#"<p>Read <a href="https://example.com/post?id=1&amp;ref=mail">the post</a>
+or <a href="https://example.com/archive">the archive</a>.</p>"#;
+
+async fn publish_newsletter(app: &TestApp, track_engagement: bool) -> Uuid {
+    app.test_user.login(app).await;
+    let resp = app
+        .post_newsletters(&serde_json::json!({
+            "title": "newsletter",
+            "content": {
+                "text": "Newsletter body",
+                "html": HTML,
+            },
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "track_engagement": track_engagement,
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    sqlx::query_scalar!("SELECT newsletter_id FROM newsletters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+/// Publishes an issue to one subscriber and returns the HTML they received.
+async fn deliver_newsletter(app: &TestApp, track_engagement: bool) -> (Uuid, String) {
+    create_confirmed_subscriber(app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let newsletter_id = publish_newsletter(app, track_engagement).await;
+    app.dispatch_all_pending_emails().await;
+
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    (
+        newsletter_id,
+        body["HtmlBody"].as_str().unwrap().to_string(),
+    )
+}
+
+/// Every tracking URL in `html` with the given path, pointed at the test server.
+fn tracking_links(app: &TestApp, html: &str, tracking_path: &str) -> Vec<reqwest::Url> {
+    linkify::LinkFinder::new()
+        .links(html)
+        .filter(|l| l.as_str().contains(tracking_path))
+        .map(|l| {
+            let mut url = reqwest::Url::parse(l.as_str()).unwrap();
+            url.set_port(Some(app.port)).unwrap();
+            url
+        })
+        .collect()
+}
+
+async fn engagement_report(app: &TestApp, newsletter_id: Uuid) -> serde_json::Value {
+    let response = app.get_newsletter_engagement(newsletter_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn untracked_issues_go_out_unchanged() {
+    let app = spawn_app().await;
+
+    let (_, html) = deliver_newsletter(&app, false).await;
+
+    assert!(html.contains(r#"href="https://example.com/archive""#));
+    assert!(!html.contains("/tracking/"));
+}
+
+#[tokio::test]
+async fn tracked_issues_have_their_links_rewritten_and_a_pixel() {
+    let app = spawn_app().await;
+
+    let (_, html) = deliver_newsletter(&app, true).await;
+
+    assert_eq!(tracking_links(&app, &html, "/tracking/click").len(), 2);
+    assert_eq!(tracking_links(&app, &html, "/tracking/open").len(), 1);
+    assert!(!html.contains("https://example.com"));
+    assert!(html.contains("/subscriptions/unsubscribe?token="));
+}
+
+#[tokio::test]
+async fn clicking_a_tracked_link_redirects_to_the_original_url() {
+    let app = spawn_app().await;
+    let (_, html) = deliver_newsletter(&app, true).await;
+    let link = tracking_links(&app, &html, "/tracking/click").remove(0);
+
+    let response = app.api_client.get(link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/post?id=1&ref=mail"
+    );
+}
+
+#[tokio::test]
+async fn the_pixel_is_a_gif() {
+    let app = spawn_app().await;
+    let (_, html) = deliver_newsletter(&app, true).await;
+    let pixel = tracking_links(&app, &html, "/tracking/open").remove(0);
+
+    let response = app.api_client.get(pixel).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+}
+
+#[tokio::test]
+async fn tampered_tracking_links_are_rejected() {
+    let app = spawn_app().await;
+    let (_, html) = deliver_newsletter(&app, true).await;
+
+    for tracking_path in ["/tracking/click", "/tracking/open"] {
+        let mut link = tracking_links(&app, &html, tracking_path).remove(0);
+        let token = link.query_pairs().next().unwrap().1.to_string();
+        link.set_query(Some(&format!("token=x{}", token)));
+
+        let response = app.api_client.get(link).send().await.unwrap();
+
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
+
+#[tokio::test]
+async fn the_engagement_report_counts_unique_opens_and_clicks() {
+    let app = spawn_app().await;
+    let (newsletter_id, html) = deliver_newsletter(&app, true).await;
+    let clicks = tracking_links(&app, &html, "/tracking/click");
+    let pixel = tracking_links(&app, &html, "/tracking/open").remove(0);
+
+    app.api_client.get(pixel.clone()).send().await.unwrap();
+    app.api_client.get(pixel).send().await.unwrap();
+    for _ in 0..3 {
+        app.api_client.get(clicks[1].clone()).send().await.unwrap();
+    }
+    app.api_client.get(clicks[0].clone()).send().await.unwrap();
+
+    let report = engagement_report(&app, newsletter_id).await;
+    assert_eq!(report["track_engagement"], true);
+    assert_eq!(report["delivered"], 1);
+    assert_eq!(report["unique_opens"], 1);
+    assert_eq!(report["unique_clicks"], 1);
+    assert_eq!(report["open_rate"], 1.0);
+    assert_eq!(report["click_rate"], 1.0);
+    assert_eq!(
+        report["top_links"],
+        serde_json::json!([
+            {"url": "https://example.com/archive", "clicks": 3, "unique_clicks": 1},
+            {"url": "https://example.com/post?id=1&ref=mail", "clicks": 1, "unique_clicks": 1},
+        ])
+    );
+}
+
+#[tokio::test]
+async fn a_click_without_an_open_still_counts_as_an_open() {
+    let app = spawn_app().await;
+    let (newsletter_id, html) = deliver_newsletter(&app, true).await;
+    let link = tracking_links(&app, &html, "/tracking/click").remove(0);
+
+    app.api_client.get(link).send().await.unwrap();
+
+    let report = engagement_report(&app, newsletter_id).await;
+    assert_eq!(report["unique_opens"], 1);
+    assert_eq!(report["unique_clicks"], 1);
+}
+
+#[tokio::test]
+async fn engagement_of_an_unknown_issue_is_not_found() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_newsletter_engagement(Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_engagement() {
+    let app = spawn_app().await;
+
+    let response = app.get_newsletter_engagement(Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn tracking_can_be_turned_on_for_drafts() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_draft(&serde_json::json!({
+            "title": "draft",
+            "content": {"text": "text", "html": HTML},
+            "track_engagement": true,
+        }))
+        .await;
+    let newsletter_id: Uuid = serde_json::from_value(
+        response.json::<serde_json::Value>().await.unwrap()["newsletter_id"].clone(),
+    )
+    .unwrap();
+    let draft: serde_json::Value = app.get_draft(newsletter_id).await.json().await.unwrap();
+    assert_eq!(draft["track_engagement"], true);
+
+    app.put_draft(
+        newsletter_id,
+        &serde_json::json!({
+            "title": "draft",
+            "content": {"text": "text", "html": HTML},
+        }),
+    )
+    .await;
+    let draft: serde_json::Value = app.get_draft(newsletter_id).await.json().await.unwrap();
+    assert_eq!(draft["track_engagement"], false);
+}
@@ -1,14 +1,22 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
+use secrecy::ExposeSecret;
 use serde_json::Value;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use std::sync::LazyLock;
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 use zero2prod::cloneable_auth_token::{AuthToken, SecretAuthToken};
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::configuration::{get_configuration, DatabaseSettings, Settings};
+use zero2prod::domain::newsletters::enqueue_due_newsletters;
 use zero2prod::email_client::EmailClient;
-use zero2prod::newsletter_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::newsletter_delivery_worker::{
+    try_execute_outbox_task, try_execute_task, ExecutionOutcome,
+};
 use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
@@ -33,6 +41,7 @@ pub struct TestApp {
     pub hmac_secret: SecretAuthToken,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub config: Settings,
 }
 
 impl TestApp {
@@ -41,7 +50,7 @@ impl TestApp {
         Value: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", self.address))
+            .post(format!("{}/login", self.address))
             .form(body)
             .send()
             .await
@@ -50,7 +59,7 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/logout", &self.address))
+            .post(format!("{}/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -61,7 +70,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/password", &self.address))
+            .post(format!("{}/password", &self.address))
             .form(body)
             .send()
             .await
@@ -70,7 +79,7 @@ impl TestApp {
 
     pub async fn post_subscription(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -78,6 +87,40 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_resend_confirmation(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/resend", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_privacy_request(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/privacy", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Posts a provider event authenticated the way Postmark does it, with Basic credentials.
+    pub async fn post_email_event(&self, body: &Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/email-events", &self.address))
+            .basic_auth(
+                "postmark",
+                Some(&self.config.application.webhook_secret.expose_secret().token),
+            )
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_newsletters<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -90,6 +133,329 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn get_dead_letters(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/newsletters/dead_letters", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_requeue_dead_letters<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/newsletters/dead_letters/requeue",
+                &self.address
+            ))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_draft<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/newsletters/drafts", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_drafts(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/newsletters/drafts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_draft(&self, newsletter_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/newsletters/drafts/{}",
+                &self.address, newsletter_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn put_draft<Body>(&self, newsletter_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .put(format!(
+                "{}/newsletters/drafts/{}",
+                &self.address, newsletter_id
+            ))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn delete_draft(&self, newsletter_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .delete(format!(
+                "{}/newsletters/drafts/{}",
+                &self.address, newsletter_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_draft_preview(&self, newsletter_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/newsletters/drafts/{}/preview",
+                &self.address, newsletter_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_draft_test<Body>(&self, newsletter_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/newsletters/drafts/{}/test",
+                &self.address, newsletter_id
+            ))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_publish_draft<Body>(
+        &self,
+        newsletter_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/newsletters/drafts/{}/publish",
+                &self.address, newsletter_id
+            ))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_scheduled_newsletters(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/newsletters/scheduled", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn put_scheduled_newsletter<Body>(
+        &self,
+        newsletter_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .put(format!(
+                "{}/newsletters/scheduled/{}",
+                &self.address, newsletter_id
+            ))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn delete_scheduled_newsletter(&self, newsletter_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .delete(format!(
+                "{}/newsletters/scheduled/{}",
+                &self.address, newsletter_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_newsletter_report(&self, newsletter_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/newsletters/{}/report",
+                &self.address, newsletter_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_newsletter_engagement(&self, newsletter_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/newsletters/{}/engagement",
+                &self.address, newsletter_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_lists(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_list(&self, body: &Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/lists", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscribers(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscribers", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscribers/{}", &self.address, subscriber_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_subscriber_action(
+        &self,
+        subscriber_id: Uuid,
+        action: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/subscribers/{}/{}",
+                &self.address, subscriber_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_subscriber_tags(
+        &self,
+        subscriber_id: Uuid,
+        tags: &[&str],
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/subscribers/{}/tags",
+                &self.address, subscriber_id
+            ))
+            .json(&serde_json::json!({ "tags": tags }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn delete_subscriber_tag(&self, subscriber_id: Uuid, tag: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!(
+                "{}/subscribers/{}/tags/{}",
+                &self.address, subscriber_id, tag
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_segments(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/segments", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_segment(&self, body: &Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/segments", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_segment_preview(
+        &self,
+        segment_id: &str,
+        query: &[(&str, &str)],
+    ) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/segments/{}/preview", &self.address, segment_id))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_subscribers_import(
+        &self,
+        query: &[(&str, &str)],
+        body: impl Into<reqwest::Body>,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscribers/import", &self.address))
+            .query(query)
+            .header("Content-Type", "text/csv")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscribers_export(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscribers/export", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn delete_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .delete(format!("{}/subscribers/{}", &self.address, subscriber_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -110,19 +476,41 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_links(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_links(&body["TextBody"].as_str().unwrap());
+        let html = get_links(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_links(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
+
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let links: Vec<_> = linkify::LinkFinder::new()
+            .links(body["TextBody"].as_str().unwrap())
+            .filter(|l| *l.kind() == linkify::LinkKind::Url)
+            .filter(|l| l.as_str().contains("/subscriptions/unsubscribe"))
+            .collect();
+
+        assert_eq!(links.len(), 1);
+        reqwest::Url::parse(links[0].as_str()).unwrap()
+    }
 }
 
 impl TestApp {
     pub async fn dispatch_all_pending_emails(&self) {
+        enqueue_due_newsletters(&self.db_pool).await.unwrap();
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            let outbox = try_execute_outbox_task(&self.db_pool, &self.email_client)
+                .await
+                .unwrap();
+            let newsletters = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.address,
+                &self.hmac_secret,
+            )
+            .await
+            .unwrap();
+            if let (ExecutionOutcome::EmptyQueue, ExecutionOutcome::EmptyQueue) =
+                (outbox, newsletters)
             {
                 break;
             }
@@ -130,6 +518,49 @@ impl TestApp {
     }
 }
 
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+    let body = serde_urlencoded::to_string(serde_json::json!(
+    {    "name": name,
+        "email":email
+    }))
+    .unwrap();
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscription(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_links = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
 pub struct ConfirmationLinks {
     pub html: reqwest::Url,
     pub plain_text: reqwest::Url,
@@ -169,7 +600,7 @@ impl TestUser {
             Version::V0x13,
             Params::new(15000, 2, 1, None).unwrap(),
         )
-        .hash_password(&self.password.as_bytes(), &salt)
+        .hash_password(self.password.as_bytes(), &salt)
         .unwrap()
         .to_string();
         sqlx::query!(
@@ -195,7 +626,7 @@ pub async fn spawn_app() -> TestApp {
         let mut c = get_configuration().expect("Failed to read configuration.");
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
-        c.email_client.base_url = email_server.uri();
+        c.email_client.provider.base_url = email_server.uri();
         c
     };
 
@@ -206,7 +637,7 @@ pub async fn spawn_app() -> TestApp {
         .expect("Failed to build application.");
     let address = format!("http://127.0.0.1:{}", application.port());
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    drop(tokio::spawn(application.run_until_stopped()));
 
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
@@ -220,9 +651,10 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         port: application_port,
         test_user: TestUser::generate(),
-        hmac_secret: config.application.hmac_secret,
+        hmac_secret: config.application.hmac_secret.clone(),
         api_client: client,
-        email_client: config.email_client.client(),
+        email_client: config.email_client.clone().client(),
+        config,
     };
 
     test_app.test_user.store(&test_app.db_pool).await;
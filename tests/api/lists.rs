@@ -0,0 +1,315 @@
+use crate::helpers::{spawn_app, TestApp};
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::unsubscribe::parse_unsubscribe_token;
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn create_list(app: &TestApp, slug: &str) {
+    let response = app
+        .post_list(&json!({ "slug": slug, "name": "Release notes" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+/// Subscribes `EMAIL` to `list` and returns the confirmation link that arrives.
+async fn subscribe_to(app: &TestApp, list: &str) -> reqwest::Url {
+    let body = serde_urlencoded::to_string([("name", "le guin"), ("email", EMAIL), ("list", list)])
+        .unwrap();
+    app.post_subscription(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+async fn confirm(link: reqwest::Url) {
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn memberships(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT list_slug, status FROM list_memberships ORDER BY list_slug")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|m| (m.list_slug, m.status))
+        .collect()
+}
+
+async fn publish_to(app: &TestApp, list: &str) -> reqwest::Response {
+    app.post_newsletters(&json!({
+        "title": "Issue",
+        "content": { "text": "Issue body", "html": "<p>Issue body</p>" },
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list": list,
+    }))
+    .await
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn admins_can_create_and_list_mailing_lists() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    create_list(&app, "release-notes").await;
+    let lists: Value = app.get_lists().await.json().await.unwrap();
+
+    let slugs: Vec<_> = lists
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| l["slug"].as_str().unwrap())
+        .collect();
+    assert_eq!(slugs, ["newsletter", "release-notes"]);
+    assert_eq!(lists[1]["name"], "Release notes");
+    assert_eq!(lists[1]["confirmed"], 0);
+}
+
+#[tokio::test]
+async fn creating_a_list_twice_is_a_conflict() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "release-notes").await;
+
+    let response = app
+        .post_list(&json!({ "slug": "release-notes", "name": "Again" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn lists_need_a_valid_slug_and_a_name() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for (body, description) in [
+        (
+            json!({ "slug": "Release Notes", "name": "Release notes" }),
+            "bad slug",
+        ),
+        (
+            json!({ "slug": "release-notes", "name": "  " }),
+            "blank name",
+        ),
+    ] {
+        let response = app.post_list(&body).await;
+        assert_eq!(response.status().as_u16(), 400, "{}", description);
+    }
+}
+
+#[tokio::test]
+async fn managing_lists_requires_login() {
+    let app = spawn_app().await;
+
+    assert_eq!(app.get_lists().await.status().as_u16(), 401);
+    let response = app
+        .post_list(&json!({ "slug": "release-notes", "name": "Release notes" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+
+    let body =
+        serde_urlencoded::to_string([("name", "le guin"), ("email", EMAIL), ("list", "nope")])
+            .unwrap();
+    let response = app.post_subscription(body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn confirming_one_list_leaves_the_others_pending() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    mount_email_server(&app).await;
+    create_list(&app, "release-notes").await;
+
+    let _newsletter_link = subscribe_to(&app, "newsletter").await;
+    let release_notes_link = subscribe_to(&app, "release-notes").await;
+    confirm(release_notes_link).await;
+
+    assert_eq!(
+        memberships(&app).await,
+        [
+            ("newsletter".to_string(), "pending_confirmation".to_string()),
+            ("release-notes".to_string(), "confirmed".to_string()),
+        ]
+    );
+    let status = sqlx::query_scalar!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status.as_deref(), Some("confirmed"));
+}
+
+#[tokio::test]
+async fn issues_only_reach_confirmed_members_of_their_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    mount_email_server(&app).await;
+    create_list(&app, "release-notes").await;
+    confirm(subscribe_to(&app, "newsletter").await).await;
+    let n_before = app.email_server.received_requests().await.unwrap().len();
+
+    let response = publish_to(&app, "release-notes").await;
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        app.email_server.received_requests().await.unwrap().len(),
+        n_before
+    );
+
+    let response = publish_to(&app, "newsletter").await;
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        app.email_server.received_requests().await.unwrap().len(),
+        n_before + 1
+    );
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = publish_to(&app, "nope").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_only_leaves_the_list_the_issue_went_to() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    mount_email_server(&app).await;
+    create_list(&app, "release-notes").await;
+    confirm(subscribe_to(&app, "newsletter").await).await;
+    confirm(subscribe_to(&app, "release-notes").await).await;
+
+    publish_to(&app, "release-notes")
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let unsubscribe_link = app.get_unsubscribe_link(&email_request);
+    let token = unsubscribe_link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .to_string();
+    assert_eq!(
+        parse_unsubscribe_token(&app.hmac_secret, &token)
+            .unwrap()
+            .list_slug
+            .as_deref(),
+        Some("release-notes")
+    );
+    reqwest::Client::new()
+        .post(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        memberships(&app).await,
+        [
+            ("newsletter".to_string(), "confirmed".to_string()),
+            ("release-notes".to_string(), "unsubscribed".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn unsubscribe_links_from_before_lists_leave_every_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    mount_email_server(&app).await;
+    create_list(&app, "release-notes").await;
+    confirm(subscribe_to(&app, "newsletter").await).await;
+    confirm(subscribe_to(&app, "release-notes").await).await;
+    let subscriber_id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let token =
+        zero2prod::signed_token::sign(&app.hmac_secret, "unsubscribe", &subscriber_id.to_string());
+
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions/unsubscribe", app.address))
+        .query(&[("token", token)])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        memberships(&app).await,
+        [
+            ("newsletter".to_string(), "unsubscribed".to_string()),
+            ("release-notes".to_string(), "unsubscribed".to_string()),
+        ]
+    );
+    let status = sqlx::query_scalar!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status.as_deref(), Some("unsubscribed"));
+}
+
+#[tokio::test]
+async fn imports_can_target_a_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "release-notes").await;
+
+    let response = app
+        .post_subscribers_import(
+            &[("list", "release-notes"), ("skip_confirmation", "true")],
+            format!("email,name\n{},le guin\n", EMAIL),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        memberships(&app).await,
+        [("release-notes".to_string(), "confirmed".to_string())]
+    );
+}
@@ -1,8 +1,28 @@
+mod batch_delivery;
 mod change_password;
+mod delivery_retries;
+mod email_events;
+mod engagement;
 mod health_check;
 mod helpers;
+mod lists;
 mod login;
 mod logout;
+mod newsletter_drafts;
+mod newsletter_report;
+mod newsletter_templates;
 mod newsletters;
+mod provider_failover;
+mod scheduled_newsletters;
+mod segments;
+mod subscriber_csv;
+mod subscribers;
 mod subscription_confirm;
+mod subscription_preferences;
+mod subscription_privacy;
+mod subscription_resend;
+mod subscription_sweeper;
+mod subscription_unsubscribe;
 mod subscriptions;
+mod worker_shutdown;
+mod worker_wake_up;
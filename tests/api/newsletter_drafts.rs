@@ -0,0 +1,353 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_draft(app: &TestApp) -> Uuid {
+    let resp = app
+        .post_draft(&serde_json::json!({
+            "title": "Draft title",
+            "content": {
+                "text": "Draft body",
+                "html": "<p>Draft body</p>",
+            }
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 201);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    body["newsletter_id"].as_str().unwrap().parse().unwrap()
+}
+
+async fn newsletter_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM newsletters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+#[tokio::test]
+async fn drafts_are_stored_without_being_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_id = create_draft(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let draft: serde_json::Value = app.get_draft(newsletter_id).await.json().await.unwrap();
+    assert_eq!(draft["title"], "Draft title");
+    assert_eq!(draft["html_content"], "<p>Draft body</p>");
+    assert_eq!(newsletter_status(&app).await, "draft");
+}
+
+#[tokio::test]
+async fn drafts_can_be_listed_edited_and_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_id = create_draft(&app).await;
+
+    let listed: serde_json::Value = app.get_drafts().await.json().await.unwrap();
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+
+    let resp = app
+        .put_draft(
+            newsletter_id,
+            &serde_json::json!({
+                "title": "New title",
+                "content": { "text": "New body", "html": "<p>New body</p>" }
+            }),
+        )
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let draft: serde_json::Value = app.get_draft(newsletter_id).await.json().await.unwrap();
+    assert_eq!(draft["title"], "New title");
+    assert_eq!(draft["text_content"], "New body");
+
+    let resp = app.delete_draft(newsletter_id).await;
+    assert_eq!(resp.status().as_u16(), 204);
+    assert_eq!(app.get_draft(newsletter_id).await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_preview_matches_what_subscribers_receive() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_id = create_draft(&app).await;
+
+    let preview: serde_json::Value = app
+        .get_draft_preview(newsletter_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(preview["subject"], "Draft title");
+    let html_body = preview["html_body"].as_str().unwrap();
+    let text_body = preview["text_body"].as_str().unwrap();
+    assert!(html_body.starts_with("<p>Draft body</p>"));
+    assert!(html_body.contains("/subscriptions/unsubscribe?token="));
+    assert!(text_body.starts_with("Draft body\n\nUnsubscribe: "));
+    assert_eq!(preview["headers"][0]["Name"], "List-Unsubscribe");
+}
+
+#[tokio::test]
+async fn previews_of_tracked_drafts_carry_the_tracking() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let resp = app
+        .post_draft(&serde_json::json!({
+            "title": "Draft title",
+            "content": {
+                "text": "Draft body",
+                "html": r#"<p><a href="https://example.com/post">Read it</a></p>"#,
+            },
+            "track_engagement": true,
+        }))
+        .await;
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let newsletter_id: Uuid = body["newsletter_id"].as_str().unwrap().parse().unwrap();
+
+    let preview: serde_json::Value = app
+        .get_draft_preview(newsletter_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    let html_body = preview["html_body"].as_str().unwrap();
+    assert!(!html_body.contains(r#"href="https://example.com/post""#));
+    assert!(html_body.contains("/tracking/click?token="));
+    assert!(html_body.contains("/tracking/open?token="));
+}
+
+#[tokio::test]
+async fn test_sends_go_to_the_given_address_only() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let newsletter_id = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let resp = app
+        .post_draft_test(
+            newsletter_id,
+            &serde_json::json!({ "email": "editor@example.com" }),
+        )
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(resp.status().as_u16(), 200);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "editor@example.com");
+    assert_eq!(body["Subject"], "Draft title");
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+    assert_eq!(newsletter_status(&app).await, "draft");
+}
+
+#[tokio::test]
+async fn test_sends_to_invalid_addresses_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_id = create_draft(&app).await;
+
+    let resp = app
+        .post_draft_test(
+            newsletter_id,
+            &serde_json::json!({ "email": "not-an-email" }),
+        )
+        .await;
+
+    assert_eq!(resp.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn test_sends_report_provider_failures() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_id = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let resp = app
+        .post_draft_test(
+            newsletter_id,
+            &serde_json::json!({ "email": "editor@example.com" }),
+        )
+        .await;
+
+    assert_eq!(resp.status().as_u16(), 502);
+}
+
+#[tokio::test]
+async fn published_drafts_go_through_sending_to_sent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let newsletter_id = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let resp = app
+        .post_publish_draft(newsletter_id, &serde_json::json!({}))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    assert_eq!(newsletter_status(&app).await, "sending");
+
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(newsletter_status(&app).await, "sent");
+}
+
+#[tokio::test]
+async fn published_drafts_go_to_the_list_they_name() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_list(&serde_json::json!({ "slug": "release-notes", "name": "Release notes" }))
+        .await
+        .error_for_status()
+        .unwrap();
+    let resp = app
+        .post_draft(&serde_json::json!({
+            "title": "Draft title",
+            "content": { "text": "Draft body", "html": "<p>Draft body</p>" },
+            "list": "release-notes",
+        }))
+        .await;
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let newsletter_id: Uuid = body["newsletter_id"].as_str().unwrap().parse().unwrap();
+    let draft: serde_json::Value = app.get_draft(newsletter_id).await.json().await.unwrap();
+    assert_eq!(draft["list_slug"], "release-notes");
+
+    // The only subscriber is on the default list.
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_publish_draft(newsletter_id, &serde_json::json!({}))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(newsletter_status(&app).await, "sent");
+}
+
+#[tokio::test]
+async fn drafts_for_an_unknown_list_or_segment_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_id = create_draft(&app).await;
+
+    for (audience, description) in [
+        (serde_json::json!({ "list": "nope" }), "unknown list"),
+        (
+            serde_json::json!({ "segment": Uuid::new_v4() }),
+            "unknown segment",
+        ),
+    ] {
+        let mut body = serde_json::json!({
+            "title": "Draft title",
+            "content": { "text": "Draft body", "html": "<p>Draft body</p>" },
+        });
+        body.as_object_mut()
+            .unwrap()
+            .extend(audience.as_object().unwrap().clone());
+
+        let post = app.post_draft(&body).await;
+        let put = app.put_draft(newsletter_id, &body).await;
+
+        assert_eq!(post.status().as_u16(), 400, "{}", description);
+        assert_eq!(put.status().as_u16(), 400, "{}", description);
+    }
+}
+
+#[tokio::test]
+async fn drafts_published_with_a_send_at_are_scheduled() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_id = create_draft(&app).await;
+
+    let resp = app
+        .post_publish_draft(
+            newsletter_id,
+            &serde_json::json!({ "send_at": chrono::Utc::now() + chrono::Duration::hours(1) }),
+        )
+        .await;
+
+    assert_eq!(resp.status().as_u16(), 200);
+    assert_eq!(newsletter_status(&app).await, "scheduled");
+}
+
+#[tokio::test]
+async fn published_issues_are_no_longer_editable() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_id = create_draft(&app).await;
+    app.post_publish_draft(newsletter_id, &serde_json::json!({}))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let put = app
+        .put_draft(
+            newsletter_id,
+            &serde_json::json!({
+                "title": "New title",
+                "content": { "text": "New body", "html": "<p>New body</p>" }
+            }),
+        )
+        .await;
+    let delete = app.delete_draft(newsletter_id).await;
+    let publish = app
+        .post_publish_draft(newsletter_id, &serde_json::json!({}))
+        .await;
+
+    assert_eq!(put.status().as_u16(), 404);
+    assert_eq!(delete.status().as_u16(), 404);
+    assert_eq!(publish.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_drafts() {
+    let app = spawn_app().await;
+
+    let resp = app.get_drafts().await;
+
+    assert_eq!(resp.status().as_u16(), 401);
+}
@@ -0,0 +1,137 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp) -> Uuid {
+    let resp = app
+        .post_newsletters(&serde_json::json!({
+            "title": "newsletter",
+            "content": {
+                "text": "Newsletter body",
+                "html": "<p>Newsletter body</p>",
+            },
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    sqlx::query!("SELECT newsletter_id FROM newsletters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_id
+}
+
+async fn get_report(app: &TestApp, newsletter_id: Uuid) -> serde_json::Value {
+    let resp = app.get_newsletter_report(newsletter_id).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    resp.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn deliveries_are_pending_until_the_worker_runs() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let newsletter_id = publish_newsletter(&app).await;
+    let report = get_report(&app, newsletter_id).await;
+
+    assert_eq!(report["pending"], 1);
+    assert_eq!(report["sent"], 0);
+    assert_eq!(report["recipients"][0]["status"], "pending");
+    assert_eq!(report["recipients"][0]["n_attempts"], 0);
+}
+
+#[tokio::test]
+async fn sent_deliveries_are_reported_with_the_provider_message_id() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({"MessageID": "provider-message-id"})),
+        )
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    let report = get_report(&app, newsletter_id).await;
+
+    assert_eq!(report["sent"], 1);
+    assert_eq!(report["pending"], 0);
+    let recipient = &report["recipients"][0];
+    assert_eq!(recipient["status"], "sent");
+    assert_eq!(recipient["n_attempts"], 1);
+    assert_eq!(recipient["provider_message_id"], "provider-message-id");
+}
+
+#[tokio::test]
+async fn retried_deliveries_stay_pending_and_count_attempts() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    let report = get_report(&app, newsletter_id).await;
+
+    assert_eq!(report["pending"], 1);
+    let recipient = &report["recipients"][0];
+    assert_eq!(recipient["status"], "pending");
+    assert_eq!(recipient["n_attempts"], 1);
+    assert!(recipient["last_error"].as_str().unwrap().contains("500"));
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_reported() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    let report = get_report(&app, newsletter_id).await;
+
+    assert_eq!(report["failed"], 1);
+    assert_eq!(report["pending"], 0);
+    assert_eq!(report["recipients"][0]["status"], "failed");
+}
+
+#[tokio::test]
+async fn reports_for_unknown_newsletters_return_404() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let resp = app.get_newsletter_report(Uuid::new_v4()).await;
+
+    assert_eq!(resp.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn reports_require_authentication() {
+    let app = spawn_app().await;
+
+    let resp = app.get_newsletter_report(Uuid::new_v4()).await;
+
+    assert_eq!(resp.status().as_u16(), 401);
+}
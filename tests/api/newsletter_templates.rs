@@ -0,0 +1,169 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish(app: &TestApp, html: &str, text: &str) -> reqwest::Response {
+    app.post_newsletters(&serde_json::json!({
+        "title": "Issue <1>",
+        "content": { "html": html, "text": text },
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await
+}
+
+async fn delivered_body(app: &TestApp) -> serde_json::Value {
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    serde_json::from_slice(&email_request.body).unwrap()
+}
+
+#[tokio::test]
+async fn merge_fields_are_filled_in_per_recipient() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let subscriber = sqlx::query!("SELECT name, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let resp = publish(
+        &app,
+        "<p>Hi {{ subscriber.name }}</p>",
+        "Hi {{subscriber.name}} <{{ subscriber.email }}>, this is {{ issue.title }}",
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 200);
+
+    let body = delivered_body(&app).await;
+    assert!(body["TextBody"].as_str().unwrap().starts_with(&format!(
+        "Hi {} <{}>, this is Issue <1>",
+        subscriber.name, subscriber.email
+    )));
+    assert!(body["HtmlBody"].as_str().unwrap().starts_with("<p>Hi "));
+}
+
+#[tokio::test]
+async fn merge_fields_are_escaped_in_html_bodies() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish(&app, "<h1>{{ issue.title }}</h1>", "{{ issue.title }}")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let body = delivered_body(&app).await;
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<h1>Issue &lt;1&gt;</h1>"));
+    assert!(body["TextBody"].as_str().unwrap().starts_with("Issue <1>"));
+}
+
+#[tokio::test]
+async fn templates_placing_the_unsubscribe_url_get_no_extra_footer() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish(
+        &app,
+        "<a href=\"{{ unsubscribe_url }}\">Leave</a>",
+        "Leave: {{ unsubscribe_url }}",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let body = delivered_body(&app).await;
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<a href=\"http://127.0.0.1"));
+    assert!(!html_body.contains(">Unsubscribe</a>"));
+    assert!(text_body.starts_with("Leave: http://127.0.0.1"));
+    assert!(!text_body.contains("Unsubscribe:"));
+}
+
+#[tokio::test]
+async fn newsletters_with_bad_placeholders_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let cases = [
+        (
+            "<p>{{ subscriber.age }}</p>",
+            "text",
+            "unknown field in html",
+        ),
+        ("<p>html</p>", "Hi {{ name }}", "unknown field in text"),
+        ("<p>{{ issue.title</p>", "text", "unclosed placeholder"),
+        ("<p>{{}}</p>", "text", "empty placeholder"),
+    ];
+    for (html, text, case) in cases {
+        let resp = publish(&app, html, text).await;
+        assert_eq!(resp.status().as_u16(), 400, "{}", case);
+    }
+    let n_newsletters = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletters"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_newsletters, 0);
+}
+
+#[tokio::test]
+async fn drafts_with_bad_placeholders_cannot_be_published() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let resp = app
+        .post_draft(&serde_json::json!({
+            "title": "Draft",
+            "content": { "html": "<p>{{ nope }}</p>", "text": "text" }
+        }))
+        .await;
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let newsletter_id: Uuid = body["newsletter_id"].as_str().unwrap().parse().unwrap();
+
+    let publish = app
+        .post_publish_draft(newsletter_id, &serde_json::json!({}))
+        .await;
+    let preview = app.get_draft_preview(newsletter_id).await;
+
+    assert_eq!(publish.status().as_u16(), 400);
+    assert_eq!(preview.status().as_u16(), 400);
+    let saved = sqlx::query!("SELECT status FROM newsletters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "draft");
+}
@@ -0,0 +1,103 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::email_client::EmailClient;
+use zero2prod::newsletter_delivery_worker::try_execute_task;
+
+/// A client that sends through the app's mock server first and `secondary` second.
+fn client_with_failover(app: &TestApp, secondary: &MockServer) -> EmailClient {
+    let mut settings = app.config.email_client.clone();
+    settings.provider.name = Some("primary".into());
+    let mut failover = settings.provider.clone();
+    failover.name = Some("secondary".into());
+    failover.base_url = secondary.uri();
+    settings.failover = vec![failover];
+    settings.client()
+}
+
+async fn publish_newsletter(app: &TestApp) -> Uuid {
+    app.test_user.login(app).await;
+    let resp = app
+        .post_newsletters(&serde_json::json!({
+            "title": "newsletter",
+            "content": {
+                "text": "Newsletter body",
+                "html": "<p>Newsletter body</p>",
+            },
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    sqlx::query_scalar!("SELECT newsletter_id FROM newsletters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn deliveries_fail_over_when_the_primary_returns_5xx() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let secondary = MockServer::start().await;
+    let email_client = client_with_failover(&app, &secondary);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&secondary)
+        .await;
+
+    let newsletter_id = publish_newsletter(&app).await;
+    try_execute_task(&app.db_pool, &email_client, &app.address, &app.hmac_secret)
+        .await
+        .unwrap();
+
+    let report: serde_json::Value = app
+        .get_newsletter_report(newsletter_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["sent"], 1);
+    assert_eq!(report["recipients"][0]["provider"], "secondary");
+    assert_eq!(
+        report["sent_by_provider"],
+        serde_json::json!({"secondary": 1})
+    );
+}
+
+#[tokio::test]
+async fn rejected_emails_do_not_fail_over() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let secondary = MockServer::start().await;
+    let email_client = client_with_failover(&app, &secondary);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&secondary)
+        .await;
+
+    publish_newsletter(&app).await;
+    try_execute_task(&app.db_pool, &email_client, &app.address, &app.hmac_secret)
+        .await
+        .unwrap();
+
+    let dead_letters: serde_json::Value = app.get_dead_letters().await.json().await.unwrap();
+    assert_eq!(dead_letters.as_array().unwrap().len(), 1);
+}
@@ -0,0 +1,200 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn schedule_newsletter(app: &TestApp, send_at: chrono::DateTime<Utc>) -> Uuid {
+    let resp = app
+        .post_newsletters(&serde_json::json!({
+            "title": "scheduled newsletter",
+            "content": {
+                "text": "Newsletter body",
+                "html": "<p>Newsletter body</p>",
+            },
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "send_at": send_at,
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 202);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    body["newsletter_id"].as_str().unwrap().parse().unwrap()
+}
+
+async fn make_due(app: &TestApp) {
+    sqlx::query!("UPDATE newsletters SET send_at = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_not_delivered_before_send_at() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    schedule_newsletter(&app, Utc::now() + Duration::hours(1)).await;
+    app.dispatch_all_pending_emails().await;
+
+    let saved = sqlx::query!("SELECT status, published_at FROM newsletters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "scheduled");
+    assert!(saved.published_at.is_none());
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_delivered_once_due() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    schedule_newsletter(&app, Utc::now() + Duration::hours(1)).await;
+    make_due(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let saved = sqlx::query!("SELECT status, published_at FROM newsletters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "sent");
+    assert!(saved.published_at.is_some());
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_listed_until_they_fire() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_id = schedule_newsletter(&app, Utc::now() + Duration::hours(1)).await;
+
+    let listed: serde_json::Value = app.get_scheduled_newsletters().await.json().await.unwrap();
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    assert_eq!(listed[0]["newsletter_id"], newsletter_id.to_string());
+
+    make_due(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let listed: serde_json::Value = app.get_scheduled_newsletters().await.json().await.unwrap();
+    assert_eq!(listed, serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_can_be_rescheduled() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_id = schedule_newsletter(&app, Utc::now() + Duration::hours(1)).await;
+    let send_at = Utc::now() + Duration::days(2);
+
+    let resp = app
+        .put_scheduled_newsletter(newsletter_id, &serde_json::json!({ "send_at": send_at }))
+        .await;
+
+    assert_eq!(resp.status().as_u16(), 200);
+    let saved = sqlx::query!(r#"SELECT send_at AS "send_at!" FROM newsletters"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.send_at.timestamp_micros(), send_at.timestamp_micros());
+}
+
+#[tokio::test]
+async fn cancelled_newsletters_are_never_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_id = schedule_newsletter(&app, Utc::now() + Duration::hours(1)).await;
+    let resp = app.delete_scheduled_newsletter(newsletter_id).await;
+    assert_eq!(resp.status().as_u16(), 204);
+
+    make_due(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let saved = sqlx::query!("SELECT status FROM newsletters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "cancelled");
+}
+
+#[tokio::test]
+async fn issues_that_already_fired_cannot_be_rescheduled_or_cancelled() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_id = schedule_newsletter(&app, Utc::now() + Duration::hours(1)).await;
+    make_due(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let put = app
+        .put_scheduled_newsletter(
+            newsletter_id,
+            &serde_json::json!({ "send_at": Utc::now() + Duration::hours(1) }),
+        )
+        .await;
+    let delete = app.delete_scheduled_newsletter(newsletter_id).await;
+
+    assert_eq!(put.status().as_u16(), 404);
+    assert_eq!(delete.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_send_at_in_the_past_publishes_immediately() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let resp = app
+        .post_newsletters(&serde_json::json!({
+            "title": "newsletter",
+            "content": {
+                "text": "Newsletter body",
+                "html": "<p>Newsletter body</p>",
+            },
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "send_at": Utc::now() - Duration::hours(1),
+        }))
+        .await;
+
+    assert_eq!(resp.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM newsletters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "sent");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_scheduled_newsletters() {
+    let app = spawn_app().await;
+    let newsletter_id = Uuid::new_v4();
+
+    let list = app.get_scheduled_newsletters().await;
+    let put = app
+        .put_scheduled_newsletter(newsletter_id, &serde_json::json!({ "send_at": Utc::now() }))
+        .await;
+    let delete = app.delete_scheduled_newsletter(newsletter_id).await;
+
+    for resp in [list, put, delete] {
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Three confirmed subscribers, oldest first: the first is tagged `beta` but joined long ago,
+/// the second is tagged `beta`, the third has no tags.
+async fn seed_subscribers(app: &TestApp) -> Vec<Uuid> {
+    for _ in 0..3 {
+        create_confirmed_subscriber(app).await;
+    }
+    let ids: Vec<Uuid> = sqlx::query_scalar!("SELECT id FROM subscriptions ORDER BY subscribed_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        "UPDATE subscriptions SET subscribed_at = now() - interval '90 days' WHERE id = $1",
+        ids[0]
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    for id in &ids[..2] {
+        let response = app.post_subscriber_tags(*id, &["Beta"]).await;
+        assert_eq!(response.status().as_u16(), 204);
+    }
+    ids
+}
+
+async fn create_segment(app: &TestApp, filter: Value) -> String {
+    let response = app
+        .post_segment(&json!({ "name": "Recent beta testers", "filter": filter }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let segment: Value = response.json().await.unwrap();
+    segment["segment_id"].as_str().unwrap().to_string()
+}
+
+/// "tag = beta AND subscribed in the last 30 days".
+fn recent_beta() -> Value {
+    json!({ "tags": ["beta"], "subscribed_within_days": 30 })
+}
+
+#[tokio::test]
+async fn tags_show_up_on_the_subscriber_and_can_be_removed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let ids = seed_subscribers(&app).await;
+
+    let subscriber: Value = app.get_subscriber(ids[1]).await.json().await.unwrap();
+    assert_eq!(subscriber["tags"], json!(["beta"]));
+
+    let response = app.delete_subscriber_tag(ids[1], "beta").await;
+    assert_eq!(response.status().as_u16(), 204);
+    let subscriber: Value = app.get_subscriber(ids[1]).await.json().await.unwrap();
+    assert_eq!(subscriber["tags"], json!([]));
+    let response = app.delete_subscriber_tag(ids[1], "beta").await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn tagging_needs_a_known_subscriber_and_valid_tags() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_subscriber_tags(Uuid::new_v4(), &["beta"]).await;
+    assert_eq!(response.status().as_u16(), 404);
+    let response = app.post_subscriber_tags(id, &["beta testers"]).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn segments_can_be_created_and_listed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let segment_id = create_segment(&app, recent_beta()).await;
+    let segments: Value = app.get_segments().await.json().await.unwrap();
+
+    assert_eq!(segments[0]["segment_id"], segment_id);
+    assert_eq!(segments[0]["name"], "Recent beta testers");
+    assert_eq!(segments[0]["filter"]["tags"], json!(["beta"]));
+    assert_eq!(segments[0]["filter"]["subscribed_within_days"], 30);
+}
+
+#[tokio::test]
+async fn segments_need_a_unique_name_and_a_valid_filter() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_segment(&app, recent_beta()).await;
+
+    let response = app
+        .post_segment(&json!({ "name": "Recent beta testers", "filter": {} }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+    for (filter, description) in [
+        (json!({ "tag": "beta" }), "unknown condition"),
+        (json!({ "tags": ["beta testers"] }), "invalid tag"),
+        (json!({ "subscribed_within_days": 0 }), "empty window"),
+    ] {
+        let response = app
+            .post_segment(&json!({ "name": description, "filter": filter }))
+            .await;
+        assert_eq!(response.status().as_u16(), 400, "{}", description);
+    }
+}
+
+#[tokio::test]
+async fn the_preview_counts_confirmed_subscribers_in_the_segment() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    seed_subscribers(&app).await;
+    let recent_beta = create_segment(&app, recent_beta()).await;
+
+    let preview: Value = app
+        .get_segment_preview(&recent_beta, &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(preview["count"], 1);
+    assert_eq!(preview["list"], "newsletter");
+}
+
+#[tokio::test]
+async fn previewing_an_unknown_segment_is_a_404() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .get_segment_preview(&Uuid::new_v4().to_string(), &[])
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn issues_sent_to_a_segment_only_reach_its_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let ids = seed_subscribers(&app).await;
+    let segment_id = create_segment(
+        &app,
+        json!({ "any_tags": ["beta", "alpha"], "exclude_tags": ["staff"] }),
+    )
+    .await;
+    app.post_subscriber_tags(ids[0], &["staff"]).await;
+    let recipient = sqlx::query_scalar!("SELECT email FROM subscriptions WHERE id = $1", ids[1])
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&json!({
+            "title": "Beta news",
+            "content": { "text": "Beta body", "html": "<p>Beta body</p>" },
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "segment": segment_id,
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], recipient);
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_segment_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&json!({
+            "title": "Beta news",
+            "content": { "text": "Beta body", "html": "<p>Beta body</p>" },
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "segment": Uuid::new_v4(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn managing_segments_and_tags_requires_login() {
+    let app = spawn_app().await;
+    let id = Uuid::new_v4();
+
+    let responses = [
+        app.get_segments().await,
+        app.post_segment(&json!({ "name": "All", "filter": {} }))
+            .await,
+        app.get_segment_preview(&id.to_string(), &[]).await,
+        app.post_subscriber_tags(id, &["beta"]).await,
+        app.delete_subscriber_tag(id, "beta").await,
+    ];
+
+    for response in responses {
+        assert_eq!(response.status().as_u16(), 401, "{}", response.url());
+    }
+}
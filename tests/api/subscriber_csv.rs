@@ -0,0 +1,321 @@
+use crate::helpers::{spawn_app, TestApp};
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn statuses(app: &TestApp) -> Vec<(String, Option<String>)> {
+    sqlx::query!("SELECT email, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.email, r.status))
+        .collect()
+}
+
+fn pair(email: &str, status: &str) -> (String, Option<String>) {
+    (email.to_string(), Some(status.to_string()))
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_or_export_subscribers() {
+    let app = spawn_app().await;
+
+    let import = app
+        .post_subscribers_import(&[], "email,name\nursula@example.com,Ursula\n")
+        .await;
+    let export = app.get_subscribers_export(&[]).await;
+
+    assert_eq!(import.status().as_u16(), 401);
+    assert_eq!(export.status().as_u16(), 401);
+    assert!(statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn imported_subscribers_must_confirm_by_default() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscribers_import(
+            &[],
+            "email,name\nursula@example.com,Ursula Le Guin\noctavia@example.com,Octavia Butler\n",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 2);
+    assert_eq!(report["errors"], json!([]));
+    assert_eq!(
+        statuses(&app).await,
+        vec![
+            pair("octavia@example.com", "pending_confirmation"),
+            pair("ursula@example.com", "pending_confirmation"),
+        ]
+    );
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn consented_contacts_can_skip_double_opt_in() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscribers_import(
+            &[("skip_confirmation", "true")],
+            "email,name,status\n\
+             ursula@example.com,Ursula,\n\
+             octavia@example.com,Octavia,pending_confirmation\n\
+             iain@example.com,Iain,unsubscribed\n",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        statuses(&app).await,
+        vec![
+            pair("iain@example.com", "unsubscribed"),
+            pair("octavia@example.com", "pending_confirmation"),
+            pair("ursula@example.com", "confirmed"),
+        ]
+    );
+    // Only the contact still pending gets a confirmation link.
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn invalid_rows_are_reported_and_the_rest_imported() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_subscribers_import(
+            &[("skip_confirmation", "true")],
+            "email,name,subscribed_at\n\
+             ursula@example.com,Ursula,2025-01-10T00:00:00Z\n\
+             not-an-email,Nobody,\n\
+             octavia@example.com,,\n\
+             iain@example.com,Iain,yesterday\n\
+             \"n.k@example.com\",\"Jemisin, N. K.\",\n",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 2);
+    let rows: Vec<u64> = report["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["row"].as_u64().unwrap())
+        .collect();
+    assert_eq!(rows, vec![3, 4, 5]);
+    assert_eq!(
+        statuses(&app).await,
+        vec![
+            pair("n.k@example.com", "confirmed"),
+            pair("ursula@example.com", "confirmed"),
+        ]
+    );
+    let subscribed_at = sqlx::query_scalar!(
+        "SELECT subscribed_at FROM subscriptions WHERE email = 'ursula@example.com'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(subscribed_at.to_rfc3339(), "2025-01-10T00:00:00+00:00");
+}
+
+#[tokio::test]
+async fn existing_and_suppressed_addresses_are_left_alone() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import(
+        &[("skip_confirmation", "true")],
+        "email,name,status\nursula@example.com,Ursula,unsubscribed\n",
+    )
+    .await;
+    sqlx::query!(
+        r#"
+    INSERT INTO suppressed_emails (email, reason, suppressed_at)
+    VALUES ('bounce@example.com', 'bounced', now())"#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .post_subscribers_import(
+            &[("skip_confirmation", "true")],
+            "email,name\nursula@example.com,Ursula\nBounce@example.com,Bounce\n",
+        )
+        .await;
+
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 0);
+    assert_eq!(report["already_subscribed"], 1);
+    assert_eq!(report["suppressed"], 1);
+    assert_eq!(
+        statuses(&app).await,
+        vec![pair("ursula@example.com", "unsubscribed")]
+    );
+}
+
+#[tokio::test]
+async fn unknown_columns_are_stored_as_custom_fields() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_subscribers_import(
+        &[("skip_confirmation", "true")],
+        "Email,Name,company,city\nursula@example.com,Ursula,Earthsea,\n",
+    )
+    .await;
+
+    let attributes = sqlx::query_scalar!("SELECT attributes FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(attributes, json!({"company": "Earthsea"}));
+}
+
+#[tokio::test]
+async fn files_without_the_required_columns_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for body in ["", "email,status\nursula@example.com,confirmed\n"] {
+        let response = app.post_subscribers_import(&[], body).await;
+        assert_eq!(response.status().as_u16(), 400, "body: {:?}", body);
+    }
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import(
+        &[("skip_confirmation", "true")],
+        "email,name,status,subscribed_at,company\n\
+         ursula@example.com,\"Le Guin, Ursula\",confirmed,2025-01-10T00:00:00Z,Earthsea\n\
+         iain@example.com,Iain Banks,unsubscribed,2025-03-10T00:00:00Z,\n\
+         octavia@example.com,Octavia Butler,confirmed,2025-02-10T00:00:00Z,\n",
+    )
+    .await;
+
+    let response = app.get_subscribers_export(&[("status", "confirmed")]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    assert_eq!(
+        response.text().await.unwrap(),
+        "email,name,status,subscribed_at,company\n\
+         ursula@example.com,\"Le Guin, Ursula\",confirmed,2025-01-10T00:00:00+00:00,Earthsea\n\
+         octavia@example.com,Octavia Butler,confirmed,2025-02-10T00:00:00+00:00,\n"
+    );
+}
+
+#[tokio::test]
+async fn an_export_can_be_imported_back() {
+    let source = spawn_app().await;
+    source.test_user.login(&source).await;
+    source
+        .post_subscribers_import(
+            &[("skip_confirmation", "true")],
+            "email,name,status,city\n\
+             ursula@example.com,Ursula,,Portland\n\
+             iain@example.com,Iain,unsubscribed,\n",
+        )
+        .await;
+    let export = source
+        .get_subscribers_export(&[])
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    let target = spawn_app().await;
+    target.test_user.login(&target).await;
+    let response = target
+        .post_subscribers_import(&[("skip_confirmation", "true")], export)
+        .await;
+
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 2);
+    assert_eq!(statuses(&target).await, statuses(&source).await);
+}
+
+#[tokio::test]
+async fn bounced_and_complained_subscribers_survive_a_round_trip() {
+    let source = spawn_app().await;
+    source.test_user.login(&source).await;
+    source
+        .post_subscribers_import(
+            &[("skip_confirmation", "true")],
+            "email,name
+ursula@example.com,Ursula
+iain@example.com,Iain
+",
+        )
+        .await;
+    sqlx::query!(
+        r#"
+    UPDATE subscriptions SET status = CASE
+        WHEN email = 'ursula@example.com' THEN 'bounced' ELSE 'complained'
+    END"#
+    )
+    .execute(&source.db_pool)
+    .await
+    .unwrap();
+    let export = source
+        .get_subscribers_export(&[])
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(export.contains(",bounced,") && export.contains(",complained,"));
+
+    let target = spawn_app().await;
+    target.test_user.login(&target).await;
+    let response = target
+        .post_subscribers_import(&[("skip_confirmation", "true")], export)
+        .await;
+
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 2);
+    assert_eq!(report["errors"], json!([]));
+    assert_eq!(
+        statuses(&target).await,
+        vec![
+            pair("iain@example.com", "unsubscribed"),
+            pair("ursula@example.com", "unsubscribed")
+        ]
+    );
+}
+
+#[tokio::test]
+async fn invalid_export_filters_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_subscribers_export(&[("status", "famous")]).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,462 @@
+use crate::helpers::{create_unconfirmed_subscriber, spawn_app, TestApp};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use zero2prod::domain::email_events::email_hash;
+
+type Query<'a> = Vec<(&'a str, &'a str)>;
+
+async fn insert_subscriber(
+    app: &TestApp,
+    email: &str,
+    name: &str,
+    status: &str,
+    subscribed_at: &str,
+) -> Uuid {
+    let id = Uuid::new_v4();
+    let subscribed_at: DateTime<Utc> = subscribed_at.parse().unwrap();
+    sqlx::query!(
+        r#"
+    INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+    VALUES ($1, $2, $3, $4, $5)"#,
+        id,
+        email,
+        name,
+        subscribed_at,
+        status
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    id
+}
+
+async fn seed(app: &TestApp) {
+    insert_subscriber(
+        app,
+        "ursula@example.com",
+        "Ursula Le Guin",
+        "confirmed",
+        "2025-01-10T00:00:00Z",
+    )
+    .await;
+    insert_subscriber(
+        app,
+        "octavia@example.com",
+        "Octavia Butler",
+        "pending_confirmation",
+        "2025-02-10T00:00:00Z",
+    )
+    .await;
+    insert_subscriber(
+        app,
+        "iain@example.com",
+        "Iain Banks",
+        "unsubscribed",
+        "2025-03-10T00:00:00Z",
+    )
+    .await;
+    insert_subscriber(
+        app,
+        "n_k@example.com",
+        "N. K. Jemisin",
+        "confirmed",
+        "2025-04-10T00:00:00Z",
+    )
+    .await;
+}
+
+async fn emails(app: &TestApp, query: &[(&str, &str)]) -> Vec<String> {
+    let response = app.get_subscribers(query).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: Value = response.json().await.unwrap();
+    body["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["email"].as_str().unwrap().to_string())
+        .collect()
+}
+
+async fn status(app: &TestApp, id: Uuid) -> Option<String> {
+    sqlx::query_scalar!("SELECT status FROM subscriptions WHERE id = $1", id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_subscribers() {
+    let app = spawn_app().await;
+    let id = Uuid::new_v4();
+
+    for response in [
+        app.get_subscribers(&[]).await,
+        app.get_subscriber(id).await,
+        app.post_subscriber_action(id, "confirm").await,
+        app.post_subscriber_action(id, "unsubscribe").await,
+        app.delete_subscriber(id).await,
+    ] {
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_newest_first() {
+    let app = spawn_app().await;
+    seed(&app).await;
+    app.test_user.login(&app).await;
+
+    assert_eq!(
+        emails(&app, &[]).await,
+        vec![
+            "n_k@example.com",
+            "iain@example.com",
+            "octavia@example.com",
+            "ursula@example.com"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated() {
+    let app = spawn_app().await;
+    seed(&app).await;
+    app.test_user.login(&app).await;
+
+    let body: Value = app
+        .get_subscribers(&[("page", "2"), ("per_page", "3")])
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(body["total"], 4);
+    assert_eq!(body["page"], 2);
+    assert_eq!(body["per_page"], 3);
+    assert_eq!(body["subscribers"].as_array().unwrap().len(), 1);
+    assert_eq!(body["subscribers"][0]["email"], "ursula@example.com");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered() {
+    let app = spawn_app().await;
+    seed(&app).await;
+    app.test_user.login(&app).await;
+
+    let test_cases: Vec<(Query, Vec<&str>)> = vec![
+        (
+            vec![("status", "confirmed")],
+            vec!["n_k@example.com", "ursula@example.com"],
+        ),
+        (
+            vec![
+                ("subscribed_after", "2025-02-01T00:00:00Z"),
+                ("subscribed_before", "2025-04-01T00:00:00Z"),
+            ],
+            vec!["iain@example.com", "octavia@example.com"],
+        ),
+        (vec![("search", "BUTLER")], vec!["octavia@example.com"]),
+        (vec![("search", "iain@")], vec!["iain@example.com"]),
+        // Underscores are matched literally rather than as LIKE wildcards.
+        (vec![("search", "n_k")], vec!["n_k@example.com"]),
+        (
+            vec![("status", "confirmed"), ("search", "ursula")],
+            vec!["ursula@example.com"],
+        ),
+    ];
+
+    for (query, expected) in test_cases {
+        assert_eq!(emails(&app, &query).await, expected, "query: {:?}", query);
+    }
+}
+
+#[tokio::test]
+async fn invalid_list_parameters_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for query in [
+        [("status", "famous")],
+        [("page", "0")],
+        [("per_page", "0")],
+        [("per_page", "10000")],
+        [("subscribed_after", "yesterday")],
+    ] {
+        let response = app.get_subscribers(&query).await;
+        assert_eq!(response.status().as_u16(), 400, "query: {:?}", query);
+    }
+}
+
+#[tokio::test]
+async fn a_subscriber_is_shown_with_their_history() {
+    let app = spawn_app().await;
+    let id = insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Ursula Le Guin",
+        "confirmed",
+        "2025-01-10T00:00:00Z",
+    )
+    .await;
+    let newsletter_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+    INSERT INTO newsletters (newsletter_id, title, text_content, html_content, status)
+    VALUES ($1, 'Issue #1', 'text', '<p>html</p>', 'sent')"#,
+        newsletter_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+    INSERT INTO newsletter_deliveries (
+        newsletter_id, subscriber_email, n_attempts, outcome, first_attempted_at, last_attempted_at
+    )
+    VALUES ($1, 'ursula@example.com', 1, 'sent', now(), now())"#,
+        newsletter_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    let response = app.get_subscriber(id).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["email"], "ursula@example.com");
+    assert_eq!(body["status"], "confirmed");
+    assert_eq!(body["suppressed"], Value::Null);
+    assert_eq!(body["deliveries"][0]["title"], "Issue #1");
+    assert_eq!(body["deliveries"][0]["outcome"], "sent");
+    assert_eq!(body["engagement"], json!([]));
+}
+
+#[tokio::test]
+async fn unknown_subscribers_are_not_found() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let id = Uuid::new_v4();
+
+    assert_eq!(app.get_subscriber(id).await.status().as_u16(), 404);
+    assert_eq!(
+        app.post_subscriber_action(id, "confirm")
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+    assert_eq!(
+        app.post_subscriber_action(id, "unsubscribe")
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+    assert_eq!(app.delete_subscriber(id).await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn admins_can_confirm_a_pending_subscriber() {
+    let app = spawn_app().await;
+    let links = create_unconfirmed_subscriber(&app).await;
+    let id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    let response = app.post_subscriber_action(id, "confirm").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(status(&app, id).await.as_deref(), Some("confirmed"));
+    // The emailed link has been revoked.
+    assert_eq!(
+        reqwest::get(links.html).await.unwrap().status().as_u16(),
+        401
+    );
+}
+
+#[tokio::test]
+async fn confirming_a_subscriber_leaves_lists_they_left_alone() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_list(&json!({ "slug": "release-notes", "name": "Release notes" }))
+        .await
+        .error_for_status()
+        .unwrap();
+    let id = insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Ursula Le Guin",
+        "pending_confirmation",
+        "2025-01-10T00:00:00Z",
+    )
+    .await;
+    sqlx::query!(
+        r#"
+    INSERT INTO list_memberships (list_slug, subscriber_id, status, subscribed_at)
+    VALUES ('newsletter', $1, 'unsubscribed', now()),
+        ('release-notes', $1, 'pending_confirmation', now())"#,
+        id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app.post_subscriber_action(id, "confirm").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let memberships: Vec<_> =
+        sqlx::query!("SELECT list_slug, status FROM list_memberships ORDER BY list_slug")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.list_slug, m.status))
+            .collect();
+    assert_eq!(
+        memberships,
+        [
+            ("newsletter".to_string(), "unsubscribed".to_string()),
+            ("release-notes".to_string(), "confirmed".to_string()),
+        ]
+    );
+    assert_eq!(status(&app, id).await.as_deref(), Some("confirmed"));
+}
+
+#[tokio::test]
+async fn suppressed_subscribers_cannot_be_confirmed() {
+    let app = spawn_app().await;
+    let id = insert_subscriber(
+        &app,
+        "bounce@example.com",
+        "Bounce",
+        "bounced",
+        "2025-01-10T00:00:00Z",
+    )
+    .await;
+    sqlx::query!(
+        r#"
+    INSERT INTO suppressed_emails (email, reason, suppressed_at)
+    VALUES ('bounce@example.com', 'bounced', now())"#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    let response = app.post_subscriber_action(id, "confirm").await;
+
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(status(&app, id).await.as_deref(), Some("bounced"));
+}
+
+#[tokio::test]
+async fn erased_addresses_cannot_be_confirmed() {
+    let app = spawn_app().await;
+    let id = insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Ursula Le Guin",
+        "pending_confirmation",
+        "2025-01-10T00:00:00Z",
+    )
+    .await;
+    sqlx::query!(
+        r#"
+    INSERT INTO suppressed_emails (email, reason, suppressed_at)
+    VALUES ($1, 'erased', now())"#,
+        email_hash(&app.hmac_secret, "ursula@example.com")
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    let response = app.post_subscriber_action(id, "confirm").await;
+
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(
+        status(&app, id).await.as_deref(),
+        Some("pending_confirmation")
+    );
+}
+
+#[tokio::test]
+async fn admins_can_unsubscribe_a_subscriber() {
+    let app = spawn_app().await;
+    let id = insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Ursula Le Guin",
+        "confirmed",
+        "2025-01-10T00:00:00Z",
+    )
+    .await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_subscriber_action(id, "unsubscribe").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(status(&app, id).await.as_deref(), Some("unsubscribed"));
+}
+
+#[tokio::test]
+async fn unsubscribing_a_bounced_subscriber_keeps_the_bounce() {
+    let app = spawn_app().await;
+    let id = insert_subscriber(
+        &app,
+        "bounce@example.com",
+        "Bounce",
+        "bounced",
+        "2025-01-10T00:00:00Z",
+    )
+    .await;
+    sqlx::query!(
+        r#"
+    INSERT INTO list_memberships (list_slug, subscriber_id, status, subscribed_at)
+    VALUES ('newsletter', $1, 'confirmed', now())"#,
+        id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    let response = app.post_subscriber_action(id, "unsubscribe").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(status(&app, id).await.as_deref(), Some("bounced"));
+    let membership = sqlx::query_scalar!(
+        "SELECT status FROM list_memberships WHERE subscriber_id = $1",
+        id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(membership, "unsubscribed");
+}
+
+#[tokio::test]
+async fn admins_can_delete_a_subscriber() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    let response = app.delete_subscriber(id).await;
+
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(app.get_subscriber(id).await.status().as_u16(), 404);
+    let tokens = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens, 0);
+}
@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{create_unconfirmed_subscriber, spawn_app};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -14,6 +14,7 @@ async fn the_link_returned_by_subscribe_returns_a_200() {
         .await;
 
     app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(&email_request);
@@ -46,6 +47,7 @@ async fn clicking_the_confirmation_link_confirms_subscription() {
         .await;
 
     app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(&email_request);
@@ -65,3 +67,51 @@ async fn clicking_the_confirmation_link_confirms_subscription() {
     assert_eq!(saved.name, "le guin");
     assert_eq!(saved.status, Some("confirmed".to_string()));
 }
+
+#[tokio::test]
+async fn confirmation_links_only_work_once() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let resp = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(resp.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_return_410() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let resp = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(resp.status().as_u16(), 410);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status.as_deref(), Some("pending_confirmation"));
+}
+
+#[tokio::test]
+async fn confirmation_tokens_expire_after_the_configured_ttl() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    let token =
+        sqlx::query!(r#"SELECT EXTRACT(EPOCH FROM expires_at - created_at)::bigint AS "ttl_seconds!" FROM subscription_tokens"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+
+    assert_eq!(token.ttl_seconds, 48 * 60 * 60);
+}
@@ -0,0 +1,313 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use chrono::{Duration, Utc};
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::preferences::preferences_token;
+
+async fn subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+fn preferences_url(app: &TestApp, id: Uuid, path: &str) -> reqwest::Url {
+    let mut url = reqwest::Url::parse(&format!("{}{}", app.address, path)).unwrap();
+    url.query_pairs_mut()
+        .append_pair("token", &preferences_token(&app.hmac_secret, id));
+    url
+}
+
+async fn post_preferences(app: &TestApp, id: Uuid, form: &[(&str, &str)]) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(preferences_url(app, id, "/subscriptions/preferences"))
+        .form(form)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn memberships(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT list_slug, status FROM list_memberships ORDER BY list_slug")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|m| (m.list_slug, m.status))
+        .collect()
+}
+
+async fn publish_newsletter(app: &TestApp) {
+    let response = app
+        .post_newsletters(&json!({
+            "title": "Issue",
+            "content": { "text": "Issue body", "html": "<p>Issue body</p>" },
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_carry_a_preferences_link() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let link = preferences_url(
+        &app,
+        subscriber_id(&app).await,
+        "/subscriptions/preferences",
+    );
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains(&format!("Manage your preferences: {}", link)));
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains(">Manage your preferences</a>"));
+}
+
+#[tokio::test]
+async fn the_preferences_page_shows_the_current_choices() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+
+    let response = reqwest::get(preferences_url(&app, id, "/subscriptions/preferences"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"<input type="checkbox" name="list" value="newsletter" checked>"#));
+    assert!(page.contains(r#"name="pause_weeks""#));
+    assert!(page.contains(r#"action="/subscriptions/preferences/unsubscribe?token="#));
+}
+
+#[tokio::test]
+async fn preference_links_need_a_valid_token() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_token = zero2prod::domain::unsubscribe::unsubscribe_token(
+        &app.hmac_secret,
+        subscriber_id(&app).await,
+        "newsletter",
+    );
+    let client = reqwest::Client::new();
+
+    for token in [unsubscribe_token.as_str(), "forged"] {
+        for (method, path) in [
+            (reqwest::Method::GET, "/subscriptions/preferences"),
+            (reqwest::Method::POST, "/subscriptions/preferences"),
+            (
+                reqwest::Method::POST,
+                "/subscriptions/preferences/unsubscribe",
+            ),
+        ] {
+            let response = client
+                .request(method, format!("{}{}", app.address, path))
+                .query(&[("token", token)])
+                .form(&[("name", "le guin")])
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status().as_u16(), 401, "{} {}", path, token);
+        }
+    }
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name_and_lists() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_list(&json!({ "slug": "release-notes", "name": "Release notes" }))
+        .await
+        .error_for_status()
+        .unwrap();
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+
+    let response = post_preferences(
+        &app,
+        id,
+        &[("name", "Ursula K. Le Guin"), ("list", "release-notes")],
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        memberships(&app).await,
+        [
+            ("newsletter".to_string(), "unsubscribed".to_string()),
+            ("release-notes".to_string(), "confirmed".to_string()),
+        ]
+    );
+    let subscriber = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.name, "Ursula K. Le Guin");
+    assert_eq!(subscriber.status.as_deref(), Some("confirmed"));
+}
+
+#[tokio::test]
+async fn paused_subscribers_get_no_issues_until_they_resume() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+
+    let form = [
+        ("name", "le guin"),
+        ("list", "newsletter"),
+        ("pause_weeks", "4"),
+    ];
+    post_preferences(&app, id, &form)
+        .await
+        .error_for_status()
+        .unwrap();
+    let paused_until = sqlx::query_scalar!("SELECT paused_until FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(paused_until > Utc::now() + Duration::weeks(4) - Duration::minutes(1));
+    {
+        let _guard = Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        publish_newsletter(&app).await;
+    }
+
+    let form = [
+        ("name", "le guin"),
+        ("list", "newsletter"),
+        ("pause_weeks", "0"),
+    ];
+    post_preferences(&app, id, &form)
+        .await
+        .error_for_status()
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+}
+
+#[tokio::test]
+async fn saving_other_preferences_keeps_a_running_pause() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+    let form = [
+        ("name", "le guin"),
+        ("list", "newsletter"),
+        ("pause_weeks", "4"),
+    ];
+    post_preferences(&app, id, &form)
+        .await
+        .error_for_status()
+        .unwrap();
+    let paused_until = sqlx::query_scalar!("SELECT paused_until FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    let page = reqwest::get(preferences_url(&app, id, "/subscriptions/preferences"))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains(r#"<option value="keep" selected>Keep the current pause</option>"#));
+    // What the browser submits when only the name is changed.
+    let form = [
+        ("name", "Ursula K. Le Guin"),
+        ("list", "newsletter"),
+        ("pause_weeks", "keep"),
+    ];
+    post_preferences(&app, id, &form)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let subscriber = sqlx::query!("SELECT name, paused_until FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.name, "Ursula K. Le Guin");
+    assert_eq!(subscriber.paused_until, paused_until);
+}
+
+#[tokio::test]
+async fn invalid_preferences_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+
+    for (form, description) in [
+        (&[("name", ""), ("list", "newsletter")][..], "empty name"),
+        (&[("name", "le guin"), ("pause_weeks", "100")], "long pause"),
+    ] {
+        let response = post_preferences(&app, id, form).await;
+        assert_eq!(response.status().as_u16(), 400, "{}", description);
+    }
+    assert_eq!(
+        memberships(&app).await,
+        [("newsletter".to_string(), "confirmed".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_unsubscribe_from_everything() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+
+    let response = reqwest::Client::new()
+        .post(preferences_url(
+            &app,
+            id,
+            "/subscriptions/preferences/unsubscribe",
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        memberships(&app).await,
+        [("newsletter".to_string(), "unsubscribed".to_string())]
+    );
+    let status = sqlx::query_scalar!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status.as_deref(), Some("unsubscribed"));
+}
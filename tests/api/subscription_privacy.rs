@@ -0,0 +1,362 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use chrono::{Duration, Utc};
+use serde_json::Value;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::email_events::email_hash;
+use zero2prod::domain::privacy::privacy_token;
+
+async fn subscriber(app: &TestApp) -> (Uuid, String) {
+    let row = sqlx::query!("SELECT id, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    (row.id, row.email)
+}
+
+/// Asks for a privacy link for `email` and returns the one that arrives.
+async fn receive_privacy_link(app: &TestApp, email: &str) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let body = serde_urlencoded::to_string([("email", email)]).unwrap();
+    let response = app.post_privacy_request(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+fn with_path(link: &reqwest::Url, path: &str) -> reqwest::Url {
+    let mut link = link.clone();
+    link.set_path(path);
+    link
+}
+
+async fn seed_history(app: &TestApp, email: &str) -> Uuid {
+    let newsletter_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+    INSERT INTO newsletters (newsletter_id, title, text_content, html_content, status)
+    VALUES ($1, 'Issue #1', 'text', '<p>html</p>', 'sent')"#,
+        newsletter_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+    INSERT INTO newsletter_deliveries (
+        newsletter_id, subscriber_email, n_attempts, outcome, last_error,
+        first_attempted_at, last_attempted_at
+    )
+    VALUES ($1, $2, 1, 'sent', 'Accepted for ' || $2, now(), now())"#,
+        newsletter_id,
+        email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+    INSERT INTO email_events (event_id, kind, email, detail, occurred_at, received_at)
+    VALUES ($1, 'delivery', $2, 'Delivered to ' || $2, now(), now())"#,
+        Uuid::new_v4(),
+        email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+    INSERT INTO newsletter_delivery_queue (newsletter_id, subscriber_email, execute_after)
+    VALUES ($1, $2, now() + interval '1 hour')"#,
+        newsletter_id,
+        email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    newsletter_id
+}
+
+#[tokio::test]
+async fn privacy_requests_for_unknown_addresses_send_nothing() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_urlencoded::to_string([("email", "nobody@example.com")]).unwrap();
+    let response = app.post_privacy_request(body).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn privacy_requests_for_addresses_that_must_not_be_mailed_send_nothing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (id, email) = subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_urlencoded::to_string([("email", &email)]).unwrap();
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'complained' WHERE id = $1",
+        id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let complained = app.post_privacy_request(body.clone()).await;
+    app.dispatch_all_pending_emails().await;
+    // Suppressed without the status changing, as a manual suppression does.
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'confirmed' WHERE id = $1",
+        id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO suppressed_emails (email, reason, suppressed_at) VALUES ($1, 'bounced', now())",
+        email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let suppressed = app.post_privacy_request(body).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(complained.status().as_u16(), 200);
+    assert_eq!(suppressed.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn privacy_links_are_not_resent_straight_away() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (_, email) = subscriber(&app).await;
+    receive_privacy_link(&app, &email).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_urlencoded::to_string([("email", &email)]).unwrap();
+    let response = app.post_privacy_request(body).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn privacy_requests_need_a_valid_address() {
+    let app = spawn_app().await;
+
+    let body = serde_urlencoded::to_string([("email", "not-an-email")]).unwrap();
+    let response = app.post_privacy_request(body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_privacy_link_leads_to_a_page_that_does_not_erase_anything() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (_, email) = subscriber(&app).await;
+
+    let link = receive_privacy_link(&app, &email).await;
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains("/subscriptions/privacy/data?token="));
+    assert!(page.contains(r#"action="/subscriptions/privacy/erase?token="#));
+    assert_eq!(subscriber(&app).await.1, email);
+}
+
+#[tokio::test]
+async fn subscribers_can_download_their_data() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (id, email) = subscriber(&app).await;
+    seed_history(&app, &email).await;
+
+    let link = receive_privacy_link(&app, &email).await;
+    let response = reqwest::get(with_path(&link, "/subscriptions/privacy/data"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Disposition"]
+        .to_str()
+        .unwrap()
+        .starts_with("attachment"));
+    let data: Value = response.json().await.unwrap();
+    assert_eq!(data["id"], id.to_string());
+    assert_eq!(data["email"], email);
+    assert_eq!(data["status"], "confirmed");
+    assert_eq!(data["deliveries"][0]["title"], "Issue #1");
+    assert_eq!(data["queued_deliveries"][0]["title"], "Issue #1");
+    let tokens = data["confirmation_tokens"].as_array().unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert!(!tokens[0]["consumed_at"].is_null());
+    assert!(tokens[0].get("subscription_token").is_none());
+}
+
+#[tokio::test]
+async fn subscribers_can_erase_their_data() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (_, email) = subscriber(&app).await;
+    let newsletter_id = seed_history(&app, &email).await;
+
+    let link = receive_privacy_link(&app, &email).await;
+    let response = reqwest::Client::new()
+        .post(with_path(&link, "/subscriptions/privacy/erase"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let remaining = sqlx::query_scalar!(
+        r#"
+    SELECT (SELECT count(*) FROM subscriptions)
+        + (SELECT count(*) FROM subscription_tokens)
+        + (SELECT count(*) FROM newsletter_delivery_queue)
+        + (SELECT count(*) FROM email_outbox WHERE recipient = $1)
+        + (SELECT count(*) FROM newsletter_deliveries WHERE subscriber_email = $1)
+        AS "count!""#,
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(remaining, 0);
+
+    // Sending history goes too, not just the address it's filed under.
+    let history = sqlx::query_scalar!(
+        r#"
+    SELECT (SELECT count(*) FROM newsletter_deliveries WHERE newsletter_id = $1)
+        + (SELECT count(*) FROM email_events)
+        AS "count!""#,
+        newsletter_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(history, 0);
+
+    let suppressions = sqlx::query!("SELECT email, reason FROM suppressed_emails")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(suppressions.len(), 1);
+    assert_eq!(suppressions[0].email, email_hash(&app.hmac_secret, &email));
+    assert_eq!(suppressions[0].reason, "erased");
+}
+
+#[tokio::test]
+async fn erased_addresses_are_not_mailed_again() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (_, email) = subscriber(&app).await;
+    let link = receive_privacy_link(&app, &email).await;
+    reqwest::Client::new()
+        .post(with_path(&link, "/subscriptions/privacy/erase"))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_urlencoded::to_string([("name", "le guin"), ("email", &email.to_uppercase())])
+        .unwrap();
+    let response = app.post_subscription(body).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers, 0);
+}
+
+#[tokio::test]
+async fn erasing_twice_is_harmless() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (_, email) = subscriber(&app).await;
+    let link = with_path(
+        &receive_privacy_link(&app, &email).await,
+        "/subscriptions/privacy/erase",
+    );
+
+    for _ in 0..2 {
+        let response = reqwest::Client::new()
+            .post(link.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let data = reqwest::get(with_path(&link, "/subscriptions/privacy/data"))
+        .await
+        .unwrap();
+    assert_eq!(data.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn privacy_links_need_a_valid_unexpired_token() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (id, _) = subscriber(&app).await;
+    let expired = privacy_token(&app.hmac_secret, id, Utc::now() - Duration::minutes(1));
+    let unsubscribe =
+        zero2prod::domain::unsubscribe::unsubscribe_token(&app.hmac_secret, id, "newsletter");
+    let client = reqwest::Client::new();
+
+    for token in [expired.as_str(), unsubscribe.as_str(), "forged"] {
+        for (method, path) in [
+            (reqwest::Method::GET, "/subscriptions/privacy"),
+            (reqwest::Method::GET, "/subscriptions/privacy/data"),
+            (reqwest::Method::POST, "/subscriptions/privacy/erase"),
+        ] {
+            let response = client
+                .request(method, format!("{}{}", app.address, path))
+                .query(&[("token", token)])
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status().as_u16(), 401, "{} {}", path, token);
+        }
+    }
+    assert_eq!(subscriber(&app).await.0, id);
+}
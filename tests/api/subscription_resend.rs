@@ -0,0 +1,104 @@
+use crate::helpers::{
+    create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app, TestApp,
+};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn pending_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+#[tokio::test]
+async fn resend_sends_a_new_link_to_pending_subscribers() {
+    let app = spawn_app().await;
+    let old_links = create_unconfirmed_subscriber(&app).await;
+    let email = pending_email(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_urlencoded::to_string([("email", &email)]).unwrap();
+    let response = app.post_resend_confirmation(body).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let new_links = app.get_confirmation_links(&email_request);
+    assert_eq!(
+        reqwest::get(old_links.html)
+            .await
+            .unwrap()
+            .status()
+            .as_u16(),
+        401
+    );
+    assert_eq!(
+        reqwest::get(new_links.html)
+            .await
+            .unwrap()
+            .status()
+            .as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn resend_does_nothing_for_unknown_emails() {
+    let app = spawn_app().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_resend_confirmation("email=nobody%40example.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn resend_does_nothing_for_confirmed_subscribers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = pending_email(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_urlencoded::to_string([("email", &email)]).unwrap();
+    let response = app.post_resend_confirmation(body).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn resend_rejects_invalid_emails() {
+    let app = spawn_app().await;
+
+    for body in ["email=not-an-email", "email=", ""] {
+        let response = app.post_resend_confirmation(body.into()).await;
+        assert_eq!(400, response.status().as_u16(), "body: {:?}", body);
+    }
+}
@@ -0,0 +1,64 @@
+use crate::helpers::{create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app};
+use zero2prod::subscription_sweeper::sweep_stale_subscriptions;
+
+#[tokio::test]
+async fn the_sweeper_removes_pending_subscribers_with_expired_tokens() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let n_removed = sweep_stale_subscriptions(&app.db_pool).await.unwrap();
+
+    assert_eq!(n_removed, 1);
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 0);
+    assert_eq!(n_tokens, 0);
+}
+
+#[tokio::test]
+async fn the_sweeper_keeps_pending_subscribers_with_live_tokens() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    let n_removed = sweep_stale_subscriptions(&app.db_pool).await.unwrap();
+
+    assert_eq!(n_removed, 0);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status.as_deref(), Some("pending_confirmation"));
+}
+
+#[tokio::test]
+async fn the_sweeper_keeps_confirmed_subscribers_but_drops_their_used_tokens() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let n_removed = sweep_stale_subscriptions(&app.db_pool).await.unwrap();
+
+    assert_eq!(n_removed, 0);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status.as_deref(), Some("confirmed"));
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 0);
+}
@@ -0,0 +1,192 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp) {
+    let resp = app
+        .post_newsletters(&serde_json::json!({
+            "title": "newsletter",
+            "content": {
+                "text": "Newsletter body",
+                "html": "<p>Newsletter body</p>",
+            },
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+}
+
+async fn receive_unsubscribe_link(app: &TestApp) -> reqwest::Url {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_unsubscribe_link(&email_request)
+}
+
+async fn subscriber_status(app: &TestApp) -> Option<String> {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .status
+}
+
+#[tokio::test]
+async fn newsletters_carry_an_unsubscribe_link() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let unsubscribe_link = receive_unsubscribe_link(&app).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains(unsubscribe_link.as_str()));
+}
+
+#[tokio::test]
+async fn newsletters_carry_one_click_list_unsubscribe_headers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let unsubscribe_link = receive_unsubscribe_link(&app).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body["Headers"],
+        serde_json::json!([
+            {"Name": "List-Unsubscribe", "Value": format!("<{}>", unsubscribe_link)},
+            {"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"},
+        ])
+    );
+}
+
+#[tokio::test]
+async fn the_unsubscribe_page_does_not_unsubscribe() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let unsubscribe_link = receive_unsubscribe_link(&app).await;
+
+    let resp = reqwest::get(unsubscribe_link).await.unwrap();
+
+    assert_eq!(resp.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, Some("confirmed".to_string()));
+}
+
+#[tokio::test]
+async fn a_one_click_post_unsubscribes_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let unsubscribe_link = receive_unsubscribe_link(&app).await;
+
+    let resp = reqwest::Client::new()
+        .post(unsubscribe_link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status().as_u16(), 200);
+    assert_eq!(
+        subscriber_status(&app).await,
+        Some("unsubscribed".to_string())
+    );
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let unsubscribe_link = receive_unsubscribe_link(&app).await;
+    reqwest::Client::new()
+        .post(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.email_server.reset().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn subscribers_who_unsubscribe_after_publishing_are_skipped() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let unsubscribe_link = receive_unsubscribe_link(&app).await;
+    app.email_server.reset().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    reqwest::Client::new()
+        .post(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn unsubscribe_requests_with_an_invalid_token_are_rejected() {
+    let app = spawn_app().await;
+    let url = format!(
+        "{}/subscriptions/unsubscribe?token=not-a-token",
+        app.address
+    );
+
+    let get = reqwest::get(&url).await.unwrap();
+    let post = reqwest::Client::new().post(&url).send().await.unwrap();
+
+    assert_eq!(get.status().as_u16(), 401);
+    assert_eq!(post.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn unsubscribe_requests_without_a_token_are_rejected() {
+    let app = spawn_app().await;
+
+    let resp = reqwest::get(&format!("{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status().as_u16(), 400);
+}
@@ -31,6 +31,7 @@ async fn subscribe_persists_a_new_subscriber() {
         .await;
 
     app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
         .fetch_one(&app.db_pool)
@@ -99,6 +100,7 @@ async fn subscrive_sends_a_confirmation_email_for_valid_data() {
         .await;
 
     app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 }
 
 #[tokio::test]
@@ -113,6 +115,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
         .await;
 
     app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = &app.get_confirmation_links(&email_request);
@@ -120,6 +123,210 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
 
+#[tokio::test]
+async fn subscribe_does_not_send_the_confirmation_email_itself() {
+    let app = spawn_app().await;
+    let body = "name=leguin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscription(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM email_outbox"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 1);
+}
+
+#[tokio::test]
+async fn subscribe_succeeds_when_the_email_provider_is_down() {
+    let app = spawn_app().await;
+    let body = "name=leguin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+    let email = sqlx::query!(
+        r#"SELECT n_retries, execute_after > now() AS "in_the_future!", failed_at FROM email_outbox"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The confirmation email should still be in the outbox.");
+    assert_eq!(email.n_retries, 1);
+    assert!(email.in_the_future);
+    assert!(email.failed_at.is_none());
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_retried_until_delivered() {
+    let app = spawn_app().await;
+    let body = "name=leguin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("UPDATE email_outbox SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM email_outbox"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn confirmation_emails_rejected_by_the_provider_are_marked_as_failed() {
+    let app = spawn_app().await;
+    let body = "name=leguin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email = sqlx::query!("SELECT failed_at, last_error FROM email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(email.failed_at.is_some());
+    assert!(email.last_error.unwrap().contains("422"));
+}
+
+#[tokio::test]
+async fn subscribing_twice_sends_a_fresh_confirmation_link() {
+    let app = spawn_app().await;
+    let body = "name=leguin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let response = app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_links(&email_requests[0]).html;
+    let second_link = app.get_confirmation_links(&email_requests[1]).html;
+    assert_ne!(first_link, second_link);
+
+    let resp = reqwest::get(first_link).await.unwrap();
+    assert_eq!(resp.status().as_u16(), 401);
+    let resp = reqwest::get(second_link).await.unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribing_with_a_confirmed_email_sends_nothing() {
+    let app = spawn_app().await;
+    let body = "name=leguin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status.as_deref(), Some("confirmed"));
+}
+
+#[tokio::test]
+async fn unsubscribed_emails_can_subscribe_again() {
+    let app = spawn_app().await;
+    let body = "name=leguin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    app.post_subscription(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status.as_deref(), Some("confirmed"));
+}
+
 // this is commented out, use it to test the Error Handling, Logging of the post_subscription() fn
 
 // #[tokio::test]
@@ -0,0 +1,78 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::newsletter_delivery_worker::run_worker_until_stopped;
+
+#[tokio::test]
+async fn an_idle_worker_stops_as_soon_as_it_is_asked_to() {
+    let app = spawn_app().await;
+    let shutdown = CancellationToken::new();
+    let worker = tokio::spawn(run_worker_until_stopped(
+        app.config.clone(),
+        shutdown.clone(),
+    ));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    shutdown.cancel();
+
+    let outcome = tokio::time::timeout(Duration::from_secs(2), worker)
+        .await
+        .expect("The worker should stop without waiting for its next poll.");
+    outcome.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn the_worker_finishes_an_in_flight_delivery_before_stopping() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let resp = app
+        .post_newsletters(&serde_json::json!({
+            "title": "newsletter",
+            "content": {
+                "text": "Newsletter body",
+                "html": "<p>Newsletter body</p>",
+            },
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let n_received_before = app.email_server.received_requests().await.unwrap().len();
+
+    let shutdown = CancellationToken::new();
+    let worker = tokio::spawn(run_worker_until_stopped(
+        app.config.clone(),
+        shutdown.clone(),
+    ));
+    // Wait for the provider to receive the email, then ask the worker to stop mid-send.
+    while app.email_server.received_requests().await.unwrap().len() == n_received_before {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(5), worker)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    let n_queued =
+        sqlx::query_scalar!("SELECT COUNT(*) AS \"count!\" FROM newsletter_delivery_queue")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(n_queued, 0);
+    let outcome = sqlx::query_scalar!("SELECT outcome FROM newsletter_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(outcome, "sent");
+}
@@ -0,0 +1,55 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::newsletter_delivery_worker::run_worker_until_stopped;
+
+#[tokio::test]
+async fn the_worker_wakes_up_as_soon_as_an_issue_is_published() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let mut config = app.config.clone();
+    config.worker.idle_poll_interval_milliseconds = 60 * 60 * 1000;
+    let worker = tokio::spawn(run_worker_until_stopped(config, CancellationToken::new()));
+    // Give the worker time to find the queues empty and go to sleep.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let resp = app
+        .post_newsletters(&serde_json::json!({
+            "title": "newsletter",
+            "content": {
+                "text": "Newsletter body",
+                "html": "<p>Newsletter body</p>",
+            },
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+
+    let mut n_queued = 1;
+    for _ in 0..50 {
+        n_queued =
+            sqlx::query_scalar!("SELECT COUNT(*) AS \"count!\" FROM newsletter_delivery_queue")
+                .fetch_one(&app.db_pool)
+                .await
+                .unwrap();
+        if n_queued == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    worker.abort();
+    assert_eq!(
+        n_queued, 0,
+        "The worker should have been woken up by the notification."
+    );
+}